@@ -0,0 +1,155 @@
+use monkey_lang::{
+    compiler::Compiler,
+    evaluator::{eval, Environment},
+    lexer::lexer,
+    parser::parse,
+    vm::Vm,
+};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+const SAMPLES: usize = 25;
+
+struct Timings {
+    evaluator: Duration,
+    compile: Duration,
+    vm: Duration,
+}
+
+// Programs run through both engines. Most of them are straight line code
+// so each snippet is repeated to make a workload. Functions can't capture
+// outer variables and strings can't be concatenated, so there are no
+// closure or string building programs; "higher-order" passes functions
+// around instead.
+fn programs() -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "arithmetic",
+            "(1 + 2 * 3 - 4 / 2) * (7 - 3) + -5;".repeat(20000),
+        ),
+        (
+            "globals",
+            "let a = 1; let b = a + a; let c = b * a - b; a + b + c;".repeat(10000),
+        ),
+        (
+            "conditionals",
+            "if (1 < 2) { if (3 > 4) { 1 } else { 2 } } else { 3 };".repeat(20000),
+        ),
+        (
+            "prefixes",
+            "-(-1) - -(2 * -3) > -4; !!(1 < 2);".repeat(20000),
+        ),
+        (
+            "while",
+            "let i = 0; while (i < 200000) { let i = i + 1; }; i;".to_owned(),
+        ),
+        (
+            "for",
+            "let s = 0; for (i in 0..200000) { let s = s + i; }; s;".to_owned(),
+        ),
+        (
+            "collections",
+            "let a = [1, 2, 3]; let h = {1: a, 2: [a[2]]}; h[2][0] + a[0];".repeat(10000),
+        ),
+        (
+            "fib",
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(25);"
+                .to_owned(),
+        ),
+        (
+            "higher-order",
+            "let twice = fn(f, x) { f(f(x)) }; let inc = fn(x) { x + 1 }; \
+             let s = 0; for (i in 0..20000) { let s = twice(inc, s) + twice(fn(x) { x * 1 }, i); }; s;"
+                .to_owned(),
+        ),
+        (
+            "rebinding",
+            "let x = 1; let y = x; let x = y + 1; x;".repeat(10000),
+        ),
+    ]
+}
+
+// Run `f` SAMPLES times and return the median duration of one run. `setup`
+// is called before each sample and isn't timed.
+fn median<S, T, F>(mut setup: S, mut f: F) -> Duration
+where
+    S: FnMut() -> T,
+    F: FnMut(T),
+{
+    let mut samples: Vec<Duration> = (0..SAMPLES)
+        .map(|_| {
+            let input = setup();
+            let start = Instant::now();
+            f(input);
+            start.elapsed()
+        })
+        .collect();
+    samples.sort();
+    samples[SAMPLES / 2]
+}
+
+fn bench(input: &str) -> Timings {
+    let ast = parse(&mut lexer(input)).unwrap();
+    let evaluator = median(
+        || ast.clone(),
+        |ast| {
+            eval(ast, &mut Environment::new());
+        },
+    );
+
+    // Compiling from source includes lexing and parsing
+    let compile = median(|| (), |_| drop(Compiler::from_source(input).unwrap()));
+
+    // Decoding happens when the Vm is created so isn't part of the run time
+    let byte_code = Compiler::from_source(input).unwrap();
+    let vm = median(
+        || Vm::new(byte_code.clone()).unwrap(),
+        |mut vm| vm.run().unwrap(),
+    );
+
+    Timings {
+        evaluator,
+        compile,
+        vm,
+    }
+}
+
+fn speedup(evaluator: Duration, vm: Duration) -> f64 {
+    evaluator.as_secs_f64() / vm.as_secs_f64()
+}
+
+fn main() {
+    // `cargo bench -- <filter>` only runs programs containing <filter>.
+    // Cargo also passes `--bench` which is skipped.
+    let filter = env::args().skip(1).find(|arg| !arg.starts_with("--"));
+
+    println!(
+        "{:<14} {:>12} {:>12} {:>12} {:>8}",
+        "program", "evaluator", "compile", "vm", "speedup"
+    );
+
+    let mut speedups = vec![];
+    for (name, input) in programs() {
+        if let Some(filter) = &filter {
+            if !name.contains(filter.as_str()) {
+                continue;
+            }
+        }
+
+        let timings = bench(&input);
+        let speedup = speedup(timings.evaluator, timings.vm);
+        speedups.push(speedup);
+        println!(
+            "{:<14} {:>12.3?} {:>12.3?} {:>12.3?} {:>7.2}x",
+            name, timings.evaluator, timings.compile, timings.vm, speedup
+        );
+    }
+
+    if !speedups.is_empty() {
+        // Geometric mean so no single program dominates the summary
+        let geomean = speedups.iter().map(|s| s.ln()).sum::<f64>() / speedups.len() as f64;
+        println!("{:<14} {:>46} {:>7.2}x", "geomean", "", geomean.exp());
+    }
+}
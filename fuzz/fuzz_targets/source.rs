@@ -0,0 +1,19 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use monkey_lang::{compiler::Compiler, lexer::lexer, parser::parse, vm::Vm};
+
+// Feed arbitrary input through the lexer, parser, compiler and VM. Each
+// stage may return an error but none of them should panic.
+fuzz_target!(|data: &[u8]| {
+    // Source is always a str, so only valid UTF-8 can reach the lexer
+    if let Ok(input) = std::str::from_utf8(data) {
+        let mut tokens = lexer(input);
+        let _ = parse(&mut tokens);
+
+        if let Ok(byte_code) = Compiler::from_source(input) {
+            // The compiler's output is always valid byte code
+            let mut vm = Vm::new(byte_code).unwrap();
+            let _ = vm.run();
+        }
+    }
+});
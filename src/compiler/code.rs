@@ -17,6 +17,92 @@ pub enum OpCode {
     OpJmpIfFalse(u16),
     OpSetGlobal(u16),
     OpGetGlobal(u16),
+    // Wide variants take a four byte operand for programs that outgrow the
+    // two byte operands above
+    OpConstantWide(u32),
+    OpJmpWide(u32),
+    OpJmpIfFalseWide(u32),
+    OpSetGlobalWide(u32),
+    OpGetGlobalWide(u32),
+    // Build an array or hash from the given number of items or pairs
+    OpArray(u16),
+    OpHash(u16),
+    OpIndex,
+    OpRange,
+    OpRangeInclusive,
+    // Replace the iterable on the stack with an iterator. The operand is
+    // the number of loop variables, 1 or 2.
+    OpIterInit(u8),
+    // Push the next item(s), or jump to the operand once there are none
+    OpIterNext(u16),
+    OpIterNextWide(u32),
+    // Pops an index and a container, then pushes the container with the
+    // value under them stored at that index
+    OpSetIndex,
+    OpJmpIfTrue(u16),
+    OpJmpIfTrueWide(u32),
+    OpMod,
+    OpPow,
+    OpGreaterEqual,
+    OpLessEqual,
+    OpNull,
+    // Tests for match patterns, each pushes whether the test passed.
+    // OpMatchEqual pops a literal and a value and compares them like a
+    // literal pattern, OpMatchArray pops a value and checks it is an array
+    // of the operand's length, OpMatchHash pops a value and checks it is a
+    // hash, and OpMatchKey pops a key and a value and checks the value is a
+    // hash with that key.
+    OpMatchEqual,
+    OpMatchArray(u16),
+    OpMatchHash,
+    OpMatchKey,
+    // Pops a description of a failed pattern and the value it failed on,
+    // then stops with a destructuring error
+    OpMismatch,
+    // Pops an assignment as source and a value, then sets the global like
+    // OpSetGlobal. Stops with an error naming the assignment if the global
+    // was never set, as when assigning to a variable declared in a branch
+    // that didn't run, or was last set by a `const`.
+    OpAssignGlobal(u16),
+    OpAssignGlobalWide(u32),
+    // Pops a description of a constant, its name and `const` declaration,
+    // and a value. Sets the global to the value and marks it as a constant
+    // until it is set again.
+    OpSetConst(u16),
+    OpSetConstWide(u32),
+    // Pops a function's signature and pushes the function whose body
+    // starts at the next instruction, then jumps past the body to the
+    // operand
+    OpFunction(u16),
+    OpFunctionWide(u32),
+    // Pops the operand's number of arguments and the function under them,
+    // then runs the function with the arguments as its first locals
+    OpCall(u16),
+    // Pops the result of a function and returns it to the caller
+    OpReturn,
+    // Calls the built in function at the first operand's index into
+    // BUILTINS, popping the second operand's number of arguments
+    OpCallBuiltin(u8, u16),
+    // Starts a function body by checking the number of arguments. The
+    // operands are how many are required, how many there can be before a
+    // rest parameter, and whether there is one, which the arguments past
+    // the others are collected into.
+    OpParameters(u16, u16, bool),
+    // Makes room for the operand's number of locals, any that weren't
+    // passed as arguments start out unset
+    OpLocals(u16),
+    // Pushes whether the local holding a parameter was passed an argument
+    OpHasArgument(u16),
+    // Locals of the function running, which act like the global versions
+    OpGetLocal(u16),
+    OpSetLocal(u16),
+    OpAssignLocal(u16),
+    OpSetLocalConst(u16),
+    // Like OpCall, but the function replaces the one running rather than
+    // returning to it
+    OpTailCall(u16),
+    // Pushes the function running, which a body binds to its own name
+    OpCurrentFunction,
 }
 
 pub fn make_op(opcode: OpCode) -> Vec<u8> {
@@ -74,16 +160,157 @@ pub fn make_op(opcode: OpCode) -> Vec<u8> {
             output.push(int_two);
             output
         }
+        OpCode::OpConstantWide(operand) => wide_op(0x13, operand),
+        OpCode::OpJmpWide(operand) => wide_op(0x14, operand),
+        OpCode::OpJmpIfFalseWide(operand) => wide_op(0x15, operand),
+        OpCode::OpSetGlobalWide(operand) => wide_op(0x16, operand),
+        OpCode::OpGetGlobalWide(operand) => wide_op(0x17, operand),
+        OpCode::OpArray(operand) => narrow_op(0x18, operand),
+        OpCode::OpHash(operand) => narrow_op(0x19, operand),
+        OpCode::OpIndex => vec![0x1a],
+        OpCode::OpRange => vec![0x1b],
+        OpCode::OpRangeInclusive => vec![0x1c],
+        OpCode::OpIterInit(operand) => vec![0x1d, operand],
+        OpCode::OpIterNext(operand) => narrow_op(0x1e, operand),
+        OpCode::OpIterNextWide(operand) => wide_op(0x1f, operand),
+        OpCode::OpSetIndex => vec![0x20],
+        OpCode::OpJmpIfTrue(operand) => narrow_op(0x21, operand),
+        OpCode::OpJmpIfTrueWide(operand) => wide_op(0x22, operand),
+        OpCode::OpMod => vec![0x23],
+        OpCode::OpPow => vec![0x24],
+        OpCode::OpGreaterEqual => vec![0x25],
+        OpCode::OpLessEqual => vec![0x26],
+        OpCode::OpNull => vec![0x27],
+        OpCode::OpMatchEqual => vec![0x28],
+        OpCode::OpMatchArray(operand) => narrow_op(0x29, operand),
+        OpCode::OpMatchHash => vec![0x2a],
+        OpCode::OpMatchKey => vec![0x2b],
+        OpCode::OpMismatch => vec![0x2c],
+        OpCode::OpAssignGlobal(operand) => narrow_op(0x2d, operand),
+        OpCode::OpAssignGlobalWide(operand) => wide_op(0x2e, operand),
+        OpCode::OpSetConst(operand) => narrow_op(0x2f, operand),
+        OpCode::OpSetConstWide(operand) => wide_op(0x30, operand),
+        OpCode::OpFunction(operand) => narrow_op(0x31, operand),
+        OpCode::OpFunctionWide(operand) => wide_op(0x32, operand),
+        OpCode::OpCall(operand) => narrow_op(0x33, operand),
+        OpCode::OpReturn => vec![0x34],
+        OpCode::OpCallBuiltin(builtin, count) => {
+            let mut output = vec![0x35, builtin];
+            output.extend_from_slice(&count.to_be_bytes());
+            output
+        }
+        OpCode::OpParameters(required, max, rest) => {
+            let mut output = narrow_op(0x36, required);
+            output.extend_from_slice(&max.to_be_bytes());
+            output.push(rest as u8);
+            output
+        }
+        OpCode::OpLocals(operand) => narrow_op(0x37, operand),
+        OpCode::OpHasArgument(operand) => narrow_op(0x38, operand),
+        OpCode::OpGetLocal(operand) => narrow_op(0x39, operand),
+        OpCode::OpSetLocal(operand) => narrow_op(0x3a, operand),
+        OpCode::OpAssignLocal(operand) => narrow_op(0x3b, operand),
+        OpCode::OpSetLocalConst(operand) => narrow_op(0x3c, operand),
+        OpCode::OpTailCall(operand) => narrow_op(0x3d, operand),
+        OpCode::OpCurrentFunction => vec![0x3e],
+    }
+}
+
+// How many values an instruction leaves on the stack minus how many it
+// takes off. Conditional jumps pop their condition whichever way they go.
+// OpIterNext pushes the loop variables only when it doesn't jump, and as it
+// doesn't say how many there are the compiler counts them itself. A call
+// leaves its result in place of the function and arguments, and the code
+// after an OpReturn is only reached by jumping to it.
+pub fn stack_effect(opcode: &OpCode) -> isize {
+    match opcode {
+        OpCode::OpConstant(_)
+        | OpCode::OpConstantWide(_)
+        | OpCode::OpTrue
+        | OpCode::OpFalse
+        | OpCode::OpNull
+        | OpCode::OpGetGlobal(_)
+        | OpCode::OpGetGlobalWide(_)
+        | OpCode::OpCurrentFunction
+        | OpCode::OpHasArgument(_)
+        | OpCode::OpGetLocal(_) => 1,
+        OpCode::OpBang
+        | OpCode::OpMinus
+        | OpCode::OpJmp(_)
+        | OpCode::OpJmpWide(_)
+        | OpCode::OpIterInit(_)
+        | OpCode::OpIterNext(_)
+        | OpCode::OpIterNextWide(_)
+        | OpCode::OpMatchArray(_)
+        | OpCode::OpMatchHash
+        | OpCode::OpFunction(_)
+        | OpCode::OpFunctionWide(_)
+        | OpCode::OpParameters(..)
+        | OpCode::OpLocals(_) => 0,
+        OpCode::OpArray(len) => 1 - *len as isize,
+        OpCode::OpCall(count) | OpCode::OpTailCall(count) => -(*count as isize),
+        OpCode::OpCallBuiltin(_, count) => 1 - *count as isize,
+        OpCode::OpHash(len) => 1 - 2 * *len as isize,
+        OpCode::OpAdd
+        | OpCode::OpSub
+        | OpCode::OpMul
+        | OpCode::OpDiv
+        | OpCode::OpMod
+        | OpCode::OpPow
+        | OpCode::OpPop
+        | OpCode::OpGreater
+        | OpCode::OpLess
+        | OpCode::OpGreaterEqual
+        | OpCode::OpLessEqual
+        | OpCode::OpEqual
+        | OpCode::OpNotEqual
+        | OpCode::OpJmpIfFalse(_)
+        | OpCode::OpJmpIfFalseWide(_)
+        | OpCode::OpJmpIfTrue(_)
+        | OpCode::OpJmpIfTrueWide(_)
+        | OpCode::OpSetGlobal(_)
+        | OpCode::OpSetGlobalWide(_)
+        | OpCode::OpIndex
+        | OpCode::OpSetIndex
+        | OpCode::OpRange
+        | OpCode::OpRangeInclusive
+        | OpCode::OpMatchEqual
+        | OpCode::OpMatchKey
+        | OpCode::OpReturn
+        | OpCode::OpSetLocal(_) => -1,
+        OpCode::OpMismatch
+        | OpCode::OpAssignGlobal(_)
+        | OpCode::OpAssignGlobalWide(_)
+        | OpCode::OpSetConst(_)
+        | OpCode::OpSetConstWide(_)
+        | OpCode::OpAssignLocal(_)
+        | OpCode::OpSetLocalConst(_) => -2,
     }
 }
 
+fn narrow_op(opcode: u8, operand: u16) -> Vec<u8> {
+    let mut output = vec![opcode];
+    output.extend_from_slice(&operand.to_be_bytes());
+    output
+}
+
+fn wide_op(opcode: u8, operand: u32) -> Vec<u8> {
+    let mut output = vec![opcode];
+    output.extend_from_slice(&operand.to_be_bytes());
+    output
+}
+
 pub fn two_u8_to_usize(int_one: u8, int_two: u8) -> usize {
     ((int_one as usize) << 8) | int_two as usize
 }
 
+pub fn four_u8_to_usize(bytes: &[u8]) -> usize {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::compiler::{make_op, two_u8_to_usize, OpCode};
+    use crate::compiler::{four_u8_to_usize, make_op, stack_effect, two_u8_to_usize, OpCode};
 
     #[test]
     fn make_op_constant() {
@@ -149,6 +376,26 @@ mod tests {
         let op = make_op(OpCode::OpMinus);
         let expected = vec![0x0e];
         assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpMod);
+        let expected = vec![0x23];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpPow);
+        let expected = vec![0x24];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpGreaterEqual);
+        let expected = vec![0x25];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpLessEqual);
+        let expected = vec![0x26];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpNull);
+        let expected = vec![0x27];
+        assert_eq!(expected, op);
     }
 
     #[test]
@@ -160,6 +407,10 @@ mod tests {
         let op = make_op(OpCode::OpJmpIfFalse(65534));
         let expected = vec![0x10, 255, 254];
         assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpJmpIfTrue(65534));
+        let expected = vec![0x21, 255, 254];
+        assert_eq!(expected, op);
     }
 
     #[test]
@@ -171,6 +422,155 @@ mod tests {
         let op = make_op(OpCode::OpGetGlobal(65534));
         let expected = vec![0x12, 255, 254];
         assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpAssignGlobal(65534));
+        let expected = vec![0x2d, 255, 254];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpSetConst(65534));
+        let expected = vec![0x2f, 255, 254];
+        assert_eq!(expected, op);
+    }
+
+    #[test]
+    fn test_collections() {
+        let op = make_op(OpCode::OpArray(258));
+        let expected = vec![0x18, 1, 2];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpHash(3));
+        let expected = vec![0x19, 0, 3];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpIndex);
+        let expected = vec![0x1a];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpSetIndex);
+        let expected = vec![0x20];
+        assert_eq!(expected, op);
+    }
+
+    #[test]
+    fn test_iteration() {
+        let op = make_op(OpCode::OpRange);
+        let expected = vec![0x1b];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpRangeInclusive);
+        let expected = vec![0x1c];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpIterInit(2));
+        let expected = vec![0x1d, 2];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpIterNext(65534));
+        let expected = vec![0x1e, 255, 254];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpIterNextWide(65536));
+        let expected = vec![0x1f, 0, 1, 0, 0];
+        assert_eq!(expected, op);
+    }
+
+    #[test]
+    fn test_match_ops() {
+        let op = make_op(OpCode::OpMatchEqual);
+        let expected = vec![0x28];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpMatchArray(258));
+        let expected = vec![0x29, 1, 2];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpMatchHash);
+        let expected = vec![0x2a];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpMatchKey);
+        let expected = vec![0x2b];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpMismatch);
+        let expected = vec![0x2c];
+        assert_eq!(expected, op);
+    }
+
+    #[test]
+    fn test_stack_effect() {
+        assert_eq!(1, stack_effect(&OpCode::OpConstant(0)));
+        assert_eq!(-1, stack_effect(&OpCode::OpAdd));
+        assert_eq!(-1, stack_effect(&OpCode::OpJmpIfFalse(0)));
+        assert_eq!(0, stack_effect(&OpCode::OpJmp(0)));
+        assert_eq!(-2, stack_effect(&OpCode::OpArray(3)));
+        assert_eq!(1, stack_effect(&OpCode::OpHash(0)));
+        assert_eq!(-3, stack_effect(&OpCode::OpHash(2)));
+        assert_eq!(-1, stack_effect(&OpCode::OpSetIndex));
+        assert_eq!(-2, stack_effect(&OpCode::OpCall(2)));
+        assert_eq!(0, stack_effect(&OpCode::OpCallBuiltin(0, 1)));
+    }
+
+    #[test]
+    fn test_functions() {
+        let op = make_op(OpCode::OpFunction(65534));
+        let expected = vec![0x31, 255, 254];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpFunctionWide(65536));
+        let expected = vec![0x32, 0, 1, 0, 0];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpCall(2));
+        let expected = vec![0x33, 0, 2];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpReturn);
+        let expected = vec![0x34];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpCallBuiltin(3, 1));
+        let expected = vec![0x35, 3, 0, 1];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpParameters(1, 2, true));
+        let expected = vec![0x36, 0, 1, 0, 2, 1];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpLocals(5));
+        let expected = vec![0x37, 0, 5];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpTailCall(258));
+        let expected = vec![0x3d, 1, 2];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpCurrentFunction);
+        let expected = vec![0x3e];
+        assert_eq!(expected, op);
+    }
+
+    #[test]
+    fn test_locals() {
+        let op = make_op(OpCode::OpHasArgument(1));
+        let expected = vec![0x38, 0, 1];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpGetLocal(65534));
+        let expected = vec![0x39, 255, 254];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpSetLocal(1));
+        let expected = vec![0x3a, 0, 1];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpAssignLocal(1));
+        let expected = vec![0x3b, 0, 1];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpSetLocalConst(1));
+        let expected = vec![0x3c, 0, 1];
+        assert_eq!(expected, op);
     }
 
     #[test]
@@ -183,4 +583,50 @@ mod tests {
         let expected = 2637;
         assert_eq!(expected, input);
     }
+
+    #[test]
+    fn test_wide_ops() {
+        let op = make_op(OpCode::OpConstantWide(65536));
+        let expected = vec![0x13, 0, 1, 0, 0];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpJmpWide(4294967294));
+        let expected = vec![0x14, 255, 255, 255, 254];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpJmpIfTrueWide(70000));
+        let expected = vec![0x22, 0, 1, 17, 112];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpJmpIfFalseWide(70000));
+        let expected = vec![0x15, 0, 1, 17, 112];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpSetGlobalWide(65536));
+        let expected = vec![0x16, 0, 1, 0, 0];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpGetGlobalWide(65536));
+        let expected = vec![0x17, 0, 1, 0, 0];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpAssignGlobalWide(65536));
+        let expected = vec![0x2e, 0, 1, 0, 0];
+        assert_eq!(expected, op);
+
+        let op = make_op(OpCode::OpSetConstWide(65536));
+        let expected = vec![0x30, 0, 1, 0, 0];
+        assert_eq!(expected, op);
+    }
+
+    #[test]
+    fn test_four_u8_to_usize() {
+        let input = four_u8_to_usize(&[0, 1, 0, 0]);
+        let expected = 65536;
+        assert_eq!(expected, input);
+
+        let input = four_u8_to_usize(&[0, 1, 17, 112]);
+        let expected = 70000;
+        assert_eq!(expected, input);
+    }
 }
@@ -1,14 +1,12 @@
 use crate::{
-    compiler::{make_op, OpCode, SymbolTable},
-    evaluator::Object,
+    compiler::{make_op, stack_effect, Mutability, OpCode, SymbolTable},
+    evaluator::{arity, mismatch_detail, signature, Object, BUILTINS},
     lexer::lexer,
-    parser::{parse, Expression, Operator, Prefix, Statement},
+    parser::{parse, Expression, MatchArm, Operator, Parameter, Pattern, Prefix, Statement},
 };
+use std::{fmt, mem};
 
-// TODO Handle scopes more appropriately
-const GLOBAL: &str = "GLOBAL";
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ByteCode {
     pub instructions: Vec<u8>,
     pub constants: Vec<Object>,
@@ -23,78 +21,910 @@ impl ByteCode {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum CompileError {
+    Parse(String),
+    // Language features the compiler can't handle yet
+    Unsupported(&'static str),
+    UndefinedVariable(String),
+    TooManyConstants(usize),
+    TooManyGlobals(usize),
+    TooManyLocals(usize),
+    JumpOutOfRange(usize),
+    // More items in an array or hash literal, or parameters or arguments
+    // of a function, than fit in an operand
+    TooManyElements(usize),
+    // `break` or `continue` used outside of a loop
+    OutsideLoop(&'static str),
+    // Assignment to a `const` binding, with the declaration and the
+    // assignment as source
+    ConstAssignment {
+        name: String,
+        declaration: String,
+        assignment: String,
+    },
+    // A `const` binding declared again, by any kind of declaration
+    ConstRedeclaration {
+        name: String,
+        declaration: String,
+    },
+}
+
+// Problems that don't stop a program compiling
+#[derive(Debug, PartialEq)]
+pub enum Warning {
+    // A match with no arm that always matches, so it can give null. Holds
+    // the matched expression as source.
+    NonExhaustiveMatch(String),
+    // An arm that comes after an arm that always matches or an identical
+    // arm, with the pattern as source
+    UnreachableArm(String),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::NonExhaustiveMatch(value) => write!(
+                f,
+                "match ({}) is not exhaustive, add a `_` arm to handle every value",
+                value
+            ),
+            Warning::UnreachableArm(pattern) => {
+                write!(f, "match arm `{}` can never be reached", pattern)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Jump {
+    Always,
+    IfFalse,
+    IfTrue,
+    IterNext,
+    // Over a function body
+    Function,
+}
+
+// What a failed pattern test does
+enum Fails {
+    // Jump to the next match arm, the jumps are patched once it is known.
+    // Until the arm is taken its names are bound to hidden variables named
+    // after the `depth` of the match, `bindings` holds each name and its
+    // hidden variable.
+    NextArm {
+        jumps: Vec<usize>,
+        bindings: Vec<(String, usize)>,
+        depth: usize,
+    },
+    // Stop with an error, for destructuring
+    Error,
+}
+
+// The loop being compiled, `continue` jumps back to `start` and each
+// `break` is patched to jump past the end once it is known. Both first pop
+// anything pushed since the stack was `depth` values deep at the start.
+struct Loop {
+    start: usize,
+    breaks: Vec<usize>,
+    depth: usize,
+}
+
+// What is set aside while a function body is compiled. A body can only see
+// its parameters and its own name, and a `break` in it can't leave a loop
+// around the function.
+struct Scope {
+    symbol_table: SymbolTable,
+    loops: Vec<Loop>,
+    match_depth: usize,
+    stack_depth: usize,
+}
+
 pub struct Compiler {
     byte_code: ByteCode,
     symbol_table: SymbolTable,
+    loops: Vec<Loop>,
+    // Jump targets aren't known until after the jump has been emitted so
+    // the operand width has to be chosen for the whole program up front
+    wide_jumps: bool,
+    // Where the most recent instruction starts, operand bytes could
+    // otherwise be mistaken for an opcode
+    last_instruction: Option<usize>,
+    // How many matches are being compiled, each one keeps the value it is
+    // matching in a hidden global while its arms are tested
+    match_depth: usize,
+    // How many values are on the stack when the next instruction runs
+    stack_depth: usize,
+    // The scopes around the function body being compiled, empty at the top
+    // level where variables are globals
+    scopes: Vec<Scope>,
+    warnings: Vec<Warning>,
 }
 
 impl Compiler {
-    pub fn from_source(input: &str) -> ByteCode {
-        let mut compiler = Compiler {
+    fn new(wide_jumps: bool) -> Self {
+        Compiler {
             byte_code: ByteCode::new(),
             symbol_table: SymbolTable::new(),
-        };
+            loops: vec![],
+            wide_jumps,
+            last_instruction: None,
+            match_depth: 0,
+            stack_depth: 0,
+            scopes: vec![],
+            warnings: vec![],
+        }
+    }
 
-        let mut tokens = lexer(input.as_bytes());
-        let ast = parse(&mut tokens);
+    pub fn from_source(input: &str) -> Result<ByteCode, CompileError> {
+        Compiler::from_source_with_warnings(input).map(|(byte_code, _)| byte_code)
+    }
+
+    pub fn from_source_with_warnings(
+        input: &str,
+    ) -> Result<(ByteCode, Vec<Warning>), CompileError> {
+        let mut tokens = lexer(input);
+        let ast = parse(&mut tokens).map_err(CompileError::Parse)?;
 
-        compiler.compile_statements(ast);
+        // Try the compact encoding first and only fall back to wide jumps
+        // if a jump target doesn't fit in two bytes
+        match Compiler::new(false).compile(ast.clone()) {
+            Err(CompileError::JumpOutOfRange(_)) => Compiler::new(true).compile(ast),
+            result => result,
+        }
+    }
 
-        compiler.byte_code
+    fn compile(mut self, ast: Vec<Statement>) -> Result<(ByteCode, Vec<Warning>), CompileError> {
+        self.compile_statements(ast)?;
+        Ok((self.byte_code, self.warnings))
     }
 
-    fn compile_statements(&mut self, ast: Vec<Statement>) {
+    fn compile_statements(&mut self, ast: Vec<Statement>) -> Result<(), CompileError> {
         for statement in ast {
             match statement {
                 Statement::ExpressionStatement(expr) => {
-                    self.compile_expression(expr);
+                    self.compile_expression(expr)?;
                     self.add_instruction(OpCode::OpPop);
                 }
                 Statement::Let { name, value } => {
-                    self.compile_expression(value);
-                    let symbol_index = self.symbol_table.define(name, GLOBAL.to_owned());
-                    self.add_instruction(OpCode::OpSetGlobal(symbol_index));
+                    self.compile_declared(&name, value)?;
+                    self.compile_set_variable(name, Mutability::Mutable)?;
+                }
+                Statement::Destructure { pattern, value } => {
+                    self.compile_destructure(pattern, value, false)?
+                }
+                Statement::Const { name, value } => {
+                    let declaration = Statement::Const {
+                        name: name.clone(),
+                        value: value.clone(),
+                    }
+                    .to_string();
+                    self.compile_declared(&name, value)?;
+                    self.compile_set_variable(name, Mutability::Const(declaration))?;
+                }
+                Statement::Return { .. } if self.scopes.is_empty() => {
+                    return Err(CompileError::Unsupported("return"))
+                }
+                // Anything a loop left on the stack goes with the frame
+                Statement::Return { value } => {
+                    self.compile_tail(value)?;
+                    self.add_instruction(OpCode::OpReturn);
+                }
+                Statement::While { condition, body } => self.compile_while(condition, body)?,
+                Statement::For {
+                    variables,
+                    iterable,
+                    body,
+                } => self.compile_for(variables, iterable, body)?,
+                Statement::Break => {
+                    let depth = self.unwind_loop("break")?;
+                    let pos = self.add_jump(Jump::Always)?;
+                    self.stack_depth = depth;
+                    let current = self.loops.last_mut().expect("Checked by unwind_loop");
+                    current.breaks.push(pos);
+                }
+                Statement::Continue => {
+                    let depth = self.unwind_loop("continue")?;
+                    let start = self.loops.last().expect("Checked by unwind_loop").start;
+                    let op = self.jump_op(Jump::Always, start)?;
+                    self.add_instruction(op);
+                    self.stack_depth = depth;
+                }
+                Statement::Comment(_) | Statement::TrailingComment(_) => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Pop what an expression around a `break` or `continue` has pushed since
+    // the loop started. Gives the depth before popping, which the code
+    // after the jump carries on from as if it fell through.
+    fn unwind_loop(&mut self, keyword: &'static str) -> Result<usize, CompileError> {
+        let depth = self.stack_depth;
+        let start_depth = match self.loops.last() {
+            Some(current) => current.depth,
+            None => return Err(CompileError::OutsideLoop(keyword)),
+        };
+        for _ in start_depth..depth {
+            self.add_instruction(OpCode::OpPop);
+        }
+        Ok(depth)
+    }
+
+    // Compile the block of an `if` so it leaves its value on the stack, like
+    // the evaluator a `let` gives its value and other statements give null.
+    // In `tail` position of a function body a call ends the function.
+    fn compile_branch(
+        &mut self,
+        mut block: Vec<Statement>,
+        tail: bool,
+    ) -> Result<(), CompileError> {
+        // A destructuring `let` gives the whole value
+        if let Some(Statement::Destructure { .. }) = block.last() {
+            if let Some(Statement::Destructure { pattern, value }) = block.pop() {
+                self.compile_statements(block)?;
+                return self.compile_destructure(pattern, value, true);
+            }
+        }
+
+        if let (true, Some(Statement::ExpressionStatement(_))) = (tail, block.last()) {
+            if let Some(Statement::ExpressionStatement(expr)) = block.pop() {
+                self.compile_statements(block)?;
+                return self.compile_tail(expr);
+            }
+        }
+
+        let binding = match block.last() {
+            Some(Statement::Let { name, .. }) | Some(Statement::Const { name, .. }) => {
+                Some(name.clone())
+            }
+            _ => None,
+        };
+
+        self.compile_statements(block)?;
+        if self.is_last_instruction_pop() {
+            self.remove_last_pop();
+        } else if let Some(name) = binding {
+            self.compile_expression(Expression::Ident(name))?;
+        } else {
+            self.add_instruction(OpCode::OpNull);
+        }
+        Ok(())
+    }
+
+    fn compile_while(
+        &mut self,
+        condition: Expression,
+        body: Vec<Statement>,
+    ) -> Result<(), CompileError> {
+        let start = self.byte_code.instructions.len();
+        let depth = self.stack_depth;
+        self.compile_expression(condition)?;
+        let exit = self.add_jump(Jump::IfFalse)?;
+
+        self.loops.push(Loop {
+            start,
+            breaks: vec![],
+            depth,
+        });
+        self.compile_statements(body)?;
+        let current = self.loops.pop().expect("Loop pushed above");
+
+        // Jump back to check the condition again
+        let op = self.jump_op(Jump::Always, start)?;
+        self.add_instruction(op);
+
+        self.patch_jump(exit, Jump::IfFalse)?;
+        for pos in current.breaks {
+            self.patch_jump(pos, Jump::Always)?;
+        }
+
+        // A loop gives null, as in the evaluator, rather than leaving the
+        // condition as the last popped value
+        self.add_instruction(OpCode::OpNull);
+        self.add_instruction(OpCode::OpPop);
+
+        Ok(())
+    }
+
+    // The iterator stays on the stack for the whole loop. OpIterNext pushes
+    // the loop variables in order, so they are set in reverse.
+    fn compile_for(
+        &mut self,
+        variables: Vec<String>,
+        iterable: Expression,
+        body: Vec<Statement>,
+    ) -> Result<(), CompileError> {
+        self.compile_expression(iterable)?;
+        self.add_instruction(OpCode::OpIterInit(variables.len() as u8));
+
+        let start = self.byte_code.instructions.len();
+        let depth = self.stack_depth;
+        let exit = self.add_jump(Jump::IterNext)?;
+        self.stack_depth += variables.len();
+        for name in variables.into_iter().rev() {
+            self.compile_set_variable(name, Mutability::Mutable)?;
+        }
+
+        self.loops.push(Loop {
+            start,
+            breaks: vec![],
+            depth,
+        });
+        self.compile_statements(body)?;
+        let current = self.loops.pop().expect("Loop pushed above");
+
+        let op = self.jump_op(Jump::Always, start)?;
+        self.add_instruction(op);
+
+        // Finishing or breaking out of the loop discards the iterator, then
+        // like a while loop the loop gives null
+        self.patch_jump(exit, Jump::IterNext)?;
+        for pos in current.breaks {
+            self.patch_jump(pos, Jump::Always)?;
+        }
+        self.add_instruction(OpCode::OpPop);
+        self.add_instruction(OpCode::OpNull);
+        self.add_instruction(OpCode::OpPop);
+
+        Ok(())
+    }
+
+    // Arms are tried in order. A pattern's tests each jump to the next arm
+    // if they fail, so the stack is empty whenever an arm is left. The
+    // pattern and guard see the arm's names in hidden variables, which are
+    // only copied to the variables once the arm is taken. If no arm matches
+    // the match gives null.
+    fn compile_match(
+        &mut self,
+        value: Expression,
+        arms: Vec<MatchArm>,
+        tail: bool,
+    ) -> Result<(), CompileError> {
+        self.check_arms(&value, &arms);
+
+        let match_depth = self.match_depth;
+        self.compile_expression(value)?;
+        let subject = self.match_slot()?;
+        self.add_instruction(self.set_op(subject)?);
+
+        // Each arm starts from the same depth, as does the null at the end
+        let depth = self.stack_depth;
+        let mut ends = vec![];
+        for arm in arms {
+            self.stack_depth = depth;
+            let mut fails = Fails::NextArm {
+                jumps: vec![],
+                bindings: vec![],
+                depth: match_depth,
+            };
+            self.compile_pattern(&arm.pattern, subject, &mut fails)?;
+            if let Some(guard) = arm.guard {
+                let bindings = match &fails {
+                    Fails::NextArm { bindings, .. } => bindings.clone(),
+                    Fails::Error => unreachable!("Arms jump to the next arm"),
+                };
+                let shadowed: Vec<_> = bindings
+                    .into_iter()
+                    .map(|(name, index)| (name.clone(), self.symbol_table.shadow(name, index)))
+                    .collect();
+                let guard = self.compile_expression(guard);
+                for (name, symbol) in shadowed.into_iter().rev() {
+                    self.symbol_table.restore(name, symbol);
+                }
+                guard?;
+                self.compile_fail(&mut fails, subject, String::new)?;
+            }
+
+            let (jumps, bindings) = match fails {
+                Fails::NextArm {
+                    jumps, bindings, ..
+                } => (jumps, bindings),
+                Fails::Error => unreachable!("Arms jump to the next arm"),
+            };
+            for (name, index) in bindings {
+                self.add_instruction(self.get_op(index));
+                self.compile_set_variable(name, Mutability::Mutable)?;
+            }
+            self.compile_branch(arm.body, tail)?;
+            ends.push(self.add_jump(Jump::Always)?);
+
+            for pos in jumps {
+                self.patch_jump(pos, Jump::IfFalse)?;
+            }
+        }
+        self.stack_depth = depth;
+        self.add_instruction(OpCode::OpNull);
+        for pos in ends {
+            self.patch_jump(pos, Jump::Always)?;
+        }
+
+        self.match_depth -= 1;
+        Ok(())
+    }
+
+    // Bind the names in a pattern to the parts of a value, stopping with an
+    // error if the value doesn't fit. With `keep` the value is left on the
+    // stack.
+    fn compile_destructure(
+        &mut self,
+        pattern: Pattern,
+        value: Expression,
+        keep: bool,
+    ) -> Result<(), CompileError> {
+        self.compile_expression(value)?;
+        let subject = self.match_slot()?;
+        self.add_instruction(self.set_op(subject)?);
+        self.compile_pattern(&pattern, subject, &mut Fails::Error)?;
+        if keep {
+            self.add_instruction(self.get_op(subject));
+        }
+        self.match_depth -= 1;
+        Ok(())
+    }
+
+    // Test the value in the global `subject` against a pattern, binding
+    // names as it goes. Array items and hash values are tested from globals
+    // of their own.
+    fn compile_pattern(
+        &mut self,
+        pattern: &Pattern,
+        subject: usize,
+        fails: &mut Fails,
+    ) -> Result<(), CompileError> {
+        match pattern {
+            Pattern::Wildcard => (),
+            Pattern::Binding(name) => {
+                self.add_instruction(self.get_op(subject));
+                self.compile_binding(name, fails)?;
+            }
+            Pattern::Literal(literal) => {
+                self.add_instruction(self.get_op(subject));
+                self.compile_expression(literal.clone())?;
+                self.add_instruction(OpCode::OpMatchEqual);
+                self.compile_fail(fails, subject, || mismatch_detail(pattern, None))?;
+            }
+            Pattern::Array(patterns) => {
+                self.add_instruction(self.get_op(subject));
+                self.add_instruction(OpCode::OpMatchArray(element_count(patterns.len())?));
+                self.compile_fail(fails, subject, || mismatch_detail(pattern, None))?;
+                for (i, item) in patterns.iter().enumerate() {
+                    let index = Expression::Int(i as isize);
+                    self.compile_sub_pattern(item, subject, index, fails)?;
+                }
+            }
+            Pattern::Hash(pairs) => {
+                self.add_instruction(self.get_op(subject));
+                self.add_instruction(OpCode::OpMatchHash);
+                self.compile_fail(fails, subject, || mismatch_detail(pattern, None))?;
+                for (key, item) in pairs {
+                    self.add_instruction(self.get_op(subject));
+                    self.compile_expression(key.clone())?;
+                    self.add_instruction(OpCode::OpMatchKey);
+                    self.compile_fail(fails, subject, || mismatch_detail(pattern, Some(key)))?;
+                    self.compile_sub_pattern(item, subject, key.clone(), fails)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Test the item at `index` of the value in `subject`. Names and `_`
+    // can't fail so they don't need a global of their own.
+    fn compile_sub_pattern(
+        &mut self,
+        pattern: &Pattern,
+        subject: usize,
+        index: Expression,
+        fails: &mut Fails,
+    ) -> Result<(), CompileError> {
+        if *pattern == Pattern::Wildcard {
+            return Ok(());
+        }
+        self.add_instruction(self.get_op(subject));
+        self.compile_expression(index)?;
+        self.add_instruction(OpCode::OpIndex);
+        if let Pattern::Binding(name) = pattern {
+            return self.compile_binding(name, fails);
+        }
+        let slot = self.match_slot()?;
+        self.add_instruction(self.set_op(slot)?);
+        self.compile_pattern(pattern, slot, fails)?;
+        self.match_depth -= 1;
+        Ok(())
+    }
+
+    // Set a name in a pattern to the value on the stack. In a match arm it
+    // goes to a hidden variable until the arm is taken.
+    fn compile_binding(&mut self, name: &str, fails: &mut Fails) -> Result<(), CompileError> {
+        match fails {
+            Fails::NextArm {
+                bindings, depth, ..
+            } => {
+                let index =
+                    self.define(format!("{} in match {}", name, depth), Mutability::Mutable)?;
+                self.add_instruction(self.set_op(index)?);
+                if !bindings.iter().any(|(bound, _)| bound == name) {
+                    bindings.push((name.to_owned(), index));
+                }
+                Ok(())
+            }
+            Fails::Error => self.compile_set_variable(name.to_owned(), Mutability::Mutable),
+        }
+    }
+
+    // Act on the result of a test left on the stack. A failed destructure
+    // reports the value in `subject` and what the pattern expected of it.
+    fn compile_fail(
+        &mut self,
+        fails: &mut Fails,
+        subject: usize,
+        detail: impl FnOnce() -> String,
+    ) -> Result<(), CompileError> {
+        match fails {
+            Fails::NextArm { jumps, .. } => jumps.push(self.add_jump(Jump::IfFalse)?),
+            Fails::Error => {
+                let pass = self.add_jump(Jump::IfTrue)?;
+                self.add_instruction(self.get_op(subject));
+                self.compile_constant(Object::String(detail().into()))?;
+                self.add_instruction(OpCode::OpMismatch);
+                self.patch_jump(pass, Jump::IfTrue)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Reserve the next hidden global for a value being matched or
+    // destructured, the caller releases it by decrementing match_depth.
+    // The names can't clash with variables as they contain a space.
+    fn match_slot(&mut self) -> Result<usize, CompileError> {
+        let name = format!("match {}", self.match_depth);
+        self.match_depth += 1;
+        let index = self.define(name, Mutability::Mutable)?;
+        self.set_op(index)?;
+        Ok(index)
+    }
+
+    // Warn about arms that can never run and matches that can fall through
+    fn check_arms(&mut self, value: &Expression, arms: &[MatchArm]) {
+        let mut exhaustive = false;
+        let mut seen: Vec<&Pattern> = vec![];
+        for arm in arms {
+            if exhaustive || (arm.guard.is_none() && seen.contains(&&arm.pattern)) {
+                self.warnings
+                    .push(Warning::UnreachableArm(arm.pattern.to_string()));
+                continue;
+            }
+            if arm.guard.is_none() {
+                exhaustive |= matches!(arm.pattern, Pattern::Wildcard | Pattern::Binding(_));
+                seen.push(&arm.pattern);
+            }
+        }
+        if !exhaustive {
+            self.warnings
+                .push(Warning::NonExhaustiveMatch(value.to_string()));
+        }
+    }
+
+    fn compile_set_variable(
+        &mut self,
+        name: String,
+        mutability: Mutability,
+    ) -> Result<(), CompileError> {
+        // The VM names the constant when an assignment to it fails
+        let constant = match &mutability {
+            Mutability::Const(declaration) => format!("{} declared by `{}`", name, declaration),
+            Mutability::Mutable => String::new(),
+        };
+        let index = self.define(name, mutability.clone())?;
+        let op = match mutability {
+            Mutability::Mutable => self.set_op(index)?,
+            Mutability::Const(_) => {
+                self.compile_constant(Object::String(constant.into()))?;
+                match self.scopes.is_empty() {
+                    true => set_const_op(index)?,
+                    false => OpCode::OpSetLocalConst(local_index(index)?),
+                }
+            }
+        };
+        self.add_instruction(op);
+        Ok(())
+    }
+
+    fn define(&mut self, name: String, mutability: Mutability) -> Result<usize, CompileError> {
+        self.symbol_table
+            .define(name.clone(), mutability)
+            .map_err(|declaration| CompileError::ConstRedeclaration { name, declaration })
+    }
+
+    // Variables are globals at the top level and locals in a function body
+    fn set_op(&self, index: usize) -> Result<OpCode, CompileError> {
+        match self.scopes.is_empty() {
+            true => set_global_op(index),
+            false => Ok(OpCode::OpSetLocal(local_index(index)?)),
+        }
+    }
+
+    // Variables are checked when they are set so any index fits
+    fn get_op(&self, index: usize) -> OpCode {
+        match self.scopes.is_empty() {
+            true => get_global_op(index),
+            false => OpCode::OpGetLocal(index as u16),
+        }
+    }
+
+    fn assign_op(&self, index: usize) -> OpCode {
+        match self.scopes.is_empty() {
+            true => assign_global_op(index),
+            false => OpCode::OpAssignLocal(index as u16),
+        }
+    }
+
+    // The value of a `let` or `const`, a function literal is named after the
+    // variable so it can call itself
+    fn compile_declared(&mut self, name: &str, value: Expression) -> Result<(), CompileError> {
+        match value {
+            Expression::FnLiteral { parameters, body } => {
+                self.compile_function(Some(name.to_owned()), parameters, body)
+            }
+            value => self.compile_expression(value),
+        }
+    }
+
+    // The body follows the OpFunction that pushes the function, which
+    // jumps over it. Arguments are passed in the first locals, then each
+    // parameter is bound from its argument, or its default if none was
+    // passed, like the evaluator binds them.
+    fn compile_function(
+        &mut self,
+        name: Option<String>,
+        parameters: Vec<Parameter>,
+        body: Vec<Statement>,
+    ) -> Result<(), CompileError> {
+        self.compile_constant(Object::String(signature(&parameters).into()))?;
+        let function = self.add_jump(Jump::Function)?;
+        self.scopes.push(Scope {
+            symbol_table: mem::take(&mut self.symbol_table),
+            loops: mem::take(&mut self.loops),
+            match_depth: mem::replace(&mut self.match_depth, 0),
+            stack_depth: mem::replace(&mut self.stack_depth, 0),
+        });
+
+        let (required, max, rest) = arity(&parameters);
+        let required = element_count(required)?;
+        self.add_instruction(OpCode::OpParameters(required, element_count(max)?, rest));
+        let locals = self.add_instruction(OpCode::OpLocals(0));
+        // The names can't clash with variables as they contain a space
+        for i in 0..parameters.len() {
+            self.define(format!("argument {}", i), Mutability::Mutable)?;
+        }
+        if let Some(name) = name {
+            self.add_instruction(OpCode::OpCurrentFunction);
+            self.compile_set_variable(name, Mutability::Mutable)?;
+        }
+        for (i, parameter) in parameters.into_iter().enumerate() {
+            match parameter {
+                Parameter::Single { pattern, default } => {
+                    if let Some(default) = default {
+                        self.add_instruction(OpCode::OpHasArgument(i as u16));
+                        let passed = self.add_jump(Jump::IfTrue)?;
+                        self.compile_expression(default)?;
+                        self.add_instruction(OpCode::OpSetLocal(i as u16));
+                        self.patch_jump(passed, Jump::IfTrue)?;
+                    }
+                    self.compile_pattern(&pattern, i, &mut Fails::Error)?;
+                }
+                Parameter::Rest(name) => {
+                    self.add_instruction(OpCode::OpGetLocal(i as u16));
+                    self.compile_set_variable(name, Mutability::Mutable)?;
+                }
+            }
+        }
+
+        self.compile_branch(body, true)?;
+        self.add_instruction(OpCode::OpReturn);
+        let count = local_index(self.symbol_table.slots())?;
+        self.replace_op(locals, OpCode::OpLocals(count));
+
+        let scope = self.scopes.pop().expect("Scope pushed above");
+        self.symbol_table = scope.symbol_table;
+        self.loops = scope.loops;
+        self.match_depth = scope.match_depth;
+        self.stack_depth = scope.stack_depth;
+        self.patch_jump(function, Jump::Function)
+    }
+
+    // A name that isn't a variable is a built in function. In tail position
+    // the call replaces the function making it.
+    fn compile_call(
+        &mut self,
+        function: Expression,
+        args: Vec<Expression>,
+        tail: bool,
+    ) -> Result<(), CompileError> {
+        let count = element_count(args.len())?;
+        if let Expression::Ident(name) = &function {
+            let builtin = BUILTINS.iter().position(|builtin| builtin == name);
+            if let (None, Some(index)) = (self.symbol_table.resolve(name.clone()), builtin) {
+                for arg in args {
+                    self.compile_expression(arg)?;
                 }
-                _ => unimplemented!(),
+                self.add_instruction(OpCode::OpCallBuiltin(index as u8, count));
+                return Ok(());
             }
         }
+
+        self.compile_expression(function)?;
+        for arg in args {
+            self.compile_expression(arg)?;
+        }
+        match tail {
+            true => self.add_instruction(OpCode::OpTailCall(count)),
+            false => self.add_instruction(OpCode::OpCall(count)),
+        };
+        Ok(())
     }
 
-    fn compile_expression(&mut self, expr: Expression) {
+    // An expression ending a function body, as a call or through the
+    // branches of an `if` or a match
+    fn compile_tail(&mut self, expr: Expression) -> Result<(), CompileError> {
         match expr {
-            Expression::Int(val) => {
-                let index = self.add_constant(Object::Int(val));
-                self.add_instruction(OpCode::OpConstant(index));
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => self.compile_if(*condition, consequence, alternative, true),
+            Expression::Match { value, arms } => self.compile_match(*value, arms, true),
+            Expression::FnCall { function, args } => self.compile_call(*function, args, true),
+            expr => self.compile_expression(expr),
+        }
+    }
+
+    fn compile_if(
+        &mut self,
+        condition: Expression,
+        consequence: Vec<Statement>,
+        alternative: Vec<Statement>,
+        tail: bool,
+    ) -> Result<(), CompileError> {
+        self.compile_expression(condition)?;
+
+        let jmp_false = self.add_jump(Jump::IfFalse)?;
+        let depth = self.stack_depth;
+
+        self.compile_branch(consequence, tail)?;
+        // This OpJmp is hit and skips alternative if condition is true
+        let jmp = self.add_jump(Jump::Always)?;
+        // Jump to here if condition is false, a missing alternative
+        // gives null
+        self.patch_jump(jmp_false, Jump::IfFalse)?;
+        self.stack_depth = depth;
+        self.compile_branch(alternative, tail)?;
+        self.patch_jump(jmp, Jump::Always)?;
+        Ok(())
+    }
+
+    // `a && b` jumps to push false as soon as an operand is falsy, and
+    // `a || b` jumps to push true as soon as one is truthy
+    fn compile_logical(
+        &mut self,
+        left: Expression,
+        op: Operator,
+        right: Expression,
+    ) -> Result<(), CompileError> {
+        let (jump, short_circuit, otherwise) = match op {
+            Operator::AND => (Jump::IfFalse, OpCode::OpFalse, OpCode::OpTrue),
+            _ => (Jump::IfTrue, OpCode::OpTrue, OpCode::OpFalse),
+        };
+
+        self.compile_expression(left)?;
+        let first = self.add_jump(jump)?;
+        let depth = self.stack_depth;
+        self.compile_expression(right)?;
+        let second = self.add_jump(jump)?;
+        self.add_instruction(otherwise);
+        let end = self.add_jump(Jump::Always)?;
+
+        self.patch_jump(first, jump)?;
+        self.patch_jump(second, jump)?;
+        self.stack_depth = depth;
+        self.add_instruction(short_circuit);
+        self.patch_jump(end, Jump::Always)?;
+        Ok(())
+    }
+
+    // Store the value on top of the stack in `target`, leaving the value on
+    // the stack as the result of the assignment
+    fn compile_assign(&mut self, target: Expression, assignment: &str) -> Result<(), CompileError> {
+        match target {
+            Expression::Ident(name) => {
+                let index = match self.symbol_table.resolve(name.clone()) {
+                    Some(index) => index,
+                    None => return Err(CompileError::UndefinedVariable(name)),
+                };
+                if let Some(Mutability::Const(declaration)) = self
+                    .symbol_table
+                    .symbol(&name)
+                    .map(|symbol| symbol.mutability())
+                {
+                    return Err(CompileError::ConstAssignment {
+                        name,
+                        declaration: declaration.clone(),
+                        assignment: assignment.to_owned(),
+                    });
+                }
+                // A constant declared after the assignment in a loop is only
+                // caught when it runs, so the VM needs the assignment too
+                self.compile_constant(Object::String(assignment.into()))?;
+                self.add_instruction(self.assign_op(index));
+                self.add_instruction(self.get_op(index));
             }
+            Expression::Index { left, index } => {
+                self.compile_expression((*left).clone())?;
+                self.compile_expression(*index)?;
+                self.add_instruction(OpCode::OpSetIndex);
+                // Store the updated container, then drop it to leave the value
+                self.compile_assign(*left, assignment)?;
+                self.add_instruction(OpCode::OpPop);
+            }
+            _ => return Err(CompileError::Unsupported("assignment target")),
+        }
+
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expr: Expression) -> Result<(), CompileError> {
+        match expr {
+            Expression::Int(val) => self.compile_constant(Object::Int(val))?,
+            Expression::BigInt(val) => self.compile_constant(Object::BigInt(val.into()))?,
+            Expression::Float(val) => self.compile_constant(Object::Float(val))?,
+            Expression::Spelled { value, .. } => self.compile_expression(*value)?,
+            Expression::String(val) => self.compile_constant(Object::String(val.into()))?,
             Expression::Boolean(val) => {
                 match val {
                     true => self.add_instruction(OpCode::OpTrue),
                     false => self.add_instruction(OpCode::OpFalse),
                 };
             }
-            Expression::Ident(val) => {
-                match self.symbol_table.resolve(val) {
-                    Some(index) => self.add_instruction(OpCode::OpGetGlobal(index)),
-                    None => panic!("Undefined variable"),
-                };
+            Expression::Null => {
+                self.add_instruction(OpCode::OpNull);
             }
+            Expression::Ident(val) => match self.symbol_table.resolve(val.clone()) {
+                Some(index) => {
+                    self.add_instruction(self.get_op(index));
+                }
+                None => return Err(CompileError::UndefinedVariable(val)),
+            },
+            Expression::Infix {
+                left,
+                op: op @ (Operator::AND | Operator::OR),
+                right,
+            } => self.compile_logical(*left, op, *right)?,
             Expression::Infix { left, op, right } => {
-                self.compile_expression(*left);
-                self.compile_expression(*right);
+                self.compile_expression(*left)?;
+                self.compile_expression(*right)?;
 
                 match op {
                     Operator::PLUS => self.add_instruction(OpCode::OpAdd),
                     Operator::MINUS => self.add_instruction(OpCode::OpSub),
                     Operator::MULTIPLY => self.add_instruction(OpCode::OpMul),
                     Operator::DIVIDE => self.add_instruction(OpCode::OpDiv),
+                    Operator::MODULO => self.add_instruction(OpCode::OpMod),
+                    Operator::POWER => self.add_instruction(OpCode::OpPow),
                     Operator::GREATER => self.add_instruction(OpCode::OpGreater),
                     Operator::LESS => self.add_instruction(OpCode::OpLess),
+                    Operator::GREATEREQUAL => self.add_instruction(OpCode::OpGreaterEqual),
+                    Operator::LESSEQUAL => self.add_instruction(OpCode::OpLessEqual),
                     Operator::EQUAL => self.add_instruction(OpCode::OpEqual),
                     Operator::NEQUAL => self.add_instruction(OpCode::OpNotEqual),
+                    Operator::RANGE => self.add_instruction(OpCode::OpRange),
+                    Operator::RANGEINCLUSIVE => self.add_instruction(OpCode::OpRangeInclusive),
+                    Operator::AND | Operator::OR => unreachable!("compiled by compile_logical"),
                 };
             }
             Expression::Prefix { prefix, value } => {
-                self.compile_expression(*value);
+                self.compile_expression(*value)?;
 
                 match prefix {
                     Prefix::MINUS => self.add_instruction(OpCode::OpMinus),
@@ -105,65 +935,134 @@ impl Compiler {
                 condition,
                 consequence,
                 alternative,
-            } => {
-                self.compile_expression(*condition);
-
-                let jmp_false = self.byte_code.instructions.len();
-                self.add_instruction(OpCode::OpJmpIfFalse(9999));
-
-                // Consequence
-                self.compile_statements(consequence);
-                if self.is_last_instruction_pop() {
-                    self.remove_last_pop();
+            } => self.compile_if(*condition, consequence, alternative, false)?,
+            Expression::Array(items) => {
+                let len = items.len();
+                for item in items {
+                    self.compile_expression(item)?;
                 }
-
-                // Alternative
-                if alternative.len() == 0 {
-                    // No alternative
-                    let new_jmp_pos = self.byte_code.instructions.len() as u16;
-                    self.replace_op(jmp_false, OpCode::OpJmpIfFalse(new_jmp_pos));
-                } else {
-                    // This OpJmp is hit and skips alternative if condition is true
-                    let jmp = self.byte_code.instructions.len();
-                    self.add_instruction(OpCode::OpJmp(9999));
-                    // Jump to here if condition is false
-                    let jmp_false_pos = self.byte_code.instructions.len() as u16;
-                    self.replace_op(jmp_false, OpCode::OpJmpIfFalse(jmp_false_pos));
-
-                    self.compile_statements(alternative);
-                    if self.is_last_instruction_pop() {
-                        self.remove_last_pop();
-                    }
-
-                    let jmp_pos = self.byte_code.instructions.len() as u16;
-                    self.replace_op(jmp, OpCode::OpJmp(jmp_pos));
+                self.add_instruction(OpCode::OpArray(element_count(len)?));
+            }
+            Expression::Hash(pairs) => {
+                let len = pairs.len();
+                for (key, value) in pairs {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
                 }
+                self.add_instruction(OpCode::OpHash(element_count(len)?));
+            }
+            Expression::Index { left, index } => {
+                self.compile_expression(*left)?;
+                self.compile_expression(*index)?;
+                self.add_instruction(OpCode::OpIndex);
+            }
+            Expression::Assign { target, op, value } => {
+                let assignment = Expression::Assign {
+                    target: target.clone(),
+                    op: op.clone(),
+                    value: value.clone(),
+                }
+                .to_string();
+                // `x op= v` is the same as `x = x op v`
+                let value = match op {
+                    Some(op) => Expression::Infix {
+                        left: target.clone(),
+                        op,
+                        right: value,
+                    },
+                    None => *value,
+                };
+                self.compile_expression(value)?;
+                self.compile_assign(*target, &assignment)?;
             }
-            _ => unimplemented!(),
+            Expression::Match { value, arms } => self.compile_match(*value, arms, false)?,
+            Expression::FnLiteral { parameters, body } => {
+                self.compile_function(None, parameters, body)?
+            }
+            Expression::FnCall { function, args } => self.compile_call(*function, args, false)?,
         }
+
+        Ok(())
+    }
+
+    fn compile_constant(&mut self, object: Object) -> Result<(), CompileError> {
+        let op = match self.add_constant(object) {
+            index if index <= u16::MAX as usize => OpCode::OpConstant(index as u16),
+            index if index <= u32::MAX as usize => OpCode::OpConstantWide(index as u32),
+            index => return Err(CompileError::TooManyConstants(index)),
+        };
+        self.add_instruction(op);
+        Ok(())
     }
 
     // Add a value to the byte code constants and return the new index
-    fn add_constant(&mut self, object: Object) -> u16 {
+    fn add_constant(&mut self, object: Object) -> usize {
         self.byte_code.constants.push(object);
-        (self.byte_code.constants.len() - 1) as u16
+        self.byte_code.constants.len() - 1
     }
 
     // Add instruction to byte code instructions and return instruction position
-    fn add_instruction(&mut self, op_code: OpCode) -> u16 {
+    fn add_instruction(&mut self, op_code: OpCode) -> usize {
         let new_instruction_position = self.byte_code.instructions.len();
+        self.stack_depth = (self.stack_depth as isize + stack_effect(&op_code)) as usize;
         let op_bytes = make_op(op_code);
 
         self.byte_code.instructions.extend(op_bytes);
-        new_instruction_position as u16
+        self.last_instruction = Some(new_instruction_position);
+        new_instruction_position
+    }
+
+    // Build a jump OpCode of the width chosen for this program
+    fn jump_op(&self, jump: Jump, target: usize) -> Result<OpCode, CompileError> {
+        if !self.wide_jumps && target <= u16::MAX as usize {
+            let target = target as u16;
+            return Ok(match jump {
+                Jump::Always => OpCode::OpJmp(target),
+                Jump::IfFalse => OpCode::OpJmpIfFalse(target),
+                Jump::IfTrue => OpCode::OpJmpIfTrue(target),
+                Jump::IterNext => OpCode::OpIterNext(target),
+                Jump::Function => OpCode::OpFunction(target),
+            });
+        }
+        if self.wide_jumps && target <= u32::MAX as usize {
+            let target = target as u32;
+            return Ok(match jump {
+                Jump::Always => OpCode::OpJmpWide(target),
+                Jump::IfFalse => OpCode::OpJmpIfFalseWide(target),
+                Jump::IfTrue => OpCode::OpJmpIfTrueWide(target),
+                Jump::IterNext => OpCode::OpIterNextWide(target),
+                Jump::Function => OpCode::OpFunctionWide(target),
+            });
+        }
+        Err(CompileError::JumpOutOfRange(target))
+    }
+
+    // Add a jump with a placeholder target and return its position so it
+    // can be patched once the target is known
+    fn add_jump(&mut self, jump: Jump) -> Result<usize, CompileError> {
+        let op = self.jump_op(jump, 9999)?;
+        Ok(self.add_instruction(op))
+    }
+
+    // Point the jump at `pos` to the end of the current instructions
+    fn patch_jump(&mut self, pos: usize, jump: Jump) -> Result<(), CompileError> {
+        let target = self.byte_code.instructions.len();
+        let op = self.jump_op(jump, target)?;
+        self.replace_op(pos, op);
+        Ok(())
     }
 
     fn is_last_instruction_pop(&self) -> bool {
-        self.byte_code.instructions.last() == Some(&make_op(OpCode::OpPop)[0])
+        match self.last_instruction {
+            Some(pos) => self.byte_code.instructions[pos..] == make_op(OpCode::OpPop)[..],
+            None => false,
+        }
     }
 
     fn remove_last_pop(&mut self) {
         self.byte_code.instructions.pop();
+        self.last_instruction = None;
+        self.stack_depth += 1;
     }
 
     // This can only be used on OpCodes that output the same number of bytes
@@ -176,15 +1075,60 @@ impl Compiler {
     }
 }
 
+fn set_global_op(index: usize) -> Result<OpCode, CompileError> {
+    match index {
+        index if index <= u16::MAX as usize => Ok(OpCode::OpSetGlobal(index as u16)),
+        index if index <= u32::MAX as usize => Ok(OpCode::OpSetGlobalWide(index as u32)),
+        index => Err(CompileError::TooManyGlobals(index)),
+    }
+}
+
+fn set_const_op(index: usize) -> Result<OpCode, CompileError> {
+    match index {
+        index if index <= u16::MAX as usize => Ok(OpCode::OpSetConst(index as u16)),
+        index if index <= u32::MAX as usize => Ok(OpCode::OpSetConstWide(index as u32)),
+        index => Err(CompileError::TooManyGlobals(index)),
+    }
+}
+
+// Globals are checked when they are defined so any index fits
+fn get_global_op(index: usize) -> OpCode {
+    match index {
+        index if index <= u16::MAX as usize => OpCode::OpGetGlobal(index as u16),
+        index => OpCode::OpGetGlobalWide(index as u32),
+    }
+}
+
+fn assign_global_op(index: usize) -> OpCode {
+    match index {
+        index if index <= u16::MAX as usize => OpCode::OpAssignGlobal(index as u16),
+        index => OpCode::OpAssignGlobalWide(index as u32),
+    }
+}
+
+fn local_index(index: usize) -> Result<u16, CompileError> {
+    match index {
+        index if index <= u16::MAX as usize => Ok(index as u16),
+        index => Err(CompileError::TooManyLocals(index)),
+    }
+}
+
+fn element_count(len: usize) -> Result<u16, CompileError> {
+    match len {
+        len if len <= u16::MAX as usize => Ok(len as u16),
+        len => Err(CompileError::TooManyElements(len)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        compiler::{ByteCode, Compiler},
+        compiler::{ByteCode, CompileError, Compiler, Warning},
         evaluator::Object,
     };
 
     fn compiled(input: &str) -> ByteCode {
-        Compiler::from_source(input)
+        Compiler::from_source(input).unwrap()
     }
 
     #[test]
@@ -316,15 +1260,127 @@ mod tests {
         assert_eq!(expected, compiled(input));
     }
 
+    #[test]
+    fn test_match() {
+        let input = "let x = 5; match (x) { 1 => 2, _ => 3 }";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,   // Int 5
+                17, 0, 0,  // OpSetGlobal x
+                18, 0, 0,  // OpGetGlobal x
+                17, 0, 1,  // OpSetGlobal matched value
+                18, 0, 1,  // OpGetGlobal matched value
+                1, 0, 1,   // Int 1
+                40,        // OpMatchEqual
+                16, 0, 28, // OpJmpIfFalse
+                1, 0, 2,   // Int 2
+                15, 0, 35, // OpJmp
+                1, 0, 3,   // Int 3
+                15, 0, 35, // OpJmp
+                39,        // OpNull
+                6,         // OpPop
+            ],
+            constants: vec![Object::Int(5), Object::Int(1), Object::Int(2), Object::Int(3)],
+        };
+        assert_eq!(expected, compiled(input));
+    }
+
+    #[test]
+    fn test_destructure() {
+        let input = "let x = [1]; let [a] = x;";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,   // Int 1
+                24, 0, 1,  // OpArray
+                17, 0, 0,  // OpSetGlobal x
+                18, 0, 0,  // OpGetGlobal x
+                17, 0, 1,  // OpSetGlobal destructured value
+                18, 0, 1,  // OpGetGlobal destructured value
+                41, 0, 1,  // OpMatchArray
+                33, 0, 31, // OpJmpIfTrue
+                18, 0, 1,  // OpGetGlobal destructured value
+                1, 0, 1,   // String detail
+                44,        // OpMismatch
+                18, 0, 1,  // OpGetGlobal destructured value
+                1, 0, 2,   // Int 0
+                26,        // OpIndex
+                17, 0, 2,  // OpSetGlobal a
+            ],
+            constants: vec![
+                Object::Int(1),
+                Object::String("into `[a]`, expected an array of length 1".into()),
+                Object::Int(0),
+            ],
+        };
+        assert_eq!(expected, compiled(input));
+    }
+
+    #[test]
+    fn test_match_warnings() {
+        let tests = vec![
+            ("match (1) { 1 => 1, n => n }", vec![]),
+            ("match (1) { [a] => a, _ => 0 }", vec![]),
+            (
+                "match (x) { 1 => 1, n if n > 1 => n }",
+                vec![Warning::NonExhaustiveMatch("x".to_owned())],
+            ),
+            (
+                "match (1) { _ => 1, 2 => 2, n => n }",
+                vec![
+                    Warning::UnreachableArm("2".to_owned()),
+                    Warning::UnreachableArm("n".to_owned()),
+                ],
+            ),
+            (
+                "match (1) { [a, _] => 1, [a, _] if a => 2, [a, _] => 3, _ => 4 }",
+                vec![Warning::UnreachableArm("[a, _]".to_owned())],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let input = format!("let x = 1; {}", input);
+            let (_, warnings) = Compiler::from_source_with_warnings(&input).unwrap();
+            assert_eq!(expected, warnings, "{}", input);
+        }
+    }
+
     #[test]
     fn test_if() {
         let input = "if(true) { 10 }";
+        #[rustfmt::skip]
         let expected = ByteCode {
-            instructions: vec![7, 16, 0, 7, 1, 0, 0, 6],
+            instructions: vec![
+                7,         // OpTrue
+                16, 0, 10, // OpJmpIfFalse
+                1, 0, 0,   // Int 10
+                15, 0, 11, // OpJmp
+                39,        // OpNull
+                6,         // OpPop
+            ],
             constants: vec![Object::Int(10)],
         };
         assert_eq!(expected, compiled(input));
 
+        // Branches without a value give null, and a `let` gives its value
+        let input = "if(null) { } else { let x = 6; }";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                39,        // OpNull
+                16, 0, 8,  // OpJmpIfFalse
+                39,        // OpNull
+                15, 0, 17, // OpJmp
+                1, 0, 0,   // Int 6
+                17, 0, 0,  // OpSetGlobal x
+                18, 0, 0,  // OpGetGlobal x
+                6,         // OpPop
+            ],
+            constants: vec![Object::Int(6)],
+        };
+        assert_eq!(expected, compiled(input));
+
         let input = "if(true) { 10 } else { 20 }";
         #[rustfmt::skip]
         let expected = ByteCode {
@@ -384,4 +1440,423 @@ mod tests {
         };
         assert_eq!(expected, compiled(input));
     }
+
+    #[test]
+    fn test_errors() {
+        let input = "let x = ;";
+        assert!(matches!(
+            Compiler::from_source(input),
+            Err(CompileError::Parse(_))
+        ));
+
+        let input = "return 1;";
+        let expected = Err(CompileError::Unsupported("return"));
+        assert_eq!(expected, Compiler::from_source(input));
+
+        // A function body can't see the variables around it
+        let input = "let y = 1; fn(x) { y }";
+        let expected = Err(CompileError::UndefinedVariable("y".to_owned()));
+        assert_eq!(expected, Compiler::from_source(input));
+
+        let input = "while (true) { fn() { break } }";
+        let expected = Err(CompileError::OutsideLoop("break"));
+        assert_eq!(expected, Compiler::from_source(input));
+    }
+
+    #[test]
+    fn test_functions() {
+        let input = "let f = fn(n) { n }; f(1)";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,              // String n
+                49, 0, 29,            // OpFunction
+                54, 0, 1, 0, 1, 0,    // OpParameters 1 1 false
+                55, 0, 3,             // OpLocals 3
+                62,                   // OpCurrentFunction
+                58, 0, 1,             // OpSetLocal f
+                57, 0, 0,             // OpGetLocal argument 0
+                58, 0, 2,             // OpSetLocal n
+                57, 0, 2,             // OpGetLocal n
+                52,                   // OpReturn
+                17, 0, 0,             // OpSetGlobal f
+                18, 0, 0,             // OpGetGlobal f
+                1, 0, 1,              // Int 1
+                51, 0, 1,             // OpCall 1
+                6,                    // OpPop
+            ],
+            constants: vec![Object::String("n".into()), Object::Int(1)],
+        };
+        assert_eq!(expected, compiled(input));
+
+        let input = "fn(n) { len(n) }";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,              // String n
+                49, 0, 29,            // OpFunction
+                54, 0, 1, 0, 1, 0,    // OpParameters 1 1 false
+                55, 0, 2,             // OpLocals 2
+                57, 0, 0,             // OpGetLocal argument 0
+                58, 0, 1,             // OpSetLocal n
+                57, 0, 1,             // OpGetLocal n
+                53, 0, 0, 1,          // OpCallBuiltin len 1
+                52,                   // OpReturn
+                6,                    // OpPop
+            ],
+            constants: vec![Object::String("n".into())],
+        };
+        assert_eq!(expected, compiled(input));
+
+        // A call ending the body replaces the function making it
+        let input = "let f = fn() { if (true) { f() } else { f() + 1 } };";
+        let instructions = compiled(input).instructions;
+        assert_eq!(
+            1,
+            instructions
+                .windows(3)
+                .filter(|op| op == &[61, 0, 0])
+                .count()
+        );
+        assert_eq!(
+            1,
+            instructions
+                .windows(3)
+                .filter(|op| op == &[51, 0, 0])
+                .count()
+        );
+    }
+
+    #[test]
+    fn test_collections() {
+        let input = "['a', 2][0]";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,  // String a
+                1, 0, 1,  // Int 2
+                24, 0, 2, // OpArray 2
+                1, 0, 2,  // Int 0
+                26,       // OpIndex
+                6,        // OpPop
+            ],
+            constants: vec![Object::String("a".into()), Object::Int(2), Object::Int(0)],
+        };
+        assert_eq!(expected, compiled(input));
+
+        let input = "{1: 2}; 0..=3";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,  // Int 1
+                1, 0, 1,  // Int 2
+                25, 0, 1, // OpHash 1
+                6,        // OpPop
+                1, 0, 2,  // Int 0
+                1, 0, 3,  // Int 3
+                28,       // OpRangeInclusive
+                6,        // OpPop
+            ],
+            constants: vec![Object::Int(1), Object::Int(2), Object::Int(0), Object::Int(3)],
+        };
+        assert_eq!(expected, compiled(input));
+    }
+
+    #[test]
+    fn test_for() {
+        let input = "for (x in [1]) { x }";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,   // Int 1
+                24, 0, 1,  // OpArray 1
+                29, 1,     // OpIterInit with one variable
+                30, 0, 21, // OpIterNext or jump to the end
+                17, 0, 0,  // OpSetGlobal x
+                18, 0, 0,  // OpGetGlobal x
+                6,         // OpPop
+                15, 0, 8,  // OpJmp back to OpIterNext
+                6,         // OpPop the iterator
+                39,        // OpNull the loop's value
+                6,         // OpPop
+            ],
+            constants: vec![Object::Int(1)],
+        };
+        assert_eq!(expected, compiled(input));
+
+        let input = "for (k, v in {}) { break; }";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                25, 0, 0,  // OpHash 0
+                29, 2,     // OpIterInit with two variables
+                30, 0, 20, // OpIterNext or jump to the end
+                17, 0, 0,  // OpSetGlobal v
+                17, 0, 1,  // OpSetGlobal k
+                15, 0, 20, // OpJmp break
+                15, 0, 5,  // OpJmp back to OpIterNext
+                6,         // OpPop the iterator
+                39,        // OpNull the loop's value
+                6,         // OpPop
+            ],
+            constants: vec![],
+        };
+        assert_eq!(expected, compiled(input));
+    }
+
+    #[test]
+    fn test_rebinding() {
+        let input = "let x = 1; let x = 2;";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,  // Int 1
+                17, 0, 0, // OpSetGlobal x
+                1, 0, 1,  // Int 2
+                17, 0, 0, // OpSetGlobal x reuses the slot
+            ],
+            constants: vec![Object::Int(1), Object::Int(2)],
+        };
+        assert_eq!(expected, compiled(input));
+    }
+
+    #[test]
+    fn test_while() {
+        let input = "while (true) { break; }";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                7,          // OpTrue
+                16, 0, 10,  // OpJmpIfFalse to the end
+                15, 0, 10,  // OpJmp break
+                15, 0, 0,   // OpJmp back to the condition
+                39,         // OpNull the loop's value
+                6,          // OpPop
+            ],
+            constants: vec![],
+        };
+        assert_eq!(expected, compiled(input));
+
+        let input = "let i = 0; while (i < 1) { continue; 2; }";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,    // Int 0
+                17, 0, 0,   // OpSetGlobal i
+                18, 0, 0,   // OpGetGlobal i
+                1, 0, 1,    // Int 1
+                10,         // OpLess
+                16, 0, 26,  // OpJmpIfFalse to the end
+                15, 0, 6,   // OpJmp continue
+                1, 0, 2,    // Int 2
+                6,          // OpPop
+                15, 0, 6,   // OpJmp back to the condition
+                39,         // OpNull the loop's value
+                6,          // OpPop
+            ],
+            constants: vec![Object::Int(0), Object::Int(1), Object::Int(2)],
+        };
+        assert_eq!(expected, compiled(input));
+    }
+
+    #[test]
+    fn test_logical() {
+        let input = "true && false;";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                7,          // OpTrue
+                16, 0, 12,  // OpJmpIfFalse to OpFalse
+                8,          // OpFalse
+                16, 0, 12,  // OpJmpIfFalse to OpFalse
+                7,          // OpTrue
+                15, 0, 13,  // OpJmp to the end
+                8,          // OpFalse
+                6,          // OpPop
+            ],
+            constants: vec![],
+        };
+        assert_eq!(expected, compiled(input));
+
+        let input = "false || true;";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                8,          // OpFalse
+                33, 0, 12,  // OpJmpIfTrue to OpTrue
+                7,          // OpTrue
+                33, 0, 12,  // OpJmpIfTrue to OpTrue
+                8,          // OpFalse
+                15, 0, 13,  // OpJmp to the end
+                7,          // OpTrue
+                6,          // OpPop
+            ],
+            constants: vec![],
+        };
+        assert_eq!(expected, compiled(input));
+    }
+
+    #[test]
+    fn test_outside_loop() {
+        let input = "break;";
+        let expected = Err(CompileError::OutsideLoop("break"));
+        assert_eq!(expected, Compiler::from_source(input));
+
+        let input = "while (true) { 1 }; continue";
+        let expected = Err(CompileError::OutsideLoop("continue"));
+        assert_eq!(expected, Compiler::from_source(input));
+    }
+
+    #[test]
+    fn test_assign() {
+        let input = "let x = 1; x += 2;";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,  // Int 1
+                17, 0, 0, // OpSetGlobal x
+                18, 0, 0, // OpGetGlobal x
+                1, 0, 1,  // Int 2
+                2,        // OpAdd
+                1, 0, 2,  // String assignment
+                45, 0, 0, // OpAssignGlobal x
+                18, 0, 0, // OpGetGlobal x as the result
+                6,        // OpPop
+            ],
+            constants: vec![
+                Object::Int(1),
+                Object::Int(2),
+                Object::String("x += 2".into()),
+            ],
+        };
+        assert_eq!(expected, compiled(input));
+
+        let input = "let a = []; a[0] = 5;";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                24, 0, 0, // OpArray 0
+                17, 0, 0, // OpSetGlobal a
+                1, 0, 0,  // Int 5
+                18, 0, 0, // OpGetGlobal a
+                1, 0, 1,  // Int 0
+                32,       // OpSetIndex
+                1, 0, 2,  // String assignment
+                45, 0, 0, // OpAssignGlobal a
+                18, 0, 0, // OpGetGlobal a
+                6,        // OpPop the container
+                6,        // OpPop
+            ],
+            constants: vec![
+                Object::Int(5),
+                Object::Int(0),
+                Object::String("a[0] = 5".into()),
+            ],
+        };
+        assert_eq!(expected, compiled(input));
+
+        let input = "x = 1;";
+        let expected = Err(CompileError::UndefinedVariable("x".to_owned()));
+        assert_eq!(expected, Compiler::from_source(input));
+    }
+
+    #[test]
+    fn test_const() {
+        let input = "const x = 1; x;";
+        #[rustfmt::skip]
+        let expected = ByteCode {
+            instructions: vec![
+                1, 0, 0,  // Int 1
+                1, 0, 1,  // String constant
+                47, 0, 0, // OpSetConst x
+                18, 0, 0, // OpGetGlobal x
+                6,        // OpPop
+            ],
+            constants: vec![
+                Object::Int(1),
+                Object::String("x declared by `const x = 1;`".into()),
+            ],
+        };
+        assert_eq!(expected, compiled(input));
+
+        // Assignments are rejected even if they never run
+        let input = "const a = [1]; if (false) { a[0] = 2; };";
+        let expected = Err(CompileError::ConstAssignment {
+            name: "a".to_owned(),
+            declaration: "const a = [1];".to_owned(),
+            assignment: "a[0] = 2".to_owned(),
+        });
+        assert_eq!(expected, Compiler::from_source(input));
+
+        // A constant can't be declared again in any way
+        let tests = vec![
+            "const x = 1; let x = 2;",
+            "const x = 1; const x = 2;",
+            "const x = 1; let [x] = [2];",
+            "const x = 1; for (x in 0..1) {}",
+            "const x = 1; match (5) { x => x };",
+            "let f = fn() { const x = 1; let {'a': x} = {'a': 2}; };",
+        ];
+        for input in tests {
+            let expected = Err(CompileError::ConstRedeclaration {
+                name: "x".to_owned(),
+                declaration: "const x = 1;".to_owned(),
+            });
+            assert_eq!(expected, Compiler::from_source(input), "{}", input);
+        }
+
+        // A function body has its own constants, and a loop body only
+        // declares its constant once
+        let input = "const x = 1; let f = fn(x) { x }; for (i in 0..2) { const y = i; }";
+        assert!(Compiler::from_source(input).is_ok());
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        let input = "let x = 1; y;";
+        let expected = Err(CompileError::UndefinedVariable("y".to_owned()));
+        assert_eq!(expected, Compiler::from_source(input));
+    }
+
+    #[test]
+    fn test_wide_constants() {
+        // 65536 constants fit in narrow operands, the next one doesn't
+        let input = "1;".repeat(65537);
+        let byte_code = compiled(&input);
+        assert_eq!(65537, byte_code.constants.len());
+
+        let narrow_len = 65536 * 4; // OpConstant + OpPop
+        #[rustfmt::skip]
+        let expected = vec![
+            1, 255, 255, 6,    // Int 1 at index 65535
+            19, 0, 1, 0, 0, 6, // Wide Int 1 at index 65536
+        ];
+        assert_eq!(expected, byte_code.instructions[narrow_len - 4..].to_vec());
+    }
+
+    #[test]
+    fn test_wide_jumps() {
+        let input = format!("if(true) {{ {} }} else {{ 2 }}", "1;".repeat(20000));
+        let byte_code = compiled(&input);
+
+        // Every jump in the program switches to the wide encoding
+        #[rustfmt::skip]
+        let expected = vec![
+            7,                  // OpTrue
+            21, 0, 1, 56, 138,  // OpJmpIfFalseWide
+            1, 0, 0,            // Int 1
+        ];
+        assert_eq!(expected, byte_code.instructions[..9].to_vec());
+
+        // OpTrue, OpJmpIfFalseWide, the consequence without its last OpPop
+        // and the OpJmpWide
+        let alternative = 1 + 5 + 20000 * 4 - 1 + 5;
+        #[rustfmt::skip]
+        let expected = vec![
+            20, 0, 1, 56, 141, // OpJmpWide
+            1, 78, 32,         // Int 2
+            6,                 // OpPop
+        ];
+        assert_eq!(expected, byte_code.instructions[alternative - 5..].to_vec());
+    }
 }
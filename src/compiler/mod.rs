@@ -1,6 +1,7 @@
 mod code;
 mod compiler;
+mod serialize;
 mod symbol_table;
-pub use code::{make_op, two_u8_to_usize, OpCode};
-pub use compiler::{ByteCode, Compiler};
-pub use symbol_table::SymbolTable;
+pub use code::{four_u8_to_usize, make_op, stack_effect, two_u8_to_usize, OpCode};
+pub use compiler::{ByteCode, CompileError, Compiler, Warning};
+pub use symbol_table::{Mutability, SymbolTable};
@@ -0,0 +1,245 @@
+use crate::{compiler::ByteCode, evaluator::Object, vm};
+use num_bigint::BigInt;
+use std::convert::TryFrom;
+
+// Compiled programs are stored as:
+//
+//   "MONK", format version (1 byte)
+//   instruction length (4 bytes), instructions
+//   constant count (4 bytes), constants
+//
+// Each constant is a tag byte followed by its value. Ints and floats take
+// 8 bytes, strings a 4 byte length then UTF-8, and big ints a 4 byte length
+// then two's complement bytes. All numbers are big endian, like instruction
+// operands.
+const MAGIC: &[u8] = b"MONK";
+const VERSION: u8 = 1;
+
+const INT: u8 = 0;
+const FLOAT: u8 = 1;
+const STRING: u8 = 2;
+const BIG_INT: u8 = 3;
+
+impl ByteCode {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+
+        write_len(&mut bytes, self.instructions.len())?;
+        bytes.extend(&self.instructions);
+
+        write_len(&mut bytes, self.constants.len())?;
+        for constant in &self.constants {
+            match constant {
+                Object::Int(val) => {
+                    bytes.push(INT);
+                    bytes.extend(&(*val as i64).to_be_bytes());
+                }
+                Object::Float(val) => {
+                    bytes.push(FLOAT);
+                    bytes.extend(&val.to_bits().to_be_bytes());
+                }
+                Object::String(val) => {
+                    bytes.push(STRING);
+                    write_len(&mut bytes, val.len())?;
+                    bytes.extend(val.as_bytes());
+                }
+                Object::BigInt(val) => {
+                    let val = val.to_signed_bytes_be();
+                    bytes.push(BIG_INT);
+                    write_len(&mut bytes, val.len())?;
+                    bytes.extend(&val);
+                }
+                _ => return Err(format!("Can't serialize constant {}", constant)),
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ByteCode, String> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("Not a compiled monkey program".to_owned());
+        }
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(format!("Unsupported bytecode version {}", version));
+        }
+
+        let len = reader.len()?;
+        let instructions = reader.take(len)?.to_vec();
+
+        let count = reader.len()?;
+        let mut constants = Vec::new();
+        for _ in 0..count {
+            let constant = match reader.take(1)?[0] {
+                INT => {
+                    let val = i64::from_be_bytes(reader.array()?);
+                    let val = isize::try_from(val).map_err(|_| "Int constant out of range")?;
+                    Object::Int(val)
+                }
+                FLOAT => match f64::from_bits(u64::from_be_bytes(reader.array()?)) {
+                    val if val.is_finite() => Object::Float(val),
+                    _ => return Err("Float constants must be finite".to_owned()),
+                },
+                STRING => {
+                    let len = reader.len()?;
+                    let val = std::str::from_utf8(reader.take(len)?)
+                        .map_err(|_| "String constant is not UTF-8")?;
+                    Object::String(val.into())
+                }
+                // Normalized, so a value that fits is an Int like any other
+                BIG_INT => {
+                    let len = reader.len()?;
+                    BigInt::from_signed_bytes_be(reader.take(len)?).into()
+                }
+                tag => return Err(format!("Unknown constant tag {}", tag)),
+            };
+            constants.push(constant);
+        }
+
+        if reader.pos != bytes.len() {
+            return Err("Unexpected bytes after the constants".to_owned());
+        }
+        // Check the instructions now so a corrupt file fails to load rather
+        // than when it is run
+        vm::decode(&instructions, constants.len())?;
+        Ok(ByteCode {
+            instructions,
+            constants,
+        })
+    }
+}
+
+fn write_len(bytes: &mut Vec<u8>, len: usize) -> Result<(), String> {
+    let len = u32::try_from(len).map_err(|_| format!("Length {} is too large", len))?;
+    bytes.extend(&len.to_be_bytes());
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or("Unexpected end of bytecode")?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        Ok(u32::from_be_bytes(self.array()?) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compiler::{ByteCode, Compiler},
+        evaluator::Object,
+        vm::Vm,
+    };
+    use num_bigint::BigInt;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_round_trip() {
+        let input = "let x = 2.5e-3; let name = 'hé'; [x * 1000, name, 7, -18446744073709551616]";
+        let byte_code = Compiler::from_source(input).unwrap();
+        let expected = vec![
+            Object::Float(2.5e-3),
+            Object::String("hé".into()),
+            Object::Int(1000),
+            Object::Int(7),
+            Object::BigInt(Rc::new("18446744073709551616".parse().unwrap())),
+        ];
+        assert_eq!(expected, byte_code.constants);
+
+        let bytes = byte_code.to_bytes().unwrap();
+        let decoded = ByteCode::from_bytes(&bytes).unwrap();
+        assert_eq!(byte_code, decoded);
+
+        let mut vm = Vm::new(decoded).unwrap();
+        vm.run().unwrap();
+        assert_eq!(
+            "[2.5, hé, 7, -18446744073709551616]",
+            vm.last_popped().to_string()
+        );
+    }
+
+    #[test]
+    fn test_layout() {
+        let byte_code = ByteCode {
+            instructions: vec![1, 0, 0],
+            constants: vec![Object::Float(1.0)],
+        };
+        #[rustfmt::skip]
+        let expected = vec![
+            b'M', b'O', b'N', b'K', 1,
+            0, 0, 0, 3, 1, 0, 0,          // Instructions
+            0, 0, 0, 1,                   // One constant
+            1, 63, 240, 0, 0, 0, 0, 0, 0, // Float 1.0
+        ];
+        assert_eq!(Ok(expected), byte_code.to_bytes());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(ByteCode::from_bytes(b"MONK").is_err());
+        assert!(ByteCode::from_bytes(b"NOPE\x01\0\0\0\0\0\0\0\0").is_err());
+        assert!(ByteCode::from_bytes(b"MONK\x02\0\0\0\0\0\0\0\0").is_err());
+        assert!(ByteCode::from_bytes(b"MONK\x01\0\0\0\0\0\0\0\x01\x09").is_err());
+        assert!(ByteCode::from_bytes(b"MONK\x01\0\0\0\x05\x06").is_err());
+
+        let nan = f64::NAN.to_bits().to_be_bytes();
+        let mut bytes = b"MONK\x01\0\0\0\0\0\0\0\x01\x01".to_vec();
+        bytes.extend(&nan);
+        assert!(ByteCode::from_bytes(&bytes).is_err());
+
+        // Big ints that fit an isize decode as ints
+        let bytes = b"MONK\x01\0\0\0\0\0\0\0\x01\x03\0\0\0\x01\xff";
+        let decoded = ByteCode::from_bytes(bytes).unwrap();
+        assert_eq!(vec![Object::Int(-1)], decoded.constants);
+        let big = ByteCode {
+            instructions: vec![],
+            constants: vec![Object::BigInt(Rc::new(BigInt::from(u64::MAX)))],
+        };
+        let bytes = big.to_bytes().unwrap();
+        assert_eq!(&[3, 0, 0, 0, 9, 0, 255], &bytes[13..20]);
+        assert_eq!(Ok(big), ByteCode::from_bytes(&bytes));
+
+        // Instructions are checked as well as constants
+        assert_eq!(
+            Err("Invalid instruction: 255".to_owned()),
+            ByteCode::from_bytes(b"MONK\x01\0\0\0\x01\xff\0\0\0\0")
+        );
+        assert_eq!(
+            Err("Constant 0 doesn't exist".to_owned()),
+            ByteCode::from_bytes(b"MONK\x01\0\0\0\x03\x01\0\0\0\0\0\0")
+        );
+        assert_eq!(
+            Err("Jump to byte 2 is not the start of an instruction".to_owned()),
+            ByteCode::from_bytes(b"MONK\x01\0\0\0\x03\x0f\0\x02\0\0\0\0")
+        );
+
+        let empty = ByteCode {
+            instructions: vec![],
+            constants: vec![],
+        };
+        assert_eq!(Ok(empty), ByteCode::from_bytes(b"MONK\x01\0\0\0\0\0\0\0\0"));
+    }
+}
@@ -1,19 +1,32 @@
 use std::collections::HashMap;
 
+// Whether a declaration can be assigned to
+#[derive(Debug, PartialEq, Clone)]
+pub enum Mutability {
+    Mutable,
+    // Holds the `const` declaration, so errors can point at it
+    Const(String),
+}
+
 pub struct Symbol {
-    index: u16,
-    scope: String,
+    index: usize,
+    mutability: Mutability,
 }
 
 impl Symbol {
-    pub fn new(index: u16, scope: String) -> Self {
-        Symbol { index, scope }
+    pub fn new(index: usize, mutability: Mutability) -> Self {
+        Symbol { index, mutability }
+    }
+
+    pub fn mutability(&self) -> &Mutability {
+        &self.mutability
     }
 }
 
+#[derive(Default)]
 pub struct SymbolTable {
     symbols: HashMap<String, Symbol>,
-    next_index: u16,
+    next_index: usize,
 }
 
 impl SymbolTable {
@@ -24,19 +37,48 @@ impl SymbolTable {
         }
     }
 
-    pub fn define(&mut self, name: String, scope: String) -> u16 {
-        let symbol = Symbol::new(self.next_index, scope);
+    // Defining a name again reuses its slot, so a `let` in a loop body
+    // updates the value the rest of the loop sees. A constant can't be
+    // declared again, the error holds its declaration.
+    pub fn define(&mut self, name: String, mutability: Mutability) -> Result<usize, String> {
+        if let Some(symbol) = self.symbols.get_mut(&name) {
+            if let Mutability::Const(declaration) = &symbol.mutability {
+                return Err(declaration.clone());
+            }
+            symbol.mutability = mutability;
+            return Ok(symbol.index);
+        }
+
+        let symbol = Symbol::new(self.next_index, mutability);
         self.symbols.insert(name, symbol);
         self.next_index += 1;
-        self.next_index - 1
+        Ok(self.next_index - 1)
+    }
+
+    // How many slots have been handed out
+    pub fn slots(&self) -> usize {
+        self.next_index
+    }
+
+    pub fn resolve(&self, name: String) -> Option<usize> {
+        self.symbols.get(&name).map(|symbol| symbol.index)
     }
 
-    pub fn resolve(&self, name: String) -> Option<u16> {
-        let symbol = self.symbols.get(&name);
+    // Point a name at another slot for a while, giving what it pointed at
+    // to pass to `restore`
+    pub fn shadow(&mut self, name: String, index: usize) -> Option<Symbol> {
+        self.symbols
+            .insert(name, Symbol::new(index, Mutability::Mutable))
+    }
 
+    pub fn restore(&mut self, name: String, symbol: Option<Symbol>) {
         match symbol {
-            Some(val) => Some(val.index),
-            None => None,
-        }
+            Some(symbol) => self.symbols.insert(name, symbol),
+            None => self.symbols.remove(&name),
+        };
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
     }
 }
@@ -0,0 +1,345 @@
+use crate::evaluator::Object;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
+use std::{cmp::Ordering, convert::TryFrom, rc::Rc};
+
+// Arithmetic shared by both engines, so they fail the same way instead of
+// panicking, wrapping or producing infinities
+
+// Powers are refused past this many bits instead of running out of memory
+const MAX_POWER_BITS: u64 = 1 << 20;
+
+// The operands of a binary operator on numbers. Ints that overflow are
+// promoted to BigInts, and an int is promoted to a float when the other
+// operand is a float, which loses precision for ints beyond 2^53.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Numbers {
+    Ints(isize, isize),
+    BigInts(BigInt, BigInt),
+    Floats(f64, f64),
+}
+
+impl Numbers {
+    // None unless both operands are numbers
+    pub fn new(left: &Object, right: &Object) -> Option<Numbers> {
+        match (left, right) {
+            (Object::Int(left), Object::Int(right)) => Some(Numbers::Ints(*left, *right)),
+            (Object::Float(left), Object::Float(right)) => Some(Numbers::Floats(*left, *right)),
+            (Object::Float(left), right) => Some(Numbers::Floats(*left, to_float(right)?)),
+            (left, Object::Float(right)) => Some(Numbers::Floats(to_float(left)?, *right)),
+            (left, right) => Some(Numbers::BigInts(to_big_int(left)?, to_big_int(right)?)),
+        }
+    }
+
+    pub fn add(self) -> Result<Object, String> {
+        match self {
+            Numbers::Ints(left, right) => promote(left.checked_add(right), left, right, Self::add),
+            Numbers::BigInts(left, right) => Ok((left + right).into()),
+            Numbers::Floats(left, right) => float(left + right),
+        }
+    }
+
+    pub fn subtract(self) -> Result<Object, String> {
+        match self {
+            Numbers::Ints(left, right) => {
+                promote(left.checked_sub(right), left, right, Self::subtract)
+            }
+            Numbers::BigInts(left, right) => Ok((left - right).into()),
+            Numbers::Floats(left, right) => float(left - right),
+        }
+    }
+
+    pub fn multiply(self) -> Result<Object, String> {
+        match self {
+            Numbers::Ints(left, right) => {
+                promote(left.checked_mul(right), left, right, Self::multiply)
+            }
+            Numbers::BigInts(left, right) => Ok((left * right).into()),
+            Numbers::Floats(left, right) => float(left * right),
+        }
+    }
+
+    // Integer division truncates towards zero
+    pub fn divide(self) -> Result<Object, String> {
+        match self {
+            Numbers::Ints(_, 0) => Err("Division by zero".to_owned()),
+            // Only isize::MIN / -1 overflows
+            Numbers::Ints(left, right) => {
+                promote(left.checked_div(right), left, right, Self::divide)
+            }
+            Numbers::BigInts(_, right) if right.is_zero() => Err("Division by zero".to_owned()),
+            Numbers::BigInts(left, right) => Ok((left / right).into()),
+            Numbers::Floats(_, 0.0) => Err("Division by zero".to_owned()),
+            Numbers::Floats(left, right) => float(left / right),
+        }
+    }
+
+    pub fn modulo(self) -> Result<Object, String> {
+        match self {
+            Numbers::Ints(_, 0) => Err("Modulo by zero".to_owned()),
+            // Only isize::MIN % -1 wraps, and its result of 0 is correct
+            Numbers::Ints(left, right) => Ok(Object::Int(left.wrapping_rem(right))),
+            Numbers::BigInts(_, right) if right.is_zero() => Err("Modulo by zero".to_owned()),
+            Numbers::BigInts(left, right) => Ok((left % right).into()),
+            Numbers::Floats(_, 0.0) => Err("Modulo by zero".to_owned()),
+            Numbers::Floats(left, right) => float(left % right),
+        }
+    }
+
+    pub fn power(self) -> Result<Object, String> {
+        match self {
+            Numbers::Ints(_, exponent) if exponent < 0 => Err("Negative exponent".to_owned()),
+            Numbers::Ints(base, exponent) => {
+                let result = u32::try_from(exponent)
+                    .ok()
+                    .and_then(|exponent| base.checked_pow(exponent));
+                promote(result, base, exponent, Self::power)
+            }
+            Numbers::BigInts(base, exponent) => big_power(base, exponent),
+            Numbers::Floats(base, exponent) => float(base.powf(exponent)),
+        }
+    }
+
+    pub fn compare(self) -> Ordering {
+        match self {
+            Numbers::Ints(left, right) => left.cmp(&right),
+            Numbers::BigInts(left, right) => left.cmp(&right),
+            Numbers::Floats(left, right) => left.partial_cmp(&right).expect("Floats are never NaN"),
+        }
+    }
+}
+
+// Results that fit an isize are always Ints, so an Int and a BigInt are
+// never equal
+impl From<BigInt> for Object {
+    fn from(value: BigInt) -> Object {
+        match isize::try_from(&value) {
+            Ok(value) => Object::Int(value),
+            Err(_) => Object::BigInt(Rc::new(value)),
+        }
+    }
+}
+
+// Redo an Int operation that overflowed with BigInts
+fn promote(
+    result: Option<isize>,
+    left: isize,
+    right: isize,
+    operation: fn(Numbers) -> Result<Object, String>,
+) -> Result<Object, String> {
+    match result {
+        Some(result) => Ok(Object::Int(result)),
+        None => operation(Numbers::BigInts(left.into(), right.into())),
+    }
+}
+
+fn big_power(base: BigInt, exponent: BigInt) -> Result<Object, String> {
+    if exponent.is_negative() {
+        return Err("Negative exponent".to_owned());
+    }
+    match isize::try_from(&base) {
+        // These can't grow however large the exponent is
+        Ok(0) | Ok(1) if exponent.is_positive() => Ok(base.into()),
+        Ok(-1) if exponent.bit(0) => Ok(Object::Int(-1)),
+        Ok(-1) => Ok(Object::Int(1)),
+        _ => match u32::try_from(&exponent) {
+            Ok(exponent) if base.bits() * u64::from(exponent) <= MAX_POWER_BITS => {
+                Ok(base.pow(exponent).into())
+            }
+            _ => Err("Integer too large".to_owned()),
+        },
+    }
+}
+
+fn to_big_int(value: &Object) -> Option<BigInt> {
+    match value {
+        Object::Int(val) => Some((*val).into()),
+        Object::BigInt(val) => Some((**val).clone()),
+        _ => None,
+    }
+}
+
+// BigInts too large for a float become infinite, which `float` rejects if
+// it reaches a result
+fn to_float(value: &Object) -> Option<f64> {
+    match value {
+        Object::Int(val) => Some(*val as f64),
+        Object::BigInt(val) => val.to_f64(),
+        _ => None,
+    }
+}
+
+// Floats never hold infinity or NaN, operations that would produce them
+// are errors instead
+fn float(value: f64) -> Result<Object, String> {
+    if value.is_nan() {
+        Err("Float result is not a number".to_owned())
+    } else if value.is_infinite() {
+        Err("Float overflow".to_owned())
+    } else {
+        Ok(Object::Float(value))
+    }
+}
+
+// Convert an already rounded float to an int
+pub fn float_to_int(value: f64) -> Object {
+    BigInt::from_f64(value)
+        .expect("Floats are always finite")
+        .into()
+}
+
+pub fn square_root(value: f64) -> Result<Object, String> {
+    if value < 0.0 {
+        return Err(format!("Can't take the square root of {:?}", value));
+    }
+    float(value.sqrt())
+}
+
+// None unless the value is a number
+pub fn negate(value: &Object) -> Option<Object> {
+    match value {
+        Object::Int(val) => Some(match val.checked_neg() {
+            Some(val) => Object::Int(val),
+            None => (-BigInt::from(*val)).into(),
+        }),
+        Object::BigInt(val) => Some((-&**val).into()),
+        Object::Float(val) => Some(Object::Float(-val)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{float_to_int, negate, square_root, Numbers, Object};
+    use num_bigint::BigInt;
+    use std::{cmp::Ordering, rc::Rc};
+
+    fn ints(left: isize, right: isize) -> Numbers {
+        Numbers::Ints(left, right)
+    }
+
+    fn big(value: &str) -> Object {
+        Object::BigInt(Rc::new(value.parse().unwrap()))
+    }
+
+    #[test]
+    fn test_promotion() {
+        assert_eq!(Ok(Object::Int(isize::MAX)), ints(isize::MAX - 1, 1).add());
+        assert_eq!(Ok(big("9223372036854775808")), ints(isize::MAX, 1).add());
+        assert_eq!(
+            Ok(big("-9223372036854775809")),
+            ints(isize::MIN, 1).subtract()
+        );
+        assert_eq!(
+            Ok(big("9223372036854775808")),
+            ints(isize::MIN, -1).divide()
+        );
+        assert_eq!(
+            Ok(big("85070591730234615847396907784232501249")),
+            ints(isize::MAX, isize::MAX).multiply()
+        );
+        assert_eq!(Ok(big("10000000000000000000")), ints(10, 19).power());
+        assert_eq!(
+            Some(big("9223372036854775808")),
+            negate(&Object::Int(isize::MIN))
+        );
+        assert_eq!(
+            Some(Object::Int(-isize::MAX)),
+            negate(&Object::Int(isize::MAX))
+        );
+    }
+
+    #[test]
+    fn test_narrowing() {
+        // Results back in range are Ints again
+        let numbers = Numbers::new(&big("9223372036854775808"), &Object::Int(1)).unwrap();
+        assert_eq!(Ok(Object::Int(isize::MAX)), numbers.subtract());
+        assert_eq!(
+            Some(Object::Int(isize::MIN)),
+            negate(&big("9223372036854775808"))
+        );
+        assert_eq!(Object::Int(7), BigInt::from(7).into());
+
+        let numbers = Numbers::new(&big("-9223372036854775809"), &Object::Int(isize::MIN));
+        assert_eq!(Ordering::Less, numbers.unwrap().compare());
+    }
+
+    #[test]
+    fn test_divide() {
+        assert_eq!(Ok(Object::Int(2)), ints(7, 3).divide());
+        assert_eq!(Ok(Object::Int(-2)), ints(-7, 3).divide());
+        assert_eq!(Err("Division by zero".to_owned()), ints(7, 0).divide());
+
+        let numbers = Numbers::new(&big("-100000000000000000000"), &Object::Int(3)).unwrap();
+        assert_eq!(Ok(big("-33333333333333333333")), numbers.clone().divide());
+        assert_eq!(Ok(Object::Int(-1)), numbers.modulo());
+        let numbers = Numbers::new(&big("100000000000000000000"), &Object::Int(0)).unwrap();
+        assert_eq!(Err("Division by zero".to_owned()), numbers.divide());
+    }
+
+    #[test]
+    fn test_modulo() {
+        assert_eq!(Ok(Object::Int(1)), ints(7, 3).modulo());
+        assert_eq!(Ok(Object::Int(-1)), ints(-7, 3).modulo());
+        assert_eq!(Ok(Object::Int(0)), ints(isize::MIN, -1).modulo());
+        assert_eq!(Err("Modulo by zero".to_owned()), ints(7, 0).modulo());
+    }
+
+    #[test]
+    fn test_power() {
+        assert_eq!(Ok(Object::Int(1024)), ints(2, 10).power());
+        assert_eq!(Ok(Object::Int(1)), ints(0, 0).power());
+        assert_eq!(Ok(Object::Int(0)), ints(0, isize::MAX).power());
+        assert_eq!(Ok(Object::Int(-1)), ints(-1, isize::MAX).power());
+        assert_eq!(Ok(big("18446744073709551616")), ints(2, 64).power());
+        let too_large = Err("Integer too large".to_owned());
+        assert_eq!(too_large, ints(2, isize::MAX).power());
+        assert_eq!(too_large, ints(10, 1_000_000).power());
+        assert_eq!(Err("Negative exponent".to_owned()), ints(2, -1).power());
+    }
+
+    #[test]
+    fn test_mixed() {
+        let numbers = Numbers::new(&Object::Int(1), &Object::Float(0.5));
+        assert_eq!(Some(Numbers::Floats(1.0, 0.5)), numbers);
+        assert_eq!(Ok(Object::Float(1.5)), numbers.unwrap().add());
+
+        let numbers = Numbers::new(&big("18446744073709551616"), &Object::Float(0.5));
+        assert_eq!(
+            Ok(Object::Float(9223372036854775808.0)),
+            numbers.unwrap().multiply()
+        );
+        let huge = format!("1{}", "0".repeat(400));
+        let numbers = Numbers::new(&big(&huge), &Object::Float(0.5)).unwrap();
+        assert_eq!(Err("Float overflow".to_owned()), numbers.add());
+
+        assert_eq!(None, Numbers::new(&Object::Int(1), &Object::Boolean(true)));
+        assert_eq!(None, Numbers::new(&big(&huge), &Object::Null));
+    }
+
+    #[test]
+    fn test_floats() {
+        let numbers = |left, right| Numbers::Floats(left, right);
+        assert_eq!(Ok(Object::Float(3.5)), numbers(7.0, 2.0).divide());
+        assert_eq!(Ok(Object::Float(1.5)), numbers(7.5, 2.0).modulo());
+        assert_eq!(Ok(Object::Float(0.25)), numbers(2.0, -2.0).power());
+        assert_eq!(Ordering::Less, numbers(-0.5, 0.0).compare());
+
+        let error = |message: &str| Err(message.to_owned());
+        assert_eq!(error("Division by zero"), numbers(1.0, 0.0).divide());
+        assert_eq!(error("Modulo by zero"), numbers(1.0, 0.0).modulo());
+        assert_eq!(error("Float overflow"), numbers(1e308, 10.0).multiply());
+        assert_eq!(
+            error("Float result is not a number"),
+            numbers(-8.0, 0.5).power()
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(Object::Int(-3), float_to_int(-3.0));
+        assert_eq!(big("10000000000000000000"), float_to_int(1e19));
+        assert_eq!(Ok(Object::Float(1.5)), square_root(2.25));
+        assert!(square_root(-1.0).is_err());
+    }
+}
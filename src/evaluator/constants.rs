@@ -0,0 +1,158 @@
+use crate::parser::{Expression, MatchArm, Parameter, Pattern, Statement};
+use std::collections::HashMap;
+
+// Constants are checked before a program runs, like the compiler checks
+// them as it compiles, so both engines reject the same programs whether or
+// not the offending code runs. Going through the program in order, a name
+// declared by a `const` can't be assigned to or declared again. Holds the
+// declaration of each constant in scope.
+pub struct Constants {
+    declarations: HashMap<String, String>,
+}
+
+impl Constants {
+    pub fn new(declarations: HashMap<String, String>) -> Self {
+        Constants { declarations }
+    }
+
+    pub fn check(&mut self, ast: &[Statement]) -> Result<(), String> {
+        for statement in ast {
+            match statement {
+                Statement::Let { name, value } => {
+                    self.expression(value)?;
+                    self.declare(name)?;
+                }
+                Statement::Destructure { pattern, value } => {
+                    self.expression(value)?;
+                    self.pattern(pattern)?;
+                }
+                Statement::Const { name, value } => {
+                    self.expression(value)?;
+                    self.declare(name)?;
+                    self.declarations
+                        .insert(name.clone(), statement.to_string());
+                }
+                Statement::Return { value } | Statement::ExpressionStatement(value) => {
+                    self.expression(value)?
+                }
+                Statement::While { condition, body } => {
+                    self.expression(condition)?;
+                    self.check(body)?;
+                }
+                Statement::For {
+                    variables,
+                    iterable,
+                    body,
+                } => {
+                    self.expression(iterable)?;
+                    for name in variables {
+                        self.declare(name)?;
+                    }
+                    self.check(body)?;
+                }
+                Statement::Break
+                | Statement::Continue
+                | Statement::Comment(_)
+                | Statement::TrailingComment(_) => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expression) -> Result<(), String> {
+        match expr {
+            Expression::Infix { left, right, .. } => {
+                self.expression(left)?;
+                self.expression(right)
+            }
+            Expression::Prefix { value, .. } => self.expression(value),
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expression(condition)?;
+                self.check(consequence)?;
+                self.check(alternative)
+            }
+            // A function body can only see its parameters and its own name
+            Expression::FnLiteral { parameters, body } => {
+                let mut constants = Constants::new(HashMap::new());
+                for parameter in parameters {
+                    if let Parameter::Single {
+                        default: Some(default),
+                        ..
+                    } = parameter
+                    {
+                        constants.expression(default)?;
+                    }
+                }
+                constants.check(body)
+            }
+            Expression::FnCall { function, args } => {
+                self.expression(function)?;
+                args.iter().try_for_each(|arg| self.expression(arg))
+            }
+            Expression::Array(items) => items.iter().try_for_each(|item| self.expression(item)),
+            Expression::Hash(pairs) => pairs.iter().try_for_each(|(key, value)| {
+                self.expression(key)?;
+                self.expression(value)
+            }),
+            Expression::Index { left, index } => {
+                self.expression(left)?;
+                self.expression(index)
+            }
+            Expression::Assign { target, value, .. } => {
+                self.expression(value)?;
+                self.expression(target)?;
+                let name = target.assigned_variable().unwrap_or_default();
+                match self.declarations.get(name) {
+                    Some(declaration) => Err(format!(
+                        "Assignment to constant {} declared by `{}` in `{}`",
+                        name, declaration, expr
+                    )),
+                    None => Ok(()),
+                }
+            }
+            Expression::Match { value, arms } => {
+                self.expression(value)?;
+                arms.iter().try_for_each(|arm| self.arm(arm))
+            }
+            Expression::Int(_)
+            | Expression::BigInt(_)
+            | Expression::Float(_)
+            | Expression::Spelled { .. }
+            | Expression::Boolean(_)
+            | Expression::Null
+            | Expression::Ident(_)
+            | Expression::String(_) => Ok(()),
+        }
+    }
+
+    fn arm(&mut self, arm: &MatchArm) -> Result<(), String> {
+        self.pattern(&arm.pattern)?;
+        if let Some(guard) = &arm.guard {
+            self.expression(guard)?;
+        }
+        self.check(&arm.body)
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> Result<(), String> {
+        match pattern {
+            Pattern::Binding(name) => self.declare(name),
+            Pattern::Array(patterns) => patterns.iter().try_for_each(|item| self.pattern(item)),
+            Pattern::Hash(pairs) => pairs.iter().try_for_each(|(_, item)| self.pattern(item)),
+            Pattern::Wildcard | Pattern::Literal(_) => Ok(()),
+        }
+    }
+
+    fn declare(&self, name: &str) -> Result<(), String> {
+        match self.declarations.get(name) {
+            Some(declaration) => Err(format!(
+                "Redeclaration of constant {} declared by `{}`",
+                name, declaration
+            )),
+            None => Ok(()),
+        }
+    }
+}
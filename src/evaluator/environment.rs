@@ -1,23 +1,111 @@
 use crate::evaluator::Object;
 use std::collections::HashMap;
 
+#[derive(Default)]
 pub struct Environment {
     store: HashMap<String, Object>,
+    // The declaration of each `const` binding
+    constants: HashMap<String, String>,
+    // How many calls deep the environment's function is
+    depth: usize,
 }
 
 impl Environment {
     pub fn new() -> Self {
+        Environment::nested(0)
+    }
+
+    // The environment of a function called `depth` calls deep
+    pub fn nested(depth: usize) -> Self {
         Environment {
             store: HashMap::new(),
+            constants: HashMap::new(),
+            depth,
         }
     }
 
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
     pub fn get(&self, key: &str) -> Option<Object> {
-        let val = self.store.get(key).map(|val| val.clone());
-        val.clone()
+        self.store.get(key).cloned()
+    }
+
+    // The declaration of each constant, which a program run in this
+    // environment can't declare again
+    pub fn constants(&self) -> &HashMap<String, String> {
+        &self.constants
+    }
+
+    // The variable an assignment writes to, failing if it was never declared
+    // or is a constant
+    pub fn get_mut(&mut self, key: &str) -> Result<&mut Object, String> {
+        if let Some(declaration) = self.constants.get(key) {
+            return Err(format!(
+                "Assignment to constant {} declared by `{}`",
+                key, declaration
+            ));
+        }
+        self.store
+            .get_mut(key)
+            .ok_or_else(|| format!("Assignment to undeclared variable {}", key))
+    }
+
+    pub fn set(&mut self, key: &str, value: Object) -> Result<(), String> {
+        *self.get_mut(key)? = value;
+        Ok(())
+    }
+
+    // Declare a variable, replacing any earlier declaration of the name
+    pub fn define(&mut self, key: String, value: Object) {
+        self.constants.remove(&key);
+        self.store.insert(key, value);
+    }
+
+    // Put back a variable as `get` gave it, undeclaring it if it was never
+    // declared
+    pub fn restore(&mut self, key: String, value: Option<Object>) {
+        match value {
+            Some(value) => self.define(key, value),
+            None => {
+                self.store.remove(&key);
+            }
+        }
     }
 
-    pub fn set(&mut self, key: String, value: Object) {
+    pub fn define_const(&mut self, key: String, value: Object, declaration: String) {
+        self.constants.insert(key.clone(), declaration);
         self.store.insert(key, value);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{Environment, Object};
+
+    #[test]
+    fn test_set() {
+        let mut env = Environment::new();
+        env.define("a".to_owned(), Object::Int(1));
+        env.set("a", Object::Int(2)).unwrap();
+        assert_eq!(Some(Object::Int(2)), env.get("a"));
+
+        let expected = Err("Assignment to undeclared variable b".to_owned());
+        assert_eq!(expected, env.set("b", Object::Int(1)));
+    }
+
+    #[test]
+    fn test_constants() {
+        let mut env = Environment::new();
+        env.define_const("a".to_owned(), Object::Int(1), "const a = 1;".to_owned());
+
+        let expected = Err("Assignment to constant a declared by `const a = 1;`".to_owned());
+        assert_eq!(expected, env.set("a", Object::Int(2)));
+        assert_eq!(Some(Object::Int(1)), env.get("a"));
+
+        // Declaring the name again with `let` makes it mutable
+        env.define("a".to_owned(), Object::Int(3));
+        assert_eq!(Ok(()), env.set("a", Object::Int(4)));
+    }
+}
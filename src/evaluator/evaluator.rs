@@ -1,41 +1,279 @@
 use crate::{
-    evaluator::Environment,
-    parser::{Expression, Operator, Prefix, Statement},
+    evaluator::{
+        constants::Constants, float_to_int, negate, square_root, Environment, Hash, Iter, Numbers,
+    },
+    parser::{Expression, MatchArm, Operator, Parameter, Pattern, Prefix, Statement},
 };
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::{cmp::Ordering, convert::TryFrom, fmt, rc::Rc};
+
+// How many calls deep either engine goes before stopping with an error
+pub const MAX_FRAMES: usize = 1024;
+
+// The stack left before a call grows it, and how much it grows by
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_GROWTH: usize = 4 * 1024 * 1024;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Object {
     Null,
     Int(isize),
+    // Only holds values outside the range of an Int
+    BigInt(Rc<BigInt>),
+    Float(f64),
     Boolean(bool),
-    String(String),
+    // Heap values are shared so cloning an Object never deep copies
+    String(Rc<str>),
+    Array(Rc<Vec<Object>>),
+    Hash(Rc<Hash>),
+    Range {
+        start: isize,
+        end: isize,
+        inclusive: bool,
+    },
+    // Only used by the VM to hold the state of a `for` loop on its stack
+    Iterator {
+        iter: Box<Iter>,
+        pairs: bool,
+    },
     Return(Box<Object>),
+    // Signals from `break` and `continue` passed up to the enclosing loop
+    Break,
+    Continue,
+    // `name` is the name a `let` or `const` declared the function with,
+    // which its body can use to call itself
     Function {
-        parameters: Vec<String>,
-        body: Vec<Statement>,
+        name: Option<Rc<str>>,
+        parameters: Rc<Vec<Parameter>>,
+        body: Rc<Vec<Statement>>,
+    },
+    // Only used by the VM, a function starting at the decoded instruction
+    // `entry`. The signature is its parameters as source.
+    CompiledFunction {
+        entry: usize,
+        signature: Rc<str>,
+    },
+    // A call in tail position of a function body, passed up for the
+    // enclosing call to make so recursion doesn't grow the stack
+    TailCall {
+        name: Option<Rc<str>>,
+        parameters: Rc<Vec<Parameter>>,
+        body: Rc<Vec<Statement>>,
+        args: Vec<Object>,
     },
 }
 
-pub fn eval_block(ast: Vec<Statement>, env: &mut Environment) -> Object {
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Object::Null => write!(f, "null"),
+            Object::Int(val) => write!(f, "{}", val),
+            Object::BigInt(val) => write!(f, "{}", val),
+            // Keep the `.` so floats are distinguishable from ints, `2.0`
+            Object::Float(val) => write!(f, "{:?}", val),
+            Object::Boolean(val) => write!(f, "{}", val),
+            Object::String(val) => write!(f, "{}", val),
+            Object::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Object::Hash(hash) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in hash.entries().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Object::Range {
+                start,
+                end,
+                inclusive,
+            } => match inclusive {
+                true => write!(f, "{}..={}", start, end),
+                false => write!(f, "{}..{}", start, end),
+            },
+            Object::Iterator { .. } => write!(f, "iterator"),
+            Object::Return(val) => write!(f, "{}", val),
+            Object::Break => write!(f, "break"),
+            Object::Continue => write!(f, "continue"),
+            Object::Function { parameters, .. } => {
+                let parameters: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+                write!(f, "fn({})", parameters.join(", "))
+            }
+            Object::CompiledFunction { signature, .. } => write!(f, "fn({})", signature),
+            Object::TailCall { .. } => write!(f, "tail call"),
+        }
+    }
+}
+
+impl Object {
+    // How a value behaves as a condition, or as an operand of `!`, `&&`
+    // and `||`, in both engines. False, null, zero, the empty string and
+    // empty collections are falsy, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Object::Boolean(val) => *val,
+            Object::Null => false,
+            Object::Int(val) => *val != 0,
+            Object::Float(val) => *val != 0.0,
+            Object::String(val) => !val.is_empty(),
+            Object::Array(items) => !items.is_empty(),
+            Object::Hash(hash) => !hash.is_empty(),
+            Object::Range {
+                start,
+                end,
+                inclusive,
+            } => start < end || (*inclusive && start == end),
+            // Big ints are never zero
+            _ => true,
+        }
+    }
+
+    // Null is only equal to null, but can be compared with anything
+    pub fn equals(&self, other: &Object) -> Option<bool> {
+        match (self, other) {
+            (Object::Boolean(left), Object::Boolean(right)) => Some(left == right),
+            (Object::Null, other) | (other, Object::Null) => Some(*other == Object::Null),
+            (left, right) => Some(Numbers::new(left, right)?.compare() == Ordering::Equal),
+        }
+    }
+
+    // Whether the value matches a literal pattern. Unlike `==` any two
+    // values can be compared, values of different types just don't match.
+    pub fn matches(&self, literal: &Object) -> bool {
+        match (self, literal) {
+            (Object::String(left), Object::String(right)) => left == right,
+            (left, right) => left.equals(right).unwrap_or(false),
+        }
+    }
+
+    // Whether the value is a hash with the key, for hash patterns
+    pub fn has_key(&self, key: &Object) -> bool {
+        match self {
+            Object::Hash(hash) => matches!(hash.get(key), Ok(Some(_))),
+            _ => false,
+        }
+    }
+
+    // Look up `index` in an array or hash, missing items are Null
+    pub fn index(&self, index: &Object) -> Result<Object, String> {
+        match (self, index) {
+            (Object::Array(items), Object::Int(i)) => {
+                let item = match usize::try_from(*i) {
+                    Ok(i) => items.get(i).cloned(),
+                    Err(_) => None,
+                };
+                Ok(item.unwrap_or(Object::Null))
+            }
+            (Object::Hash(hash), key) => Ok(hash.get(key)?.cloned().unwrap_or(Object::Null)),
+            _ => Err(format!("Can't index {} with {}", self, index)),
+        }
+    }
+
+    // Store `value` at `index` of an array or hash. Containers are copied
+    // on write if anything else still shares them.
+    pub fn set_index(&mut self, index: Object, value: Object) -> Result<(), String> {
+        match (self, index) {
+            (Object::Array(items), Object::Int(i)) => {
+                let items = Rc::make_mut(items);
+                let len = items.len();
+                match usize::try_from(i).ok().and_then(|i| items.get_mut(i)) {
+                    Some(item) => *item = value,
+                    None => return Err(format!("Index {} out of range for length {}", i, len)),
+                }
+                Ok(())
+            }
+            (Object::Hash(hash), key) => Rc::make_mut(hash).insert(key, value),
+            (object, index) => Err(format!("Can't assign to {} with {}", object, index)),
+        }
+    }
+}
+
+// The value of an operand, or return early with a `break`, `continue` or
+// `return` from a block inside it so the signal reaches the enclosing loop
+// or function instead of being used as a value
+macro_rules! operand {
+    ($value:expr) => {
+        match $value {
+            signal @ Object::Return(_) | signal @ Object::Break | signal @ Object::Continue => {
+                return signal
+            }
+            value => value,
+        }
+    };
+}
+
+pub fn eval_block(ast: &[Statement], env: &mut Environment) -> Object {
+    eval_statements(ast, env, false)
+}
+
+// A block in tail position of a function body gives a TailCall for a call
+// that is its last expression or is returned
+fn eval_statements(ast: &[Statement], env: &mut Environment, tail: bool) -> Object {
     let mut result = Object::Null;
+    let count = ast.len();
 
-    for statement in ast {
+    for (i, statement) in ast.iter().enumerate() {
         match statement {
             Statement::ExpressionStatement(exp) => {
-                result = eval_expression(exp, env);
+                result = match tail && i + 1 == count {
+                    true => eval_tail(exp, env),
+                    false => eval_expression(exp, env),
+                };
+            }
+            Statement::Return { value } if tail => {
+                result = match eval_tail(value, env) {
+                    call @ Object::TailCall { .. } => call,
+                    value => Object::Return(Box::new(value)),
+                };
             }
             Statement::Return { value } => {
-                result = Object::Return(Box::new(eval_expression(value, env)));
+                result = Object::Return(Box::new(operand!(eval_expression(value, env))));
             }
             Statement::Let { name, value } => {
-                let new_value = eval_expression(value, env);
-                env.set(name, new_value.clone());
+                let new_value = operand!(eval_declared(name, value, env));
+                env.define(name.clone(), new_value.clone());
                 result = new_value
             }
+            Statement::Destructure { pattern, value } => {
+                let new_value = operand!(eval_expression(value, env));
+                if let Err(mismatch) = bind_pattern(pattern, &new_value, env) {
+                    panic!("{}", mismatch);
+                }
+                result = new_value
+            }
+            Statement::Const { name, value } => {
+                let new_value = operand!(eval_declared(name, value, env));
+                env.define_const(name.clone(), new_value.clone(), statement.to_string());
+                result = new_value
+            }
+            Statement::While { condition, body } => {
+                result = eval_while(condition, body, env);
+            }
+            Statement::For {
+                variables,
+                iterable,
+                body,
+            } => {
+                result = eval_for(variables, iterable, body, env);
+            }
+            Statement::Break => result = Object::Break,
+            Statement::Continue => result = Object::Continue,
+            Statement::Comment(_) | Statement::TrailingComment(_) => (),
         }
 
         match result {
-            Object::Return(_) => break,
+            Object::Return(_) | Object::Break | Object::Continue | Object::TailCall { .. } => break,
             _ => (),
         }
     }
@@ -44,126 +282,624 @@ pub fn eval_block(ast: Vec<Statement>, env: &mut Environment) -> Object {
 }
 
 pub fn eval(ast: Vec<Statement>, env: &mut Environment) -> Object {
-    let result = eval_block(ast, env);
+    if let Err(err) = Constants::new(env.constants().clone()).check(&ast) {
+        panic!("{}", err);
+    }
+    returned(eval_block(&ast, env))
+}
 
+fn returned(result: Object) -> Object {
     // If final result is a Return unwrap it...
     match result {
         Object::Return(val) => *val,
+        Object::Break => panic!("'break' outside of a loop"),
+        Object::Continue => panic!("'continue' outside of a loop"),
         _ => result,
     }
 }
 
-fn eval_expression(exp: Expression, env: &mut Environment) -> Object {
-    match exp {
-        Expression::Int(val) => Object::Int(val),
-        Expression::Boolean(val) => Object::Boolean(val),
-        Expression::String(val) => Object::String(val),
-        Expression::Prefix { prefix, value } => match prefix {
-            Prefix::BANG => match eval_expression(*value, env) {
-                Object::Boolean(val) => Object::Boolean(!val),
-                _ => panic!("'!' operator only valid for boolean types"),
+// Loops run in the current environment so a `let` in the body updates the
+// values seen by the condition
+fn eval_while(condition: &Expression, body: &[Statement], env: &mut Environment) -> Object {
+    loop {
+        if !operand!(eval_expression(condition, env)).is_truthy() {
+            return Object::Null;
+        }
+
+        match eval_block(body, env) {
+            Object::Break => return Object::Null,
+            result @ Object::Return(_) => return result,
+            _ => (),
+        }
+    }
+}
+
+fn eval_for(
+    variables: &[String],
+    iterable: &Expression,
+    body: &[Statement],
+    env: &mut Environment,
+) -> Object {
+    let iterable = operand!(eval_expression(iterable, env));
+    let mut iter = Iter::new(&iterable).unwrap_or_else(|err| panic!("{}", err));
+
+    loop {
+        match variables {
+            [item] => match iter.next_item() {
+                Some(value) => env.define(item.clone(), value),
+                None => return Object::Null,
             },
-            Prefix::MINUS => match eval_expression(*value, env) {
-                Object::Int(val) => Object::Int(-val),
-                _ => panic!("'-' operator only valid for integer types"),
+            [key, value] => match iter.next_pair() {
+                Some((k, v)) => {
+                    env.define(key.clone(), k);
+                    env.define(value.clone(), v);
+                }
+                None => return Object::Null,
             },
+            _ => panic!("For loops take one or two variables"),
+        }
+
+        match eval_block(body, env) {
+            Object::Break => return Object::Null,
+            result @ Object::Return(_) => return result,
+            _ => (),
+        }
+    }
+}
+
+fn eval_expression(exp: &Expression, env: &mut Environment) -> Object {
+    match exp {
+        Expression::Int(val) => Object::Int(*val),
+        Expression::BigInt(val) => Object::BigInt(val.clone().into()),
+        Expression::Float(val) => Object::Float(*val),
+        Expression::Spelled { value, .. } => eval_expression(value, env),
+        Expression::Boolean(val) => Object::Boolean(*val),
+        Expression::Null => Object::Null,
+        Expression::String(val) => Object::String(val.as_str().into()),
+        Expression::Prefix { prefix, value } => match prefix {
+            Prefix::BANG => Object::Boolean(!operand!(eval_expression(value, env)).is_truthy()),
+            Prefix::MINUS => negate(&operand!(eval_expression(value, env)))
+                .unwrap_or_else(|| panic!("'-' operator only valid for numbers")),
         },
         Expression::Infix { left, op, right } => match op {
-            // Integer operations
-            Operator::PLUS => match (eval_expression(*left, env), eval_expression(*right, env)) {
-                (Object::Int(l_val), Object::Int(r_val)) => Object::Int(l_val + r_val),
-                _ => panic!("'+' operator only valid on integers"),
-            },
-            Operator::MINUS => match (eval_expression(*left, env), eval_expression(*right, env)) {
-                (Object::Int(l_val), Object::Int(r_val)) => Object::Int(l_val - r_val),
-                _ => panic!("'-' operator only valid on integers"),
-            },
-            Operator::MULTIPLY => match (eval_expression(*left, env), eval_expression(*right, env))
-            {
-                (Object::Int(l_val), Object::Int(r_val)) => Object::Int(l_val * r_val),
-                _ => panic!("'*' operator only valid on integers"),
-            },
-            Operator::DIVIDE => match (eval_expression(*left, env), eval_expression(*right, env)) {
-                (Object::Int(l_val), Object::Int(r_val)) => Object::Int(l_val / r_val),
-                _ => panic!("'/' operator only valid on integers"),
-            },
+            // Arithmetic operations, on ints or floats
+            Operator::PLUS
+            | Operator::MINUS
+            | Operator::MULTIPLY
+            | Operator::DIVIDE
+            | Operator::MODULO
+            | Operator::POWER => {
+                let left = operand!(eval_expression(left, env));
+                let right = operand!(eval_expression(right, env));
+                let numbers = numbers(&left, op, &right);
+                let result = match op {
+                    Operator::PLUS => numbers.add(),
+                    Operator::MINUS => numbers.subtract(),
+                    Operator::MULTIPLY => numbers.multiply(),
+                    Operator::DIVIDE => numbers.divide(),
+                    Operator::MODULO => numbers.modulo(),
+                    _ => numbers.power(),
+                };
+                result.unwrap_or_else(|err| panic!("{}", err))
+            }
             // Comparison operations
-            Operator::EQUAL => match (eval_expression(*left, env), eval_expression(*right, env)) {
-                (Object::Int(l_val), Object::Int(r_val)) => Object::Boolean(l_val == r_val),
-                (Object::Boolean(l_val), Object::Boolean(r_val)) => Object::Boolean(l_val == r_val),
-                _ => panic!("Problem in Infix equality check"),
-            },
-            Operator::NEQUAL => match (eval_expression(*left, env), eval_expression(*right, env)) {
-                (Object::Int(l_val), Object::Int(r_val)) => Object::Boolean(l_val != r_val),
-                (Object::Boolean(l_val), Object::Boolean(r_val)) => Object::Boolean(l_val != r_val),
-                _ => panic!("Problem in Infix not equality check"),
-            },
-            Operator::GREATER => {
-                match (eval_expression(*left, env), eval_expression(*right, env)) {
-                    (Object::Int(l_val), Object::Int(r_val)) => Object::Boolean(l_val > r_val),
-                    _ => panic!("Problem in Infix greater than check"),
+            Operator::EQUAL | Operator::NEQUAL => {
+                let left = operand!(eval_expression(left, env));
+                let right = operand!(eval_expression(right, env));
+                let equal = left.equals(&right).unwrap_or_else(|| {
+                    panic!("'{}' operator only valid on numbers, booleans or null", op)
+                });
+                Object::Boolean(equal == (*op == Operator::EQUAL))
+            }
+            Operator::GREATER | Operator::LESS | Operator::GREATEREQUAL | Operator::LESSEQUAL => {
+                let left = operand!(eval_expression(left, env));
+                let right = operand!(eval_expression(right, env));
+                let ordering = numbers(&left, op, &right).compare();
+                Object::Boolean(match op {
+                    Operator::GREATER => ordering == Ordering::Greater,
+                    Operator::LESS => ordering == Ordering::Less,
+                    Operator::GREATEREQUAL => ordering != Ordering::Less,
+                    _ => ordering != Ordering::Greater,
+                })
+            }
+            // Logical operations only evaluate the right side if the left
+            // side doesn't decide the result
+            Operator::AND | Operator::OR => {
+                let l_val = operand!(eval_expression(left, env)).is_truthy();
+                if l_val == (*op == Operator::OR) {
+                    Object::Boolean(l_val)
+                } else {
+                    Object::Boolean(operand!(eval_expression(right, env)).is_truthy())
+                }
+            }
+            Operator::RANGE | Operator::RANGEINCLUSIVE => {
+                let left = operand!(eval_expression(left, env));
+                let right = operand!(eval_expression(right, env));
+                match (left, right) {
+                    (Object::Int(start), Object::Int(end)) => Object::Range {
+                        start,
+                        end,
+                        inclusive: *op == Operator::RANGEINCLUSIVE,
+                    },
+                    _ => panic!("Ranges are only valid on integers"),
                 }
             }
-            Operator::LESS => match (eval_expression(*left, env), eval_expression(*right, env)) {
-                (Object::Int(l_val), Object::Int(r_val)) => Object::Boolean(l_val < r_val),
-                _ => panic!("Problem in Infix less than check"),
-            },
         },
         Expression::If {
             condition,
             consequence,
             alternative,
-        } => match eval_expression(*condition, env) {
-            Object::Boolean(true) => eval_block(consequence, env),
-            Object::Boolean(false) => {
-                if alternative.len() == 0 {
-                    return Object::Null;
-                }
-                eval_block(alternative, env)
-            }
-            _ => panic!("If conditional must evaluate to a boolean"),
+        } => eval_if(condition, consequence, alternative, env, false),
+        Expression::Match { value, arms } => {
+            let value = operand!(eval_expression(value, env));
+            eval_match(&value, arms, env, false)
+        }
+        Expression::Ident(name) => env.get(name).expect("Attempted to access invalid variable"),
+        Expression::FnLiteral { parameters, body } => Object::Function {
+            name: None,
+            parameters: Rc::new(parameters.clone()),
+            body: Rc::new(body.clone()),
         },
-        Expression::Ident(name) => env
-            .get(&name)
-            .expect("Attempted to access invalid variable"),
-        Expression::FnLiteral { parameters, body } => Object::Function { parameters, body },
         Expression::FnCall { function, args } => {
-            let (parameters, body) = match *function {
-                Expression::Ident(name) => match env.get(&name) {
-                    Some(Object::Function { parameters, body }) => (parameters, body),
-                    None => {
-                        // Handle built in functions
-                        let args = args
-                            .into_iter()
-                            .map(|exp| eval_expression(exp, env))
-                            .collect();
-                        return eval_builtin(&name, args);
-                    }
-                    _ => panic!("Attempted to call non-function"),
+            call(eval_call(function, args, env), env.depth() + 1)
+        }
+        Expression::Array(items) => {
+            let mut values = Vec::with_capacity(items.len());
+            for item in items {
+                values.push(operand!(eval_expression(item, env)));
+            }
+            Object::Array(Rc::new(values))
+        }
+        Expression::Hash(pairs) => {
+            let mut hash = Hash::new();
+            for (key, value) in pairs {
+                let key = operand!(eval_expression(key, env));
+                let value = operand!(eval_expression(value, env));
+                hash.insert(key, value)
+                    .unwrap_or_else(|err| panic!("{}", err));
+            }
+            Object::Hash(Rc::new(hash))
+        }
+        Expression::Index { left, index } => {
+            let left = operand!(eval_expression(left, env));
+            let index = operand!(eval_expression(index, env));
+            left.index(&index).unwrap_or_else(|err| panic!("{}", err))
+        }
+        Expression::Assign { target, op, value } => {
+            // `x op= v` is the same as `x = x op v`
+            let new_value = match op.clone() {
+                Some(op) => Expression::Infix {
+                    left: target.clone(),
+                    op,
+                    right: value.clone(),
                 },
-                Expression::FnLiteral { parameters, body } => (parameters, body),
-                _ => panic!("Error calling function"),
+                None => (**value).clone(),
             };
+            let new_value = operand!(eval_expression(&new_value, env));
+            match eval_assign(target, new_value.clone(), env) {
+                Ok(Some(signal)) => signal,
+                Ok(None) => new_value,
+                Err(err) => panic!("{} in `{}`", err, exp),
+            }
+        }
+    }
+}
+
+// The value of an expression ending a block in tail position of a function
+// body, where a call is passed up as a TailCall instead of being made
+fn eval_tail(exp: &Expression, env: &mut Environment) -> Object {
+    match exp {
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => eval_if(condition, consequence, alternative, env, true),
+        Expression::Match { value, arms } => {
+            let value = operand!(eval_expression(value, env));
+            eval_match(&value, arms, env, true)
+        }
+        Expression::FnCall { function, args } => eval_call(function, args, env),
+        exp => eval_expression(exp, env),
+    }
+}
+
+fn eval_if(
+    condition: &Expression,
+    consequence: &[Statement],
+    alternative: &[Statement],
+    env: &mut Environment,
+    tail: bool,
+) -> Object {
+    match operand!(eval_expression(condition, env)).is_truthy() {
+        true => eval_statements(consequence, env, tail),
+        // An empty alternative gives null
+        false => eval_statements(alternative, env, tail),
+    }
+}
+
+// The value of a `let` or `const`, a function literal is named after the
+// variable so it can call itself
+fn eval_declared(name: &str, value: &Expression, env: &mut Environment) -> Object {
+    match value {
+        Expression::FnLiteral { parameters, body } => Object::Function {
+            name: Some(name.into()),
+            parameters: Rc::new(parameters.clone()),
+            body: Rc::new(body.clone()),
+        },
+        value => eval_expression(value, env),
+    }
+}
+
+// Evaluate the function and arguments of a call. Built in functions are
+// called straight away, other calls are returned as a TailCall to make.
+// A name that isn't a variable must be a built in function.
+fn eval_call(function: &Expression, args: &[Expression], env: &mut Environment) -> Object {
+    if let Expression::Ident(name) = function {
+        if env.get(name).is_none() {
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args {
+                values.push(operand!(eval_expression(arg, env)));
+            }
+            return builtin(name, &values).unwrap_or_else(|err| panic!("{}", err));
+        }
+    }
+
+    let (name, parameters, body) = match operand!(eval_expression(function, env)) {
+        Object::Function {
+            name,
+            parameters,
+            body,
+        } => (name, parameters, body),
+        _ => panic!("Attempted to call non-function"),
+    };
+
+    let mut values = Vec::with_capacity(args.len());
+    for arg in args {
+        values.push(operand!(eval_expression(arg, env)));
+    }
+    Object::TailCall {
+        name,
+        parameters,
+        body,
+        args: values,
+    }
+}
+
+// Make a call `depth` calls deep, then each call its body ends in, in a
+// loop rather than recursively so tail recursion runs in constant stack
+// space. Other calls deeper than MAX_FRAMES stop, like the VM.
+fn call(mut result: Object, depth: usize) -> Object {
+    if depth > MAX_FRAMES {
+        panic!("Stack overflow");
+    }
+
+    while let Object::TailCall {
+        name,
+        parameters,
+        body,
+        args,
+    } = result
+    {
+        let mut func_env = Environment::nested(depth);
+        // A function can call itself by the name it was declared with,
+        // unless a parameter shadows the name
+        if let Some(name) = name {
+            let function = Object::Function {
+                name: Some(name.clone()),
+                parameters: parameters.clone(),
+                body: body.clone(),
+            };
+            func_env.define(name.to_string(), function);
+        }
+        if let Err(err) = bind_parameters(&parameters, args, &mut func_env) {
+            panic!("{}", err);
+        }
+
+        // Each call evaluates its body deeper in Rust's stack, which grows
+        // as needed so every call up to MAX_FRAMES fits
+        result = stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || {
+            eval_statements(&body, &mut func_env, true)
+        });
+    }
+    returned(result)
+}
+
+// Evaluate the body of the first arm whose pattern matches and whose guard
+// is truthy, null if none do. An arm's names are only kept if it is taken,
+// until then the variables they replace are kept aside and put back if the
+// pattern or guard fails.
+fn eval_match(value: &Object, arms: &[MatchArm], env: &mut Environment, tail: bool) -> Object {
+    for arm in arms {
+        let mut names = vec![];
+        bindings(&arm.pattern, &mut names);
+        let saved: Vec<_> = names.iter().map(|name| (*name, env.get(name))).collect();
+        let restore = |env: &mut Environment| {
+            for (name, value) in saved.iter().rev() {
+                env.restore(name.to_string(), value.clone());
+            }
+        };
+
+        if bind_pattern(&arm.pattern, value, env).is_err() {
+            restore(env);
+            continue;
+        }
+        if let Some(guard) = &arm.guard {
+            match eval_expression(guard, env) {
+                signal @ Object::Return(_) | signal @ Object::Break | signal @ Object::Continue => {
+                    restore(env);
+                    return signal;
+                }
+                guard if !guard.is_truthy() => {
+                    restore(env);
+                    continue;
+                }
+                _ => (),
+            }
+        }
+        return eval_statements(&arm.body, env, tail);
+    }
+    Object::Null
+}
 
-            assert_eq!(parameters.len(), args.len());
+// The names a pattern binds
+fn bindings<'a>(pattern: &'a Pattern, names: &mut Vec<&'a str>) {
+    match pattern {
+        Pattern::Binding(name) => names.push(name),
+        Pattern::Array(patterns) => patterns.iter().for_each(|item| bindings(item, names)),
+        Pattern::Hash(pairs) => pairs.iter().for_each(|(_, item)| bindings(item, names)),
+        Pattern::Wildcard | Pattern::Literal(_) => (),
+    }
+}
+
+// Bind the arguments of a call to the parameters in a new function scope.
+// Missing arguments take their defaults, evaluated after the parameters
+// before them are bound, and a rest parameter takes any left over.
+fn bind_parameters(
+    parameters: &[Parameter],
+    args: Vec<Object>,
+    env: &mut Environment,
+) -> Result<(), String> {
+    check_arity(parameters, args.len())?;
 
-            let mut func_env = Environment::new();
-            for (paramater, arg) in parameters.into_iter().zip(args.into_iter()) {
-                func_env.set(paramater, eval_expression(arg, env));
+    let mut args = args.into_iter();
+    for parameter in parameters {
+        match parameter {
+            Parameter::Single { pattern, default } => {
+                let arg = match (args.next(), default) {
+                    (Some(arg), _) => arg,
+                    (None, Some(default)) => eval_expression(default, env),
+                    (None, None) => unreachable!("Arity checked above"),
+                };
+                bind_pattern(pattern, &arg, env).map_err(|mismatch| mismatch.to_string())?;
+            }
+            Parameter::Rest(name) => {
+                let rest = Object::Array(Rc::new(args.by_ref().collect()));
+                env.define(name.clone(), rest);
             }
+        }
+    }
+    Ok(())
+}
+
+// Calls must pass an argument for every parameter without a default, and
+// can only pass more than there are parameters to a rest parameter
+fn check_arity(parameters: &[Parameter], count: usize) -> Result<(), String> {
+    let (required, max, rest) = arity(parameters);
+    arity_error(required, max, rest, &signature(parameters), count)
+}
+
+// How many arguments a function needs, how many it takes before any rest
+// parameter, and whether it has a rest parameter
+pub fn arity(parameters: &[Parameter]) -> (usize, usize, bool) {
+    let required = parameters
+        .iter()
+        .filter(|parameter| matches!(parameter, Parameter::Single { default: None, .. }))
+        .count();
+    let rest = matches!(parameters.last(), Some(Parameter::Rest(_)));
+    (required, parameters.len() - rest as usize, rest)
+}
+
+pub fn signature(parameters: &[Parameter]) -> String {
+    let parameters: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+    parameters.join(", ")
+}
+
+// Shared with the VM, which only has the function's signature
+pub fn arity_error(
+    required: usize,
+    max: usize,
+    rest: bool,
+    signature: &str,
+    count: usize,
+) -> Result<(), String> {
+    if count >= required && (rest || count <= max) {
+        return Ok(());
+    }
+
+    let expected = match rest {
+        true => format!("at least {}", required),
+        false if required == max => required.to_string(),
+        false => format!("{} to {}", required, max),
+    };
+    let noun = match (rest && required == 1) || (!rest && max == 1) {
+        true => "argument",
+        false => "arguments",
+    };
+    Err(format!(
+        "fn({}) takes {} {} but was called with {}",
+        signature, expected, noun, count
+    ))
+}
+
+// The part of a pattern a value didn't fit. A failed match just moves on
+// to the next arm, so the message is only built for destructuring errors.
+struct Mismatch<'a> {
+    pattern: &'a Pattern,
+    value: Object,
+    key: Option<&'a Expression>,
+}
+
+impl fmt::Display for Mismatch<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let detail = mismatch_detail(self.pattern, self.key);
+        write!(f, "{}", mismatch_error(&self.value, &detail))
+    }
+}
+
+// What the part of a pattern a value didn't fit expected, `key` is the hash
+// key that was missing if there was one. The VM keeps this as a constant
+// so both engines give the same error.
+pub fn mismatch_detail(pattern: &Pattern, key: Option<&Expression>) -> String {
+    match (pattern, key) {
+        (Pattern::Array(items), _) => format!(
+            "into `{}`, expected an array of length {}",
+            pattern,
+            items.len()
+        ),
+        (Pattern::Hash(_), Some(key)) => {
+            format!("into `{}`, expected a hash with the key {}", pattern, key)
+        }
+        (Pattern::Hash(_), None) => format!("into `{}`, expected a hash", pattern),
+        (pattern, _) => format!("into `{}`, expected {}", pattern, pattern),
+    }
+}
+
+pub fn mismatch_error(value: &Object, detail: &str) -> String {
+    format!("Can't destructure {} {}", value, detail)
+}
+
+// Check a value against a pattern, binding names as it goes. Parts are
+// checked left to right and stop at the first that doesn't match, so a
+// pattern that fails can leave some of its names bound, the same as the VM
+// does when destructuring.
+fn bind_pattern<'a>(
+    pattern: &'a Pattern,
+    value: &Object,
+    env: &mut Environment,
+) -> Result<(), Mismatch<'a>> {
+    let mismatch = |key| Mismatch {
+        pattern,
+        value: value.clone(),
+        key,
+    };
+
+    match pattern {
+        Pattern::Wildcard => Ok(()),
+        Pattern::Binding(name) => {
+            env.define(name.clone(), value.clone());
+            Ok(())
+        }
+        Pattern::Literal(literal) => match value.matches(&eval_expression(literal, env)) {
+            true => Ok(()),
+            false => Err(mismatch(None)),
+        },
+        Pattern::Array(patterns) => match value {
+            Object::Array(items) if items.len() == patterns.len() => {
+                for (pattern, item) in patterns.iter().zip(items.iter()) {
+                    bind_pattern(pattern, item, env)?;
+                }
+                Ok(())
+            }
+            _ => Err(mismatch(None)),
+        },
+        Pattern::Hash(pairs) => {
+            if !matches!(value, Object::Hash(_)) {
+                return Err(mismatch(None));
+            }
+            for (key, pattern) in pairs {
+                let key_value = eval_expression(key, env);
+                if !value.has_key(&key_value) {
+                    return Err(mismatch(Some(key)));
+                }
+                let item = value.index(&key_value).expect("Key checked above");
+                bind_pattern(pattern, &item, env)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// The operands of an operator that only works on numbers
+fn numbers(left: &Object, op: &Operator, right: &Object) -> Numbers {
+    Numbers::new(left, right).unwrap_or_else(|| panic!("'{}' operator only valid on numbers", op))
+}
 
-            eval(body, &mut func_env)
+// Store a value in an assignment target. Gives the signal if an index in the
+// target is cut short by `break`, `continue` or `return`.
+fn eval_assign(
+    target: &Expression,
+    value: Object,
+    env: &mut Environment,
+) -> Result<Option<Object>, String> {
+    let signal =
+        |value: &Object| matches!(value, Object::Return(_) | Object::Break | Object::Continue);
+
+    match target {
+        Expression::Ident(name) => env.set(name, value).map(|_| None),
+        Expression::Index { left, index } => {
+            let index = eval_expression(index, env);
+            if signal(&index) {
+                return Ok(Some(index));
+            }
+            match &**left {
+                // Update the variable in place so its container is only
+                // copied if something else shares it
+                Expression::Ident(name) => env.get_mut(name)?.set_index(index, value).map(|_| None),
+                left => {
+                    let mut container = eval_expression(left, env);
+                    if signal(&container) {
+                        return Ok(Some(container));
+                    }
+                    container.set_index(index, value)?;
+                    eval_assign(left, container, env)
+                }
+            }
         }
+        _ => Err("Invalid assignment target".to_owned()),
     }
 }
 
-fn eval_builtin(fn_name: &str, args: Vec<Object>) -> Object {
-    match (fn_name, args.as_slice()) {
+// The functions `builtin` provides, in the order the VM numbers them
+pub const BUILTINS: &[&str] = &[
+    "len",
+    "lowerCase",
+    "upperCase",
+    "floor",
+    "ceil",
+    "round",
+    "sqrt",
+];
+
+// Call a built in function, shared by both engines. Arguments a built in
+// function doesn't handle give null.
+pub fn builtin(fn_name: &str, args: &[Object]) -> Result<Object, String> {
+    if !BUILTINS.contains(&fn_name) {
+        return Err(format!("Unknown function {}", fn_name));
+    }
+
+    let result = match (fn_name, args) {
         ("len", [Object::String(val)]) => Object::Int(val.len() as isize),
-        ("lowerCase", [Object::String(val)]) => Object::String(val.to_lowercase()),
-        ("upperCase", [Object::String(val)]) => Object::String(val.to_uppercase()),
+        ("len", [Object::Array(items)]) => Object::Int(items.len() as isize),
+        ("len", [Object::Hash(hash)]) => Object::Int(hash.len() as isize),
+        ("lowerCase", [Object::String(val)]) => Object::String(val.to_lowercase().into()),
+        ("upperCase", [Object::String(val)]) => Object::String(val.to_uppercase().into()),
+        // Rounding gives an int, so the result can be used as an index
+        ("floor", [Object::Int(val)])
+        | ("ceil", [Object::Int(val)])
+        | ("round", [Object::Int(val)]) => Object::Int(*val),
+        ("floor", [Object::BigInt(val)])
+        | ("ceil", [Object::BigInt(val)])
+        | ("round", [Object::BigInt(val)]) => Object::BigInt(val.clone()),
+        ("floor", [Object::Float(val)]) => float_to_int(val.floor()),
+        ("ceil", [Object::Float(val)]) => float_to_int(val.ceil()),
+        ("round", [Object::Float(val)]) => float_to_int(val.round()),
+        ("sqrt", [Object::Int(val)]) => square_root(*val as f64)?,
+        // Too large for a float is infinite, which square_root rejects
+        ("sqrt", [Object::BigInt(val)]) => square_root(val.to_f64().unwrap_or(f64::INFINITY))?,
+        ("sqrt", [Object::Float(val)]) => square_root(*val)?,
         _ => Object::Null,
-    }
+    };
+    Ok(result)
 }
 
 #[cfg(test)]
@@ -171,13 +907,14 @@ mod tests {
     use crate::{
         evaluator::{eval, Environment, Object},
         lexer::lexer,
-        parser::{parse, Expression, Statement},
+        parser::{parse, Expression, Parameter, Pattern, Statement},
     };
+    use std::rc::Rc;
 
     // Convenience function to lex, parse and eval an input
     fn evaluated(input: &str) -> Object {
-        let mut tokens = lexer(input.as_bytes());
-        let statements = parse(&mut tokens);
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
         let mut env = Environment::new();
         eval(statements, &mut env)
     }
@@ -193,7 +930,7 @@ mod tests {
         assert_eq!(expected, evaluated(input));
 
         let input = "'hello'";
-        let expected = Object::String("hello".to_owned());
+        let expected = Object::String("hello".into());
         assert_eq!(expected, evaluated(input));
     }
 
@@ -257,6 +994,201 @@ mod tests {
         let input = "3 < 4";
         let expected = Object::Boolean(true);
         assert_eq!(expected, evaluated(input));
+
+        let input = "4 <= 4";
+        let expected = Object::Boolean(true);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "3 >= 4";
+        let expected = Object::Boolean(false);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "-7 % 3";
+        let expected = Object::Int(-1);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "2 ** 3 ** 2";
+        let expected = Object::Int(512);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "-2 ** 2";
+        let expected = Object::Int(-4);
+        assert_eq!(expected, evaluated(input));
+    }
+
+    #[test]
+    fn test_floats() {
+        let tests = vec![
+            ("3.5", "3.5"),
+            ("1 + 0.5", "1.5"),
+            ("7 / 2", "3"),
+            ("7 / 2.0", "3.5"),
+            ("-2.5 * 2", "-5.0"),
+            ("7.5 % 2", "1.5"),
+            ("2 ** 0.5 == sqrt(2)", "true"),
+            ("1 == 1.0", "true"),
+            ("0.1 + 0.2 > 0.3", "true"),
+            ("1e3 >= 1000", "true"),
+            (
+                "[floor(-2.5), ceil(-2.5), round(-2.5), round(2.4), floor(3)]",
+                "[-3, -2, -3, 2, 3]",
+            ),
+            ("sqrt(16)", "4.0"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(expected, evaluated(input).to_string(), "{}", input);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Float overflow")]
+    fn test_float_overflow() {
+        evaluated("1e308 * 10");
+    }
+
+    #[test]
+    #[should_panic(expected = "Division by zero")]
+    fn test_float_division_by_zero() {
+        evaluated("1.5 / 0");
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't take the square root of -1.0")]
+    fn test_negative_square_root() {
+        evaluated("sqrt(-1)");
+    }
+
+    #[test]
+    fn test_destructure() {
+        let tests = vec![
+            ("let [a, b] = [1, 2]; a + b", "3"),
+            ("let [a, [_, b]] = [1, [2, 3]]; [a, b]", "[1, 3]"),
+            (
+                "let {'x': x, 'y': [y]} = {'y': [2], 'x': 1, 'z': 0}; [x, y]",
+                "[1, 2]",
+            ),
+            ("let [0, n] = [0, 5]; n", "5"),
+            ("if (true) { let [a] = [4]; }", "[4]"),
+            ("let first = fn([h, _]) { h }; first([7, 8])", "7"),
+            ("let f = fn({'k': v}, n) { v * n }; f({'k': 3}, 2)", "6"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(expected, evaluated(input).to_string(), "{}", input);
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Can't destructure [1, 2, 3] into `[a, b]`, expected an array of length 2"
+    )]
+    fn test_destructure_length() {
+        evaluated("let [a, b] = [1, 2, 3];");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Can't destructure {y: 1} into `{'x': x}`, expected a hash with the key 'x'"
+    )]
+    fn test_destructure_missing_key() {
+        evaluated("let {'x': x} = {'y': 1};");
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't destructure 5 into `[h, t]`, expected an array of length 2")]
+    fn test_destructure_parameter() {
+        evaluated("let f = fn([h, t]) { h }; f(5)");
+    }
+
+    #[test]
+    #[should_panic(expected = "Division by zero")]
+    fn test_division_by_zero() {
+        evaluated("let x = 0; 1 / x");
+    }
+
+    #[test]
+    fn test_big_ints() {
+        let tests = vec![
+            ("let x = 9223372036854775807; x + 1", "9223372036854775808"),
+            ("-(-9223372036854775807 - 1)", "9223372036854775808"),
+            ("-9223372036854775808", "-9223372036854775808"),
+            ("99999999999999999999 % 7 + 3 ** 40", "12157665459056928802"),
+            ("(2 ** 64 - 1) / 2 ** 32", "4294967295"),
+            (
+                "[2 ** 64 > 2 ** 63, 2 ** 64 == 18446744073709551616, 2 ** 64 * 0.5]",
+                "[true, true, 9.223372036854776e18]",
+            ),
+            ("{18446744073709551616: 'big'}[2 ** 64]", "big"),
+            ("floor(1e19)", "10000000000000000000"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(expected, evaluated(input).to_string(), "{}", input);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Modulo by zero")]
+    fn test_modulo_by_zero() {
+        evaluated("1 % 0");
+    }
+
+    #[test]
+    #[should_panic(expected = "Integer too large")]
+    fn test_power_overflow() {
+        evaluated("3 ** 10000000");
+    }
+
+    #[test]
+    fn test_null() {
+        let tests = vec![
+            ("null", "null"),
+            ("if(null) { 1 } else { 2 }", "2"),
+            ("if(false) { 1 }", "null"),
+            ("[!null, null && true, null || true]", "[true, false, true]"),
+            (
+                "[null == null, null == false, 1 != null, 'a' == null]",
+                "[true, false, true, false]",
+            ),
+            ("let x = null; while(x) { x = false; }; x", "null"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(expected, evaluated(input).to_string(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_match() {
+        let tests = vec![
+            ("match (2) { 1 => 'one', 2 => 'two', _ => 'many' }", "two"),
+            ("match (7) { 1 => 'one', n => n * 2 }", "14"),
+            ("match (1.0) { 1 => 'int', _ => 'other' }", "int"),
+            ("match (-3) { -3 => true, _ => false }", "true"),
+            ("match ('a') { 1 => 1, null => 2, 'a' => 3 }", "3"),
+            ("match (true) { 1 => 1, true => 2 }", "2"),
+            ("match ([1, 2]) { [a] => a, [a, b] => a + b }", "3"),
+            (
+                "match ([1, [2, 3]]) { [_, [b, 4]] => b, [_, [b, c]] => c }",
+                "3",
+            ),
+            ("match ({'k': 5, 'x': 0}) { {'k': v} => v }", "5"),
+            ("match ({'x': 0}) { {'k': v} => v, {} => 'hash' }", "hash"),
+            ("match ([]) { {} => 'hash', [] => 'array' }", "array"),
+            (
+                "match (5) { n if n > 9 => 'big', n if n > 3 => 'mid', _ => 'small' }",
+                "mid",
+            ),
+            ("match (5) { 1 => 1 }", "null"),
+            ("match (5) { _ => {} }", "null"),
+            ("match (5) { n => { let m = n + 1; m } }", "6"),
+            ("let x = 0; match (1) { 1 => { x = 9; } }; x", "9"),
+            ("match (match (1) { 1 => [2] }) { [n] => n }", "2"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(expected, evaluated(input).to_string(), "{}", input);
+        }
+
+        let input =
+            "let f = fn(x) { match (x) { 0 => { return 'zero'; } }; 'other' }; [f(0), f(1)]";
+        assert_eq!("[zero, other]", evaluated(input).to_string());
     }
 
     #[test]
@@ -309,19 +1241,30 @@ mod tests {
     fn test_fn_literals() {
         let input = "fn() { return 1; }";
         let expected = Object::Function {
-            parameters: vec![],
-            body: vec![Statement::Return {
+            name: None,
+            parameters: Rc::new(vec![]),
+            body: Rc::new(vec![Statement::Return {
                 value: Expression::Int(1),
-            }],
+            }]),
         };
         assert_eq!(expected, evaluated(input));
 
         let input = "fn(a, b) { return true; }";
         let expected = Object::Function {
-            parameters: vec!["a".to_owned(), "b".to_owned()],
-            body: vec![Statement::Return {
+            name: None,
+            parameters: Rc::new(vec![
+                Parameter::Single {
+                    pattern: Pattern::Binding("a".to_owned()),
+                    default: None,
+                },
+                Parameter::Single {
+                    pattern: Pattern::Binding("b".to_owned()),
+                    default: None,
+                },
+            ]),
+            body: Rc::new(vec![Statement::Return {
                 value: Expression::Boolean(true),
-            }],
+            }]),
         };
         assert_eq!(expected, evaluated(input));
     }
@@ -337,6 +1280,113 @@ mod tests {
         assert_eq!(expected, evaluated(input));
     }
 
+    #[test]
+    fn test_parameters() {
+        let tests = vec![
+            (
+                "let f = fn(x, y = 2) { [x, y] }; [f(1), f(1, 3)]",
+                "[[1, 2], [1, 3]]",
+            ),
+            ("let f = fn(x, y = x * 10) { y }; f(4)", "40"),
+            (
+                "let f = fn(first, ...rest) { [first, rest] }; f(1)",
+                "[1, []]",
+            ),
+            ("let f = fn(first, ...rest) { rest }; f(1, 2, 3)", "[2, 3]"),
+            (
+                "let f = fn(a = 1, ...rest) { [a, len(rest)] }; [f(), f(5, 6)]",
+                "[[1, 0], [5, 1]]",
+            ),
+            (
+                "let f = fn([a, b] = [1, 2]) { a + b }; [f(), f([3, 4])]",
+                "[3, 7]",
+            ),
+            ("let f = fn(x, ...rest) { x }; f", "fn(x, ...rest)"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(expected, evaluated(input).to_string(), "{}", input);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "fn(x, y) takes 2 arguments but was called with 1")]
+    fn test_too_few_arguments() {
+        evaluated("let f = fn(x, y) { x }; f(1)");
+    }
+
+    #[test]
+    #[should_panic(expected = "fn(x, y = 2) takes 1 to 2 arguments but was called with 3")]
+    fn test_too_many_arguments() {
+        evaluated("let f = fn(x, y = 2) { x }; f(1, 2, 3)");
+    }
+
+    #[test]
+    #[should_panic(expected = "fn(x, ...rest) takes at least 1 argument but was called with 0")]
+    fn test_missing_before_rest() {
+        evaluated("let f = fn(x, ...rest) { x }; f()");
+    }
+
+    #[test]
+    fn test_recursion() {
+        let tests = vec![
+            (
+                "let fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1) } }; fact(10)",
+                "3628800",
+            ),
+            // Calls in tail position don't grow the stack
+            (
+                "let loop = fn(n) { if (n == 0) { 0 } else { loop(n - 1) } }; loop(1000000)",
+                "0",
+            ),
+            (
+                "let sum = fn(n, total) { if (n == 0) { return total; } return sum(n - 1, total + n); }; sum(100000, 0)",
+                "5000050000",
+            ),
+            (
+                "let count = fn(n, total = 0) { match (n) { 0 => total, _ => count(n - 1, total + 2) } }; count(100000)",
+                "200000",
+            ),
+            ("let f = fn(f) { f }; f(1)", "1"),
+            // The name comes from the declaration rather than the call
+            (
+                "let f = fn(n) { if (n == 0) { 'done' } else { f(n - 1) } }; let g = f; g(3)",
+                "done",
+            ),
+            ("const f = fn(n) { if (n > 0) { f(n - 1) } else { n } }; f(3)", "0"),
+            // As deep as other calls can go
+            (
+                "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(1023)",
+                "1023",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(expected, evaluated(input).to_string(), "{}", input);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Stack overflow")]
+    fn test_deep_recursion() {
+        evaluated("let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100000)");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown function odd")]
+    fn test_mutual_recursion() {
+        // A function body can only see its parameters and its own name
+        evaluated(
+            "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+            let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+            even(4)",
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown function nope")]
+    fn test_unknown_function() {
+        evaluated("nope(1)");
+    }
+
     #[test]
     fn test_builtins() {
         let input = "let str = 'hello'; len(str)";
@@ -344,11 +1394,233 @@ mod tests {
         assert_eq!(expected, evaluated(input));
 
         let input = "let str = 'hello'; upperCase(str)";
-        let expected = Object::String("HELLO".to_owned());
+        let expected = Object::String("HELLO".into());
         assert_eq!(expected, evaluated(input));
 
         let input = "let str = 'hElLO'; lowerCase(str)";
-        let expected = Object::String("hello".to_owned());
+        let expected = Object::String("hello".into());
+        assert_eq!(expected, evaluated(input));
+    }
+
+    #[test]
+    fn test_logical() {
+        let tests = vec![
+            ("true && true", true),
+            ("true && false", false),
+            ("false && true", false),
+            ("false || false", false),
+            ("false || true", true),
+            ("true || false", true),
+            ("1 < 2 && 2 < 3 || false", true),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(Object::Boolean(expected), evaluated(input), "{}", input);
+        }
+
+        // The right side only runs when it decides the result
+        let input = "let x = 0; false && (x = 1) == 1; true || (x = 2) == 2; x";
+        assert_eq!(Object::Int(0), evaluated(input));
+        assert_eq!(Object::Boolean(false), evaluated("false && 1"));
+    }
+
+    #[test]
+    fn test_truthiness() {
+        let falsy = [
+            "false", "null", "0", "0.0", "''", "[]", "{}", "(1..1)", "(2..=1)",
+        ];
+        let truthy = [
+            "true", "-1", "0.5", "'0'", "[0]", "{0: 0}", "(0..=0)", "2 ** 64", "fn() {}",
+        ];
+        for input in &falsy {
+            let input = format!("[!{0}, if ({0}) {{ 1 }} else {{ 2 }}]", input);
+            assert_eq!("[true, 2]", evaluated(&input).to_string(), "{}", input);
+        }
+        for input in &truthy {
+            let input = format!("[!{0}, if ({0}) {{ 1 }} else {{ 2 }}]", input);
+            assert_eq!("[false, 1]", evaluated(&input).to_string(), "{}", input);
+        }
+
+        let input = "let n = 3; let total = 0; while (n) { total += n; n -= 1; }; total";
+        assert_eq!(Object::Int(6), evaluated(input));
+        assert_eq!(Object::Boolean(true), evaluated("true && 1"));
+        assert_eq!(Object::Boolean(false), evaluated("'' || []"));
+    }
+
+    #[test]
+    fn test_while() {
+        let input = "let i = 0; while (i < 5) { let i = i + 1; }; i";
+        let expected = Object::Int(5);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "let i = 0; let sum = 0;
+            while (true) {
+                let i = i + 1;
+                if (i == 3) { continue }
+                if (i > 5) { break }
+                let sum = sum + i;
+            };
+            sum";
+        let expected = Object::Int(12);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "let f = fn() { let i = 0; while (true) { let i = i + 1; if (i == 4) { return i; } } }; f()";
+        let expected = Object::Int(4);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "while (false) { 1 }";
+        let expected = Object::Null;
+        assert_eq!(expected, evaluated(input));
+
+        // Signals inside an operand leave the whole expression
+        let input = "let t = 0;
+            for (x in 1..=3) {
+                for (y in 1..=3) {
+                    t += [x, match (y) { 2 => { continue; }, 3 => { break; }, _ => y }][1];
+                }
+            };
+            t";
+        let expected = Object::Int(3);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "let f = fn(x) { 1 + if (x) { return 10; } else { 2 } }; [f(true), f(false)]";
+        assert_eq!("[10, 3]", evaluated(input).to_string());
+
+        let input = "let a = [0]; for (x in 1..3) { a[if (x == 2) { break; } else { 0 }] = x; }; a";
+        assert_eq!("[1]", evaluated(input).to_string());
+    }
+
+    #[test]
+    fn test_collections() {
+        let input = "let a = [1, 2 + 3, 'x']; a[1]";
+        let expected = Object::Int(5);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "[1][-1]";
+        let expected = Object::Null;
+        assert_eq!(expected, evaluated(input));
+
+        let input = "let h = {'a': 1, true: 2}; h['a']";
+        let expected = Object::Int(1);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "len([1, 2]) + len({1: 1})";
+        let expected = Object::Int(3);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "[1, {2: 'a'}, 0..=2]";
+        assert_eq!("[1, {2: a}, 0..=2]", evaluated(input).to_string());
+    }
+
+    #[test]
+    fn test_for() {
+        let input = "let sum = 0; for (x in [1, 2, 3, 4]) { let sum = sum * 10 + x; }; sum";
+        let expected = Object::Int(1234);
         assert_eq!(expected, evaluated(input));
+
+        let input = "let sum = 0;
+            for (k, v in {1: 10, 2: 20, 3: 30}) {
+                if (k == 3) { break }
+                let sum = sum + k * v;
+            };
+            sum";
+        let expected = Object::Int(50);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "let n = 0; for (x in 0..10000) { if (x == 3) { continue } let n = n + 1; }; n";
+        let expected = Object::Int(9999);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "let out = ''; for (c in 'abc') { let out = c; }; out";
+        let expected = Object::String("c".into());
+        assert_eq!(expected, evaluated(input));
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't iterate over true")]
+    fn test_for_non_iterable() {
+        evaluated("for (x in true) { x }");
+    }
+
+    #[test]
+    fn test_assign() {
+        let input = "let x = 1; x = x + 1; x += 10; x *= 2; x -= 4; x /= 5; x";
+        let expected = Object::Int(4);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "let a = 1; let b = 2; a = b = 7; a + b";
+        let expected = Object::Int(14);
+        assert_eq!(expected, evaluated(input));
+
+        let input = "let a = [1, [2, 3]]; let b = a; a[1][0] = 9; a[0] += 1; [a, b]";
+        assert_eq!("[[2, [9, 3]], [1, [2, 3]]]", evaluated(input).to_string());
+
+        let input = "let h = {}; h['a'] = 1; h['b'] = h['a'] + 1; h['a'] = 3; h";
+        assert_eq!("{a: 3, b: 2}", evaluated(input).to_string());
+
+        let input = "let i = 0; while (i < 10) { i += 1 }; i";
+        let expected = Object::Int(10);
+        assert_eq!(expected, evaluated(input));
+    }
+
+    #[test]
+    #[should_panic(expected = "Assignment to undeclared variable y")]
+    fn test_assign_undeclared() {
+        evaluated("y = 1");
+    }
+
+    #[test]
+    fn test_const() {
+        let input = "const a = [1, 2]; let b = a; b[0] = 3; [a, b]";
+        assert_eq!("[[1, 2], [3, 2]]", evaluated(input).to_string());
+
+        // Each time a loop body runs its constant is declared again
+        let input = "let total = 0; for (i in 0..3) { const step = i * 2; total += step; }; total";
+        assert_eq!(Object::Int(6), evaluated(input));
+    }
+
+    #[test]
+    #[should_panic(expected = "Redeclaration of constant a declared by `const a = 1;`")]
+    fn test_redeclare_const() {
+        evaluated("const a = 1; match (5) { a => a }; a");
+    }
+
+    // Like the compiler, the check happens before anything runs
+    #[test]
+    #[should_panic(expected = "Assignment to constant a declared by `const a = 1;` in `a = 2`")]
+    fn test_assign_const_not_run() {
+        evaluated("const a = 1; if (false) { a = 2; }; a");
+    }
+
+    #[test]
+    #[should_panic(expected = "Assignment to constant x declared by `const x = 1;` in `x += 2`")]
+    fn test_assign_const() {
+        evaluated("const x = 1; x += 2");
+    }
+
+    #[test]
+    #[should_panic(expected = "Assignment to constant h declared by `const h = {};` in `h[1] = 2`")]
+    fn test_assign_const_index() {
+        evaluated("const h = {}; h[1] = 2");
+    }
+
+    #[test]
+    #[should_panic(expected = "Index 2 out of range for length 2")]
+    fn test_assign_out_of_range() {
+        evaluated("let a = [1, 2]; a[2] = 3");
+    }
+
+    #[test]
+    #[should_panic(expected = "'break' outside of a loop")]
+    fn test_break_outside_loop() {
+        evaluated("if (true) { break; }");
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("-3", Object::Int(-3).to_string());
+        assert_eq!("true", Object::Boolean(true).to_string());
+        assert_eq!("hello", Object::String("hello".into()).to_string());
+        assert_eq!("null", Object::Null.to_string());
+        assert_eq!("fn(a, b)", evaluated("fn(a, b) { a }").to_string());
     }
 }
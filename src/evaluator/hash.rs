@@ -0,0 +1,108 @@
+use crate::evaluator::Object;
+use num_bigint::BigInt;
+use std::{collections::HashMap, rc::Rc};
+
+// Only these Objects can be used as hash keys
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+enum HashKey {
+    Int(isize),
+    BigInt(Rc<BigInt>),
+    Boolean(bool),
+    String(Rc<str>),
+}
+
+impl HashKey {
+    fn new(object: &Object) -> Result<HashKey, String> {
+        match object {
+            Object::Int(val) => Ok(HashKey::Int(*val)),
+            Object::BigInt(val) => Ok(HashKey::BigInt(val.clone())),
+            Object::Boolean(val) => Ok(HashKey::Boolean(*val)),
+            Object::String(val) => Ok(HashKey::String(val.clone())),
+            _ => Err(format!("Unusable as hash key: {}", object)),
+        }
+    }
+}
+
+// A hash that keeps its entries in insertion order, so iterating over it
+// gives the same order in every run and in both engines
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Hash {
+    entries: Vec<(Object, Object)>,
+    // Index of each key's entry
+    keys: HashMap<HashKey, usize>,
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Hash::default()
+    }
+
+    // Insert a value, replacing the value of an existing key in place
+    pub fn insert(&mut self, key: Object, value: Object) -> Result<(), String> {
+        let hash_key = HashKey::new(&key)?;
+        match self.keys.get(&hash_key) {
+            Some(&index) => self.entries[index].1 = value,
+            None => {
+                self.keys.insert(hash_key, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &Object) -> Result<Option<&Object>, String> {
+        let hash_key = HashKey::new(key)?;
+        Ok(self
+            .keys
+            .get(&hash_key)
+            .map(|&index| &self.entries[index].1))
+    }
+
+    pub fn entries(&self) -> &[(Object, Object)] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{Hash, Object};
+
+    #[test]
+    fn test_insertion_order() {
+        let mut hash = Hash::new();
+        hash.insert(Object::String("b".into()), Object::Int(1))
+            .unwrap();
+        hash.insert(Object::Int(2), Object::Int(2)).unwrap();
+        hash.insert(Object::Boolean(true), Object::Int(3)).unwrap();
+        // Replacing a value keeps its position
+        hash.insert(Object::String("b".into()), Object::Int(4))
+            .unwrap();
+
+        let expected = vec![
+            (Object::String("b".into()), Object::Int(4)),
+            (Object::Int(2), Object::Int(2)),
+            (Object::Boolean(true), Object::Int(3)),
+        ];
+        assert_eq!(expected.as_slice(), hash.entries());
+        assert_eq!(3, hash.len());
+    }
+
+    #[test]
+    fn test_get() {
+        let mut hash = Hash::new();
+        hash.insert(Object::Int(1), Object::Int(10)).unwrap();
+
+        assert_eq!(Ok(Some(&Object::Int(10))), hash.get(&Object::Int(1)));
+        assert_eq!(Ok(None), hash.get(&Object::Int(2)));
+        assert!(hash.get(&Object::Null).is_err());
+        assert!(hash.insert(Object::Null, Object::Int(1)).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::evaluator::{Hash, Object};
+use std::rc::Rc;
+
+// Iteration over the values a `for` loop accepts. Iterators share the
+// iterable rather than copying it, and ranges are never materialised.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Iter {
+    Array {
+        items: Rc<Vec<Object>>,
+        index: usize,
+    },
+    Hash {
+        hash: Rc<Hash>,
+        index: usize,
+    },
+    String {
+        value: Rc<str>,
+        // Byte offset of the next character
+        offset: usize,
+        index: usize,
+    },
+    Range {
+        // None once the range is finished, so `..=isize::MAX` can't overflow
+        next: Option<isize>,
+        end: isize,
+        inclusive: bool,
+        index: usize,
+    },
+}
+
+impl Iter {
+    pub fn new(iterable: &Object) -> Result<Iter, String> {
+        match iterable {
+            Object::Array(items) => Ok(Iter::Array {
+                items: items.clone(),
+                index: 0,
+            }),
+            Object::Hash(hash) => Ok(Iter::Hash {
+                hash: hash.clone(),
+                index: 0,
+            }),
+            Object::String(value) => Ok(Iter::String {
+                value: value.clone(),
+                offset: 0,
+                index: 0,
+            }),
+            Object::Range {
+                start,
+                end,
+                inclusive,
+            } => Ok(Iter::Range {
+                next: Some(*start),
+                end: *end,
+                inclusive: *inclusive,
+                index: 0,
+            }),
+            _ => Err(format!("Can't iterate over {}", iterable)),
+        }
+    }
+
+    // The next item for a loop with one variable, the key for hashes and
+    // the value for everything else
+    pub fn next_item(&mut self) -> Option<Object> {
+        let is_hash = matches!(self, Iter::Hash { .. });
+        self.next_pair()
+            .map(|(key, value)| if is_hash { key } else { value })
+    }
+
+    // The next key and value for a loop with two variables. Arrays, strings
+    // and ranges use the position of the item as its key.
+    pub fn next_pair(&mut self) -> Option<(Object, Object)> {
+        match self {
+            Iter::Array { items, index } => {
+                let item = items.get(*index)?.clone();
+                *index += 1;
+                Some((Object::Int(*index as isize - 1), item))
+            }
+            Iter::Hash { hash, index } => {
+                let (key, value) = hash.entries().get(*index)?.clone();
+                *index += 1;
+                Some((key, value))
+            }
+            Iter::String {
+                value,
+                offset,
+                index,
+            } => {
+                let ch = value[*offset..].chars().next()?;
+                *offset += ch.len_utf8();
+                *index += 1;
+                Some((
+                    Object::Int(*index as isize - 1),
+                    Object::String(ch.to_string().into()),
+                ))
+            }
+            Iter::Range {
+                next,
+                end,
+                inclusive,
+                index,
+            } => {
+                let current = (*next)?;
+                if current > *end || (current == *end && !*inclusive) {
+                    *next = None;
+                    return None;
+                }
+                *next = current.checked_add(1);
+                *index += 1;
+                Some((Object::Int(*index as isize - 1), Object::Int(current)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{Hash, Iter, Object};
+    use std::rc::Rc;
+
+    fn items(iterable: Object) -> Vec<Object> {
+        let mut iter = Iter::new(&iterable).unwrap();
+        std::iter::from_fn(|| iter.next_item()).collect()
+    }
+
+    fn pairs(iterable: Object) -> Vec<(Object, Object)> {
+        let mut iter = Iter::new(&iterable).unwrap();
+        std::iter::from_fn(|| iter.next_pair()).collect()
+    }
+
+    fn range(start: isize, end: isize, inclusive: bool) -> Object {
+        Object::Range {
+            start,
+            end,
+            inclusive,
+        }
+    }
+
+    #[test]
+    fn test_arrays() {
+        let array = Object::Array(Rc::new(vec![Object::Int(5), Object::Boolean(true)]));
+        let expected = vec![Object::Int(5), Object::Boolean(true)];
+        assert_eq!(expected, items(array.clone()));
+
+        let expected = vec![
+            (Object::Int(0), Object::Int(5)),
+            (Object::Int(1), Object::Boolean(true)),
+        ];
+        assert_eq!(expected, pairs(array));
+    }
+
+    #[test]
+    fn test_hashes() {
+        let mut hash = Hash::new();
+        hash.insert(Object::String("a".into()), Object::Int(1))
+            .unwrap();
+        hash.insert(Object::Int(2), Object::Int(3)).unwrap();
+        let hash = Object::Hash(Rc::new(hash));
+
+        let expected = vec![Object::String("a".into()), Object::Int(2)];
+        assert_eq!(expected, items(hash.clone()));
+
+        let expected = vec![
+            (Object::String("a".into()), Object::Int(1)),
+            (Object::Int(2), Object::Int(3)),
+        ];
+        assert_eq!(expected, pairs(hash));
+    }
+
+    #[test]
+    fn test_strings() {
+        let expected = vec![
+            (Object::Int(0), Object::String("h".into())),
+            (Object::Int(1), Object::String("é".into())),
+            (Object::Int(2), Object::String("!".into())),
+        ];
+        assert_eq!(expected, pairs(Object::String("hé!".into())));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let expected = vec![Object::Int(0), Object::Int(1), Object::Int(2)];
+        assert_eq!(expected, items(range(0, 3, false)));
+        assert_eq!(expected, items(range(0, 2, true)));
+
+        assert_eq!(Vec::<Object>::new(), items(range(3, 3, false)));
+        assert_eq!(Vec::<Object>::new(), items(range(3, 1, true)));
+
+        let expected = vec![Object::Int(isize::MAX)];
+        assert_eq!(expected, items(range(isize::MAX, isize::MAX, true)));
+
+        let expected = vec![(Object::Int(0), Object::Int(-2))];
+        assert_eq!(expected, pairs(range(-2, -1, false)));
+    }
+
+    #[test]
+    fn test_not_iterable() {
+        assert!(Iter::new(&Object::Int(1)).is_err());
+    }
+}
@@ -1,4 +1,14 @@
+mod arithmetic;
+mod constants;
 mod environment;
 mod evaluator;
+mod hash;
+mod iter;
+pub use arithmetic::{float_to_int, negate, square_root, Numbers};
 pub use environment::Environment;
-pub use evaluator::{eval, Object};
+pub use evaluator::{
+    arity, arity_error, builtin, eval, mismatch_detail, mismatch_error, signature, Object,
+    BUILTINS, MAX_FRAMES,
+};
+pub use hash::Hash;
+pub use iter::Iter;
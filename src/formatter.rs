@@ -0,0 +1,114 @@
+use crate::{
+    lexer::lexer_with_comments,
+    parser::{parse, Indented},
+};
+
+// Format Monkey source into its canonical layout. Formatting the output
+// again gives back the same source.
+//
+// Comments are kept. One after code stays at the end of its line, one on a
+// line of its own stays on its own line. A comment part way through a
+// statement is moved to after that statement.
+pub fn format(source: &str) -> Result<String, String> {
+    let mut tokens = lexer_with_comments(source);
+    let ast = parse(&mut tokens)?;
+    Ok(Indented(&ast).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+
+    #[test]
+    fn test_format() {
+        let input = "let add=fn(a,b){return a+b;};add(1,2)";
+        let expected = "let add = fn(a, b) {\n    return a + b;\n};\nadd(1, 2);\n";
+        assert_eq!(Ok(expected.to_owned()), format(input));
+
+        let input = "if(x<1){if(y){1}else{2}}else{let z=(1+2)*3;z}";
+        let expected = "\
+if (x < 1) {
+    if (y) {
+        1;
+    } else {
+        2;
+    };
+} else {
+    let z = (1 + 2) * 3;
+    z;
+};
+";
+        assert_eq!(Ok(expected.to_owned()), format(input));
+
+        let input = "map(fn(x){x*2},fn(){})";
+        let expected = "map(fn(x) {\n    x * 2;\n}, fn() {});\n";
+        assert_eq!(Ok(expected.to_owned()), format(input));
+
+        let input = "let y=match(x){-1=>'neg',[a,b] if a>b=>{a},{'k':v}=>{v;v+1},_=>{}};";
+        let expected = "\
+let y = match (x) {
+    -1 => 'neg',
+    [a, b] if a > b => a,
+    {'k': v} => {
+        v;
+        v + 1;
+    },
+    _ => {},
+};
+";
+        assert_eq!(Ok(expected.to_owned()), format(input));
+
+        assert_eq!(Ok(String::new()), format("  \n"));
+
+        // Numbers are written the way they were
+        let input = "let mask=0xFF&&0b1010;[1_000_000,1.50,2e3]";
+        let expected = "let mask = 0xFF && 0b1010;\n[1_000_000, 1.50, 2e3];\n";
+        assert_eq!(Ok(expected.to_owned()), format(input));
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "\
+// Adds two numbers
+let add = fn(a, b) { /* the sum
+   of both */ a + b // trailing
+};
+let x = add(1, /* two */ 2);
+if (x) { // empty
+} // done";
+        let expected = "\
+// Adds two numbers
+let add = fn(a, b) { /* the sum
+   of both */
+    a + b; // trailing
+};
+let x = add(1, 2); /* two */
+if (x) { // empty
+}; // done
+";
+        let formatted = format(input).unwrap();
+        assert_eq!(expected, formatted);
+        assert_eq!(Ok(formatted.clone()), format(&formatted));
+    }
+
+    #[test]
+    fn test_trailing_comments() {
+        let input = "let x = 1; // trailing\n// own line\nlet y = [1, // one\n2];\n";
+        let expected = "let x = 1; // trailing\n// own line\nlet y = [1, 2]; // one\n";
+        let formatted = format(input).unwrap();
+        assert_eq!(expected, formatted);
+        assert_eq!(Ok(formatted.clone()), format(&formatted));
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let input = "let f = fn(a) { if (a) { return -(a - 1); } }; f(2) == 1 != !true";
+        let formatted = format(input).unwrap();
+        assert_eq!(Ok(formatted.clone()), format(&formatted));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(format("let = 1;").is_err());
+    }
+}
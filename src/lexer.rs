@@ -1,4 +1,5 @@
-use std::collections::VecDeque;
+use num_bigint::BigInt;
+use std::{collections::VecDeque, convert::TryFrom};
 
 #[rustfmt::skip]
 #[derive(Debug, PartialEq)]
@@ -7,7 +8,12 @@ pub enum Token {
     EOF,                // End of file
     IDENT(String),      // Identifier
     INT(isize),         // Integer
+    BIGINT(BigInt),     // Integer too large for an isize
+    FLOAT(f64),         // Float
+    SPELLED(Box<Token>, String), // Number written other than as its value prints, like '0xff'
     STRING(String),     // String
+    COMMENT(String),    // '// ...' or '/* ... */', only kept when asked for
+    TRAILINGCOMMENT(String), // A COMMENT after code on the same line
 
     //Operators
     ASSIGN,             // '='
@@ -15,11 +21,25 @@ pub enum Token {
     MINUS,              // '-'
     LT,                 // '<' Less than
     GT,                 // '>' Greater than
+    LTEQ,               // '<=' Less than or equal to
+    GTEQ,               // '>=' Greater than or equal to
     EQ,                 // '==' Equal to
     NEQ,                // '!=' Not equal to
+    AND,                // '&&'
+    OR,                 // '||'
     BANG,               // '!'
     ASTERISK,           // '*'
     SLASH,              // '/'
+    PERCENT,            // '%'
+    POWER,              // '**'
+    PLUSEQ,             // '+='
+    MINUSEQ,            // '-='
+    ASTERISKEQ,         // '*='
+    SLASHEQ,            // '/='
+    DOTDOT,             // '..' Range
+    DOTDOTEQ,           // '..=' Inclusive range
+    ARROW,              // '=>' Match arm
+    ELLIPSIS,           // '...' Rest parameter
 
     // Delimiters
     COMMA,              // ','
@@ -28,18 +48,43 @@ pub enum Token {
     RPAREN,             // ')'
     LBRACE,             // '{'
     RBRACE,             // '}'
+    LBRACKET,           // '['
+    RBRACKET,           // ']'
+    COLON,              // ':'
 
     // Keywords
     FN,                 // Function
     LET,
+    CONST,
     IF,
     ELSE,
     RETURN,
     TRUE,
     FALSE,
+    NULL,
+    WHILE,
+    BREAK,
+    CONTINUE,
+    FOR,
+    IN,
+    MATCH,
 }
 
-pub fn lexer(input: &[u8]) -> VecDeque<Token> {
+pub fn lexer(input: &str) -> VecDeque<Token> {
+    lex(input, false)
+}
+
+// Lex the input keeping each comment as a COMMENT Token, for tools like the
+// formatter that need to write them back out
+pub fn lexer_with_comments(input: &str) -> VecDeque<Token> {
+    lex(input, true)
+}
+
+fn lex(input: &str, keep_comments: bool) -> VecDeque<Token> {
+    // Positions count chars rather than bytes, so multibyte UTF-8 is never
+    // split
+    let input: Vec<char> = input.chars().collect();
+    let input = input.as_slice();
     let mut pos = 0;
     let mut tokens = VecDeque::new();
 
@@ -56,44 +101,131 @@ pub fn lexer(input: &[u8]) -> VecDeque<Token> {
             }
             ch if is_digit(ch) => {
                 let (new_pos, token) = read_digits(pos, input);
-                tokens.push_back(token);
+                tokens.push_back(spelled(token, &input[pos..=new_pos]));
                 pos = new_pos;
             }
-            b'{' => tokens.push_back(Token::LBRACE),
-            b'}' => tokens.push_back(Token::RBRACE),
-            b'(' => tokens.push_back(Token::LPAREN),
-            b')' => tokens.push_back(Token::RPAREN),
-            b';' => tokens.push_back(Token::SEMICOLON),
-            b',' => tokens.push_back(Token::COMMA),
-            b'+' => tokens.push_back(Token::PLUS),
-            b'-' => tokens.push_back(Token::MINUS),
-            b'=' => match peek_next_char(pos, input) {
-                b'=' => {
+            '{' => tokens.push_back(Token::LBRACE),
+            '}' => tokens.push_back(Token::RBRACE),
+            '(' => tokens.push_back(Token::LPAREN),
+            ')' => tokens.push_back(Token::RPAREN),
+            ';' => tokens.push_back(Token::SEMICOLON),
+            ',' => tokens.push_back(Token::COMMA),
+            '[' => tokens.push_back(Token::LBRACKET),
+            ']' => tokens.push_back(Token::RBRACKET),
+            ':' => tokens.push_back(Token::COLON),
+            '.' => match (peek_next_char(pos, input), peek_next_char(pos + 1, input)) {
+                ('.', '=') => {
+                    tokens.push_back(Token::DOTDOTEQ);
+                    pos += 2;
+                }
+                ('.', '.') => {
+                    tokens.push_back(Token::ELLIPSIS);
+                    pos += 2;
+                }
+                ('.', _) => {
+                    tokens.push_back(Token::DOTDOT);
+                    pos += 1;
+                }
+                _ => tokens.push_back(Token::ILLEGAL),
+            },
+            '+' => match peek_next_char(pos, input) {
+                '=' => {
+                    tokens.push_back(Token::PLUSEQ);
+                    pos += 1;
+                }
+                _ => tokens.push_back(Token::PLUS),
+            },
+            '-' => match peek_next_char(pos, input) {
+                '=' => {
+                    tokens.push_back(Token::MINUSEQ);
+                    pos += 1;
+                }
+                _ => tokens.push_back(Token::MINUS),
+            },
+            '=' => match peek_next_char(pos, input) {
+                '=' => {
                     tokens.push_back(Token::EQ);
                     pos += 1;
                 }
-                0 => tokens.push_back(Token::EOF),
+                '>' => {
+                    tokens.push_back(Token::ARROW);
+                    pos += 1;
+                }
                 _ => tokens.push_back(Token::ASSIGN),
             },
-            b'!' => match peek_next_char(pos, input) {
-                b'=' => {
+            '!' => match peek_next_char(pos, input) {
+                '=' => {
                     tokens.push_back(Token::NEQ);
                     pos += 1;
                 }
-                0 => tokens.push_back(Token::EOF),
                 _ => tokens.push_back(Token::BANG),
             },
-            b'>' => tokens.push_back(Token::GT),
-            b'<' => tokens.push_back(Token::LT),
-            b'*' => tokens.push_back(Token::ASTERISK),
-            b'/' => tokens.push_back(Token::SLASH),
-            39 => {
-                // 39 is the ascii int for the ' character
+            '&' => match peek_next_char(pos, input) {
+                '&' => {
+                    tokens.push_back(Token::AND);
+                    pos += 1;
+                }
+                _ => tokens.push_back(Token::ILLEGAL),
+            },
+            '|' => match peek_next_char(pos, input) {
+                '|' => {
+                    tokens.push_back(Token::OR);
+                    pos += 1;
+                }
+                _ => tokens.push_back(Token::ILLEGAL),
+            },
+            '>' => match peek_next_char(pos, input) {
+                '=' => {
+                    tokens.push_back(Token::GTEQ);
+                    pos += 1;
+                }
+                _ => tokens.push_back(Token::GT),
+            },
+            '<' => match peek_next_char(pos, input) {
+                '=' => {
+                    tokens.push_back(Token::LTEQ);
+                    pos += 1;
+                }
+                _ => tokens.push_back(Token::LT),
+            },
+            '%' => tokens.push_back(Token::PERCENT),
+            '*' => match peek_next_char(pos, input) {
+                '=' => {
+                    tokens.push_back(Token::ASTERISKEQ);
+                    pos += 1;
+                }
+                '*' => {
+                    tokens.push_back(Token::POWER);
+                    pos += 1;
+                }
+                _ => tokens.push_back(Token::ASTERISK),
+            },
+            '/' => match peek_next_char(pos, input) {
+                '/' | '*' => {
+                    let (new_pos, token) = match read_comment(pos, input) {
+                        (new_pos, Token::COMMENT(text)) if follows_code(pos, input) => {
+                            (new_pos, Token::TRAILINGCOMMENT(text))
+                        }
+                        read => read,
+                    };
+                    // An unterminated comment is still reported
+                    if keep_comments || token == Token::ILLEGAL {
+                        tokens.push_back(token);
+                    }
+                    pos = new_pos;
+                }
+                '=' => {
+                    tokens.push_back(Token::SLASHEQ);
+                    pos += 1;
+                }
+                _ => tokens.push_back(Token::SLASH),
+            },
+            '\'' => {
                 let (new_pos, token) = read_string(pos, input);
                 tokens.push_back(token);
                 pos = new_pos;
             }
-            b' ' | b'\n' | b'\r' | b'\t' => (), // Ignore whitespace
+            ' ' | '\n' | '\r' | '\t' => (), // Ignore whitespace
             _ => tokens.push_back(Token::ILLEGAL),
         }
         pos += 1;
@@ -102,96 +234,261 @@ pub fn lexer(input: &[u8]) -> VecDeque<Token> {
     tokens
 }
 
-// Currently number digits can't be used in identifiers
-// May change this later
-#[rustfmt::skip]
-fn is_letter(ch: u8) -> bool {
-    b'a' <= ch && ch <= b'z' ||
-    b'A' <= ch && ch <= b'Z' ||
-    b'_' == ch
+// Identifiers start with a letter, from any script, or `_`. After that
+// they can also contain digits, like `user_2fa`.
+fn is_letter(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
 }
 
-fn is_digit(ch: u8) -> bool {
-    b'0' <= ch && ch <= b'9'
+fn is_identifier_char(ch: char) -> bool {
+    is_letter(ch) || ch.is_alphanumeric()
 }
 
-fn read_letters(start_pos: usize, input: &[u8]) -> (usize, Token) {
+fn is_digit(ch: char) -> bool {
+    ch.is_ascii_digit()
+}
+
+fn read_letters(start_pos: usize, input: &[char]) -> (usize, Token) {
     let mut pos = start_pos;
-    let mut identifier = Vec::new();
-    // Add next character to identifier until next character is not a letter
-    while pos < input.len() && is_letter(input[pos]) {
+    let mut identifier = String::new();
+    while pos < input.len() && is_identifier_char(input[pos]) {
         identifier.push(input[pos]);
         pos += 1;
     }
 
-    let token = is_keyword(&identifier);
+    let token = is_keyword(identifier);
     (pos - 1, token)
 }
 
-fn read_digits(start_pos: usize, input: &[u8]) -> (usize, Token) {
-    let mut pos = start_pos;
-    let mut identifier = Vec::new();
-    // Add next character to identifier until next character is not a letter
-    while pos < input.len() && is_digit(input[pos]) {
-        identifier.push(input[pos]);
-        pos += 1;
+fn read_digits(start_pos: usize, input: &[char]) -> (usize, Token) {
+    let radix = match input.get(start_pos..start_pos + 2) {
+        Some(['0', 'x']) => 16,
+        Some(['0', 'o']) => 8,
+        Some(['0', 'b']) => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        return read_radix_digits(start_pos, radix, input);
     }
 
-    let num: isize = String::from_utf8_lossy(&identifier)
-        .to_string()
-        .parse()
-        .unwrap();
+    // Underscores can separate digits, like `1_000_000`
+    let digits_from = |pos: usize| {
+        let mut end = pos;
+        while end < input.len() && (is_digit(input[end]) || input[end] == '_') {
+            end += 1;
+        }
+        end
+    };
+    let mut pos = digits_from(start_pos);
+    let mut is_float = false;
 
-    let token = Token::INT(num);
+    // A fraction needs a digit after the `.`, so `0..10` is still a range
+    if input.get(pos) == Some(&'.') && input.get(pos + 1).copied().is_some_and(is_digit) {
+        pos = digits_from(pos + 1);
+        is_float = true;
+    }
+    // An exponent is only read when digits follow it, like `1e-9`
+    if let Some('e') | Some('E') = input.get(pos) {
+        let sign = match input.get(pos + 1) {
+            Some('+') | Some('-') => 1,
+            _ => 0,
+        };
+        if input.get(pos + 1 + sign).copied().is_some_and(is_digit) {
+            pos = digits_from(pos + 1 + sign);
+            is_float = true;
+        }
+    }
+    // A number running into letters, like `12abc`, is one illegal token
+    if input.get(pos).copied().is_some_and(is_identifier_char) {
+        return (skip_word(pos, input) - 1, Token::ILLEGAL);
+    }
+    if !separated(&input[start_pos..pos], is_digit) {
+        return (pos - 1, Token::ILLEGAL);
+    }
+
+    let literal: String = input[start_pos..pos]
+        .iter()
+        .filter(|&&ch| ch != '_')
+        .collect();
+    // Float literals that overflow are illegal
+    let token = if is_float {
+        match literal.parse::<f64>() {
+            Ok(num) if num.is_finite() => Token::FLOAT(num),
+            _ => Token::ILLEGAL,
+        }
+    } else {
+        match literal.parse() {
+            Ok(num) => Token::INT(num),
+            Err(_) => Token::BIGINT(literal.parse().expect("Literal is only digits")),
+        }
+    };
     (pos - 1, token)
 }
 
-fn read_string(start_pos: usize, input: &[u8]) -> (usize, Token) {
+// Read an int written in hex `0xff`, octal `0o17` or binary `0b1010`. The
+// whole word is read so that a bad digit, as in `0b12`, makes it illegal.
+fn read_radix_digits(start_pos: usize, radix: u32, input: &[char]) -> (usize, Token) {
+    let pos = skip_word(start_pos + 2, input);
+    let digits: String = input[start_pos + 2..pos]
+        .iter()
+        .filter(|&&ch| ch != '_')
+        .collect();
+
+    let valid = !digits.is_empty()
+        && digits.chars().all(|ch| ch.is_digit(radix))
+        && separated(&input[start_pos + 2..pos], |ch| ch.is_digit(radix));
+    let token = match BigInt::parse_bytes(digits.as_bytes(), radix) {
+        Some(num) if valid => match isize::try_from(&num) {
+            Ok(num) => Token::INT(num),
+            Err(_) => Token::BIGINT(num),
+        },
+        _ => Token::ILLEGAL,
+    };
+    (pos - 1, token)
+}
+
+// Keep how a number was written if its value prints differently, so
+// `0xff`, `1_000` and `1.50` are printed back as they were written
+fn spelled(token: Token, literal: &[char]) -> Token {
+    let printed = match &token {
+        Token::INT(val) => val.to_string(),
+        Token::BIGINT(val) => val.to_string(),
+        Token::FLOAT(val) => format!("{:?}", val),
+        _ => return token,
+    };
+    let spelling: String = literal.iter().collect();
+    match spelling == printed {
+        true => token,
+        false => Token::SPELLED(Box::new(token), spelling),
+    }
+}
+
+// Underscores can only go between two digits, so `1_000` is fine but
+// `1_`, `1__0` and `0x_ff` are not
+fn separated(literal: &[char], is_digit: impl Fn(char) -> bool) -> bool {
+    literal.iter().enumerate().all(|(i, &ch)| {
+        ch != '_'
+            || (i > 0
+                && is_digit(literal[i - 1])
+                && literal.get(i + 1).copied().is_some_and(&is_digit))
+    })
+}
+
+// Find the end of a run of letters and digits
+fn skip_word(start_pos: usize, input: &[char]) -> usize {
+    let mut pos = start_pos;
+    while pos < input.len() && is_identifier_char(input[pos]) {
+        pos += 1;
+    }
+    pos
+}
+
+fn read_string(start_pos: usize, input: &[char]) -> (usize, Token) {
     let mut pos = start_pos + 1;
-    let mut value = Vec::new();
+    let mut value = String::new();
 
-    while input[pos] != 39 {
+    while pos < input.len() && input[pos] != '\'' {
         value.push(input[pos]);
         pos += 1;
     }
 
-    let token = Token::STRING(String::from_utf8_lossy(&value).to_string());
+    // Ran out of input before the closing quote
+    if pos >= input.len() {
+        return (pos, Token::ILLEGAL);
+    }
+
+    let token = Token::STRING(value);
     (pos, token)
 }
 
-fn is_keyword(chars: &[u8]) -> Token {
-    match chars {
-        [b'l', b'e', b't'] => Token::LET,
-        [b'f', b'n'] => Token::FN,
-        [b'i', b'f'] => Token::IF,
-        [b'e', b'l', b's', b'e'] => Token::ELSE,
-        [b'r', b'e', b't', b'u', b'r', b'n'] => Token::RETURN,
-        [b't', b'r', b'u', b'e'] => Token::TRUE,
-        [b'f', b'a', b'l', b's', b'e'] => Token::FALSE,
-        chars => Token::IDENT(String::from_utf8_lossy(chars).to_string()),
+// Read a line comment up to the end of the line, or a block comment up to
+// its matching '*/'. Block comments can be nested.
+fn read_comment(start_pos: usize, input: &[char]) -> (usize, Token) {
+    let mut pos = start_pos + 2;
+
+    if input[start_pos + 1] == '/' {
+        while pos < input.len() && input[pos] != '\n' {
+            pos += 1;
+        }
+        let text: String = input[start_pos..pos].iter().collect();
+        return (pos - 1, Token::COMMENT(text.trim_end().to_owned()));
+    }
+
+    let mut depth = 1;
+    while depth > 0 {
+        if pos + 1 >= input.len() {
+            // Ran out of input before the closing '*/'
+            return (input.len(), Token::ILLEGAL);
+        }
+
+        match (input[pos], input[pos + 1]) {
+            ('/', '*') => {
+                depth += 1;
+                pos += 2;
+            }
+            ('*', '/') => {
+                depth -= 1;
+                pos += 2;
+            }
+            _ => pos += 1,
+        }
+    }
+
+    (
+        pos - 1,
+        Token::COMMENT(input[start_pos..pos].iter().collect()),
+    )
+}
+
+// Whether anything comes before `pos` on its line
+fn follows_code(pos: usize, input: &[char]) -> bool {
+    input[..pos]
+        .iter()
+        .rev()
+        .find(|&&ch| !matches!(ch, ' ' | '\t' | '\r'))
+        .is_some_and(|&ch| ch != '\n')
+}
+
+fn is_keyword(identifier: String) -> Token {
+    match identifier.as_str() {
+        "let" => Token::LET,
+        "const" => Token::CONST,
+        "fn" => Token::FN,
+        "if" => Token::IF,
+        "else" => Token::ELSE,
+        "return" => Token::RETURN,
+        "true" => Token::TRUE,
+        "false" => Token::FALSE,
+        "null" => Token::NULL,
+        "while" => Token::WHILE,
+        "break" => Token::BREAK,
+        "continue" => Token::CONTINUE,
+        "for" => Token::FOR,
+        "in" => Token::IN,
+        "match" => Token::MATCH,
+        _ => Token::IDENT(identifier),
     }
 }
 
 // Peek at the next character in input
-fn peek_next_char(start_pos: usize, input: &[u8]) -> u8 {
-    if start_pos >= input.len() {
-        // We must be at the EOF, outer loop should catch this so it
-        // might not be needed here...
-        return 0;
+fn peek_next_char(start_pos: usize, input: &[char]) -> char {
+    if start_pos + 1 >= input.len() {
+        // There is no next character, we must be at the EOF
+        return '\0';
     }
     input[start_pos + 1]
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::lexer::{is_letter, lexer, Token};
+    use crate::lexer::{is_letter, lexer, lexer_with_comments, Token};
     use std::collections::VecDeque;
 
     #[test]
     fn lex_tokens() {
         let input = "{}();,";
 
-        let tokens = lexer(input.as_bytes());
+        let tokens = lexer(input);
         let expected = VecDeque::from(vec![
             Token::LBRACE,
             Token::RBRACE,
@@ -209,7 +506,7 @@ mod tests {
     fn lex_digits() {
         let input = "let x = 67;   hello    num3ber  9";
 
-        let tokens = lexer(input.as_bytes());
+        let tokens = lexer(input);
         let expected = VecDeque::from(vec![
             Token::LET,
             Token::IDENT("x".to_owned()),
@@ -217,9 +514,7 @@ mod tests {
             Token::INT(67),
             Token::SEMICOLON,
             Token::IDENT("hello".to_owned()),
-            Token::IDENT("num".to_owned()),
-            Token::INT(3),
-            Token::IDENT("ber".to_owned()),
+            Token::IDENT("num3ber".to_owned()),
             Token::INT(9),
             Token::EOF,
         ]);
@@ -229,30 +524,72 @@ mod tests {
 
     #[test]
     fn test_is_letter() {
-        let input = b'a';
+        let input = 'a';
+        assert!(is_letter(input));
+        let input = 'A';
         assert!(is_letter(input));
-        let input = b'A';
+        let input = 'z';
         assert!(is_letter(input));
-        let input = b'z';
+        let input = 'Z';
         assert!(is_letter(input));
-        let input = b'Z';
+        let input = 'f';
         assert!(is_letter(input));
-        let input = b'f';
+        let input = 'U';
         assert!(is_letter(input));
-        let input = b'U';
+        let input = '_';
+        assert!(is_letter(input));
+
+        let input = 'é';
         assert!(is_letter(input));
-        let input = b'_';
+        let input = 'λ';
         assert!(is_letter(input));
 
-        let input = b'&';
+        let input = '&';
         assert!(!is_letter(input));
+        let input = '3';
+        assert!(!is_letter(input));
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let input = "x1 user_2fa _9 café λ_2 名前 1x";
+
+        let tokens = lexer(input);
+        let expected = VecDeque::from(vec![
+            Token::IDENT("x1".to_owned()),
+            Token::IDENT("user_2fa".to_owned()),
+            Token::IDENT("_9".to_owned()),
+            Token::IDENT("café".to_owned()),
+            Token::IDENT("λ_2".to_owned()),
+            Token::IDENT("名前".to_owned()),
+            Token::ILLEGAL,
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_unicode_strings() {
+        let input = "'héllo ✓' + '日本' // ünïcode";
+
+        let tokens = lexer_with_comments(input);
+        let expected = VecDeque::from(vec![
+            Token::STRING("héllo ✓".to_owned()),
+            Token::PLUS,
+            Token::STRING("日本".to_owned()),
+            Token::TRAILINGCOMMENT("// ünïcode".to_owned()),
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
     }
 
     #[test]
     fn lex_ignore_whitespace() {
         let input = "}let  hello     ){  ; ";
 
-        let tokens = lexer(input.as_bytes());
+        let tokens = lexer(input);
         let expected = VecDeque::from(vec![
             Token::RBRACE,
             Token::LET,
@@ -272,7 +609,7 @@ mod tests {
         let y =     30;
         let    z = {    hello };";
 
-        let tokens = lexer(input.as_bytes());
+        let tokens = lexer(input);
         let expected = VecDeque::from(vec![
             Token::LET,
             Token::IDENT("x".to_owned()),
@@ -301,7 +638,7 @@ mod tests {
     fn test_double_character_tokens() {
         let input = "= == !=;";
 
-        let tokens = lexer(input.as_bytes());
+        let tokens = lexer(input);
         let expected = VecDeque::from(vec![
             Token::ASSIGN,
             Token::EQ,
@@ -317,7 +654,7 @@ mod tests {
     fn test_keywords() {
         let input = "let x = true; if hello == false { fn y() }";
 
-        let tokens = lexer(input.as_bytes());
+        let tokens = lexer(input);
         let expected = VecDeque::from(vec![
             Token::LET,
             Token::IDENT("x".to_owned()),
@@ -344,7 +681,7 @@ mod tests {
     fn test_string() {
         let input = "let name = 'jimmy * 123';";
 
-        let tokens = lexer(input.as_bytes());
+        let tokens = lexer(input);
         let expected = VecDeque::from(vec![
             Token::LET,
             Token::IDENT("name".to_owned()),
@@ -356,4 +693,367 @@ mod tests {
 
         assert_eq!(expected, tokens);
     }
+
+    #[test]
+    fn test_illegal_input() {
+        let input = "let a = 'abc";
+
+        let tokens = lexer(input);
+        let expected = VecDeque::from(vec![
+            Token::LET,
+            Token::IDENT("a".to_owned()),
+            Token::ASSIGN,
+            Token::ILLEGAL,
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+
+        let input = "1e999 !";
+
+        let tokens = lexer(input);
+        let expected = VecDeque::from(vec![Token::ILLEGAL, Token::BANG, Token::EOF]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_number_bases() {
+        let input = "0xFF 0xff_ff 0o17 0b1010 1_000_000 2_000.5 0x8000000000000000 1_0e1_0";
+
+        let tokens = lexer(input);
+        let spelled = |token, spelling: &str| Token::SPELLED(Box::new(token), spelling.to_owned());
+        let expected = VecDeque::from(vec![
+            spelled(Token::INT(255), "0xFF"),
+            spelled(Token::INT(65535), "0xff_ff"),
+            spelled(Token::INT(15), "0o17"),
+            spelled(Token::INT(10), "0b1010"),
+            spelled(Token::INT(1_000_000), "1_000_000"),
+            spelled(Token::FLOAT(2000.5), "2_000.5"),
+            spelled(
+                Token::BIGINT("9223372036854775808".parse().unwrap()),
+                "0x8000000000000000",
+            ),
+            spelled(Token::FLOAT(1e11), "1_0e1_0"),
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_malformed_numbers() {
+        let input = "0x 12abc 0b102 0o8 0xfg 1e5x 0x_ +";
+
+        let tokens = lexer(input);
+        let mut expected: VecDeque<_> = (0..7).map(|_| Token::ILLEGAL).collect();
+        expected.extend(vec![Token::PLUS, Token::EOF]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_misplaced_underscores() {
+        // Leading, trailing and doubled underscores
+        let tests = vec![
+            "1_", "1__0", "1_.5", "1.5_", "1_e5", "1e5_", "1e5__0", "0x_FF", "0b_1", "0xFF_",
+            "0o1__7",
+        ];
+        for input in tests {
+            let expected = VecDeque::from(vec![Token::ILLEGAL, Token::EOF]);
+            assert_eq!(expected, lexer(input), "{}", input);
+        }
+
+        // A leading underscore starts an identifier instead
+        let expected = VecDeque::from(vec![Token::IDENT("_1".to_owned()), Token::EOF]);
+        assert_eq!(expected, lexer("_1"));
+    }
+
+    #[test]
+    fn test_big_ints() {
+        let input = "9223372036854775807 9223372036854775808 99999999999999999999!";
+
+        let tokens = lexer(input);
+        let expected = VecDeque::from(vec![
+            Token::INT(isize::MAX),
+            Token::BIGINT("9223372036854775808".parse().unwrap()),
+            Token::BIGINT("99999999999999999999".parse().unwrap()),
+            Token::BANG,
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "1 // one\n/ 2\n  /* two /* nested */ */ #";
+
+        let tokens = lexer(input);
+        let expected = VecDeque::from(vec![
+            Token::INT(1),
+            Token::SLASH,
+            Token::INT(2),
+            Token::ILLEGAL,
+            Token::EOF,
+        ]);
+        assert_eq!(expected, tokens);
+
+        let tokens = lexer_with_comments(input);
+        let expected = VecDeque::from(vec![
+            Token::INT(1),
+            Token::TRAILINGCOMMENT("// one".to_owned()),
+            Token::SLASH,
+            Token::INT(2),
+            Token::COMMENT("/* two /* nested */ */".to_owned()),
+            Token::ILLEGAL,
+            Token::EOF,
+        ]);
+        assert_eq!(expected, tokens);
+
+        let input = "// last line";
+        let tokens = lexer_with_comments(input);
+        let expected = VecDeque::from(vec![Token::COMMENT(input.to_owned()), Token::EOF]);
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_unterminated_comment() {
+        let inputs = ["1 /* never closed", "/* /* */", "/*/"];
+        for input in inputs.iter() {
+            let tokens = lexer(input);
+            assert_eq!(
+                Some(&Token::ILLEGAL),
+                tokens.iter().rev().nth(1),
+                "{}",
+                input
+            );
+            assert_eq!(Some(&Token::EOF), tokens.back());
+        }
+    }
+
+    #[test]
+    fn test_const() {
+        let tokens = lexer("const constant");
+        let expected = VecDeque::from(vec![
+            Token::CONST,
+            Token::IDENT("constant".to_owned()),
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_match() {
+        let tokens = lexer("match (x) { _ => 1, } matches = >");
+        let expected = VecDeque::from(vec![
+            Token::MATCH,
+            Token::LPAREN,
+            Token::IDENT("x".to_owned()),
+            Token::RPAREN,
+            Token::LBRACE,
+            Token::IDENT("_".to_owned()),
+            Token::ARROW,
+            Token::INT(1),
+            Token::COMMA,
+            Token::RBRACE,
+            Token::IDENT("matches".to_owned()),
+            Token::ASSIGN,
+            Token::GT,
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_ellipsis() {
+        let tokens = lexer("fn(...rest) 0..1 0...1");
+        let expected = VecDeque::from(vec![
+            Token::FN,
+            Token::LPAREN,
+            Token::ELLIPSIS,
+            Token::IDENT("rest".to_owned()),
+            Token::RPAREN,
+            Token::INT(0),
+            Token::DOTDOT,
+            Token::INT(1),
+            Token::INT(0),
+            Token::ELLIPSIS,
+            Token::INT(1),
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_loop_keywords() {
+        let input = "while (go) { break; continue; } whiles";
+
+        let tokens = lexer(input);
+        let expected = VecDeque::from(vec![
+            Token::WHILE,
+            Token::LPAREN,
+            Token::IDENT("go".to_owned()),
+            Token::RPAREN,
+            Token::LBRACE,
+            Token::BREAK,
+            Token::SEMICOLON,
+            Token::CONTINUE,
+            Token::SEMICOLON,
+            Token::RBRACE,
+            Token::IDENT("whiles".to_owned()),
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_floats() {
+        let input = "2.75 1e-9 2.5E+3 7e2 0..1 1.x 5e 1e999";
+
+        let tokens = lexer(input);
+        let spelled = |token, spelling: &str| Token::SPELLED(Box::new(token), spelling.to_owned());
+        let expected = VecDeque::from(vec![
+            Token::FLOAT(2.75),
+            Token::FLOAT(1e-9),
+            spelled(Token::FLOAT(2500.0), "2.5E+3"),
+            spelled(Token::FLOAT(700.0), "7e2"),
+            Token::INT(0),
+            Token::DOTDOT,
+            Token::INT(1),
+            Token::INT(1),
+            Token::ILLEGAL,
+            Token::IDENT("x".to_owned()),
+            Token::ILLEGAL,
+            Token::ILLEGAL,
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_comparison_and_arithmetic_operators() {
+        let tokens = lexer("a<=b>=c<d>e%f**g*=h*i");
+        let expected = VecDeque::from(vec![
+            Token::IDENT("a".to_owned()),
+            Token::LTEQ,
+            Token::IDENT("b".to_owned()),
+            Token::GTEQ,
+            Token::IDENT("c".to_owned()),
+            Token::LT,
+            Token::IDENT("d".to_owned()),
+            Token::GT,
+            Token::IDENT("e".to_owned()),
+            Token::PERCENT,
+            Token::IDENT("f".to_owned()),
+            Token::POWER,
+            Token::IDENT("g".to_owned()),
+            Token::ASTERISKEQ,
+            Token::IDENT("h".to_owned()),
+            Token::ASTERISK,
+            Token::IDENT("i".to_owned()),
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let tokens = lexer("a && b || c & d | e");
+        let expected = VecDeque::from(vec![
+            Token::IDENT("a".to_owned()),
+            Token::AND,
+            Token::IDENT("b".to_owned()),
+            Token::OR,
+            Token::IDENT("c".to_owned()),
+            Token::ILLEGAL,
+            Token::IDENT("d".to_owned()),
+            Token::ILLEGAL,
+            Token::IDENT("e".to_owned()),
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_collections_and_ranges() {
+        let input = "for (k, v in {1: [2]}) { 0..10; 0..=n } .";
+
+        let tokens = lexer(input);
+        let expected = VecDeque::from(vec![
+            Token::FOR,
+            Token::LPAREN,
+            Token::IDENT("k".to_owned()),
+            Token::COMMA,
+            Token::IDENT("v".to_owned()),
+            Token::IN,
+            Token::LBRACE,
+            Token::INT(1),
+            Token::COLON,
+            Token::LBRACKET,
+            Token::INT(2),
+            Token::RBRACKET,
+            Token::RBRACE,
+            Token::RPAREN,
+            Token::LBRACE,
+            Token::INT(0),
+            Token::DOTDOT,
+            Token::INT(10),
+            Token::SEMICOLON,
+            Token::INT(0),
+            Token::DOTDOTEQ,
+            Token::IDENT("n".to_owned()),
+            Token::RBRACE,
+            Token::ILLEGAL,
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_assignment_operators() {
+        let input = "x = 1; x += 2; x -= 3; x *= 4; x /= 5; a[0] = x";
+
+        let tokens = lexer(input);
+        let x = || Token::IDENT("x".to_owned());
+        let expected = VecDeque::from(vec![
+            x(),
+            Token::ASSIGN,
+            Token::INT(1),
+            Token::SEMICOLON,
+            x(),
+            Token::PLUSEQ,
+            Token::INT(2),
+            Token::SEMICOLON,
+            x(),
+            Token::MINUSEQ,
+            Token::INT(3),
+            Token::SEMICOLON,
+            x(),
+            Token::ASTERISKEQ,
+            Token::INT(4),
+            Token::SEMICOLON,
+            x(),
+            Token::SLASHEQ,
+            Token::INT(5),
+            Token::SEMICOLON,
+            Token::IDENT("a".to_owned()),
+            Token::LBRACKET,
+            Token::INT(0),
+            Token::RBRACKET,
+            Token::ASSIGN,
+            x(),
+            Token::EOF,
+        ]);
+
+        assert_eq!(expected, tokens);
+    }
 }
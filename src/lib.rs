@@ -0,0 +1,10 @@
+// Token and operator names mirror the book's ALL_CAPS constants
+#![allow(clippy::upper_case_acronyms)]
+#![allow(clippy::module_inception, clippy::enum_variant_names)]
+
+pub mod compiler;
+pub mod evaluator;
+pub mod formatter;
+pub mod lexer;
+pub mod parser;
+pub mod vm;
@@ -1,28 +1,59 @@
+use monkey_lang::{
+    compiler::Compiler,
+    evaluator::{eval, Environment, Object},
+    formatter::format,
+    lexer::lexer,
+    parser::{parse, Parenthesised, Statement},
+};
 use rustyline::{self, error::ReadlineError};
-
-mod lexer;
-use lexer::lexer;
-mod parser;
-use parser::parse;
-mod evaluator;
-use evaluator::{eval, Environment, Object};
-mod compiler;
-mod vm;
+use std::{
+    env, fs,
+    io::{self, Read},
+    process,
+};
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("fmt") => process::exit(fmt(&args[1..])),
+        Some("check") => process::exit(check(&args[1..])),
+        _ => repl(),
+    }
+}
+
+fn repl() {
     let mut rl = rustyline::Editor::<()>::new();
     let mut env = Environment::new();
 
     loop {
         match rl.readline(">> ") {
             Ok(line) => {
-                let mut tokens = lexer(line.as_bytes());
-                let ast = parse(&mut tokens);
+                // `:ast <source>` prints the parsed AST instead of evaluating it
+                if let Some(source) = line.strip_prefix(":ast") {
+                    print_ast(source);
+                    continue;
+                }
+
+                let mut tokens = lexer(&line);
+                let ast = match parse(&mut tokens) {
+                    Ok(ast) => ast,
+                    Err(err) => {
+                        println!("Parse Error: {}", err);
+                        continue;
+                    }
+                };
                 let evaluated = eval(ast, &mut env);
 
                 match evaluated {
-                    Object::Int(val) => println!("{}", val),
-                    Object::Boolean(val) => println!("{}", val),
+                    Object::Int(_)
+                    | Object::BigInt(_)
+                    | Object::Float(_)
+                    | Object::Boolean(_)
+                    | Object::String(_)
+                    | Object::Array(_)
+                    | Object::Hash(_)
+                    | Object::Range { .. } => println!("{}", evaluated),
                     Object::Null => println!("Null"),
                     _ => println!("Evaluation Error"),
                 }
@@ -32,3 +63,125 @@ fn main() {
         }
     }
 }
+
+fn print_ast(source: &str) {
+    let mut tokens = lexer(source);
+    let ast: Vec<Statement> = match parse(&mut tokens) {
+        Ok(ast) => ast,
+        Err(err) => {
+            println!("Parse Error: {}", err);
+            return;
+        }
+    };
+
+    for statement in &ast {
+        println!("{}", Parenthesised(statement));
+        println!("{}", statement);
+    }
+}
+
+// `monkey fmt [--check] [files...]` formats each file in place, or stdin to
+// stdout when no files are given. With `--check` nothing is written and
+// the exit code is 1 if any input isn't already formatted.
+fn fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut source) {
+            eprintln!("Failed to read stdin: {}", err);
+            return 1;
+        }
+
+        return match format(&source) {
+            Ok(formatted) if check => (formatted != source) as i32,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            Err(err) => {
+                eprintln!("Parse Error: {}", err);
+                1
+            }
+        };
+    }
+
+    let mut status = 0;
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                status = 1;
+                continue;
+            }
+        };
+
+        let formatted = match format(&source) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!("{}: Parse Error: {}", path, err);
+                status = 1;
+                continue;
+            }
+        };
+
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            println!("{} is not formatted", path);
+            status = 1;
+        } else if let Err(err) = fs::write(path, formatted) {
+            eprintln!("{}: {}", path, err);
+            status = 1;
+        }
+    }
+
+    status
+}
+
+// `monkey check [files...]` compiles each file, or stdin when no files are
+// given, and prints any warnings. The exit code is 1 if anything fails to
+// compile, warnings alone don't change it.
+fn check(paths: &[String]) -> i32 {
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut source) {
+            eprintln!("Failed to read stdin: {}", err);
+            return 1;
+        }
+
+        return check_source("<stdin>", &source);
+    }
+
+    let mut status = 0;
+    for path in paths {
+        match fs::read_to_string(path) {
+            Ok(source) => status |= check_source(path, &source),
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                status = 1;
+            }
+        }
+    }
+
+    status
+}
+
+fn check_source(name: &str, source: &str) -> i32 {
+    match Compiler::from_source_with_warnings(source) {
+        Ok((_, warnings)) => {
+            for warning in warnings {
+                eprintln!("{}: warning: {}", name, warning);
+            }
+            0
+        }
+        Err(err) => {
+            eprintln!("{}: Compile Error: {:?}", name, err);
+            1
+        }
+    }
+}
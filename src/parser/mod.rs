@@ -0,0 +1,4 @@
+mod parser;
+mod printer;
+pub use parser::{parse, Expression, MatchArm, Operator, Parameter, Pattern, Prefix, Statement};
+pub use printer::{Indented, Parenthesised};
@@ -0,0 +1,1551 @@
+use crate::lexer::Token;
+use num_bigint::BigInt;
+use std::{cell::Cell, collections::VecDeque};
+
+// How deeply expressions, blocks and patterns can nest. The parser and
+// everything that walks the syntax tree recurse, so anything deeper is a
+// parse error rather than a stack overflow.
+const MAX_NESTING: usize = 128;
+
+thread_local! {
+    static NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+// Held while parsing something that can nest, counting how deep the parser
+// is until it is dropped
+struct Nested;
+
+impl Nested {
+    fn enter() -> Result<Nested, String> {
+        let depth = NESTING.with(|nesting| nesting.get()) + 1;
+        if depth > MAX_NESTING {
+            return Err(format!(
+                "Input nested more than {} levels deep.",
+                MAX_NESTING
+            ));
+        }
+        NESTING.with(|nesting| nesting.set(depth));
+        Ok(Nested)
+    }
+}
+
+impl Drop for Nested {
+    fn drop(&mut self) {
+        NESTING.with(|nesting| nesting.set(nesting.get() - 1));
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+    Let {
+        name: String,
+        value: Expression,
+    },
+    // `let [a, b] = value;` or `let {'k': v} = value;`, binding each name in
+    // the pattern to the matching part of the value
+    Destructure {
+        pattern: Pattern,
+        value: Expression,
+    },
+    // A binding that can't be assigned to
+    Const {
+        name: String,
+        value: Expression,
+    },
+    Return {
+        value: Expression,
+    },
+    ExpressionStatement(Expression),
+    While {
+        condition: Expression,
+        body: Vec<Statement>,
+    },
+    // `for (x in ...)` or `for (k, v in ...)`, there are always one or two
+    // variables
+    For {
+        variables: Vec<String>,
+        iterable: Expression,
+        body: Vec<Statement>,
+    },
+    Break,
+    Continue,
+    // Only parsed when the lexer keeps comments
+    Comment(String),
+    // A comment after code on the same line, it ends the line of the
+    // statement before it
+    TrailingComment(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expression {
+    Int(isize),
+    BigInt(BigInt),
+    Float(f64),
+    Boolean(bool),
+    Null,
+    Ident(String),
+    String(String),
+    Infix {
+        left: Box<Expression>,
+        op: Operator,
+        right: Box<Expression>,
+    },
+    Prefix {
+        prefix: Prefix,
+        value: Box<Expression>,
+    },
+    If {
+        condition: Box<Expression>,
+        consequence: Vec<Statement>,
+        alternative: Vec<Statement>,
+    },
+    FnLiteral {
+        parameters: Vec<Parameter>,
+        body: Vec<Statement>,
+    },
+    FnCall {
+        function: Box<Expression>,
+        args: Vec<Expression>,
+    },
+    Array(Vec<Expression>),
+    // Pairs are kept in the order they were written
+    Hash(Vec<(Expression, Expression)>),
+    Index {
+        left: Box<Expression>,
+        index: Box<Expression>,
+    },
+    // `target = value`, or `target op= value` when `op` is set. The target
+    // is an Ident or an Index into an Ident.
+    Assign {
+        target: Box<Expression>,
+        op: Option<Operator>,
+        value: Box<Expression>,
+    },
+    // Arms are tried in order, the value of the first arm that matches is
+    // the value of the match
+    Match {
+        value: Box<Expression>,
+        arms: Vec<MatchArm>,
+    },
+    // A number literal written other than as its value prints, like `0xff`
+    // or `1_000`, keeping the spelling so it prints back the same way
+    Spelled {
+        value: Box<Expression>,
+        spelling: String,
+    },
+}
+
+// Parameters with a default come after those without, and a rest parameter
+// can only be last
+#[derive(Debug, PartialEq, Clone)]
+pub enum Parameter {
+    // Destructures one argument, evaluating `default` in the function's
+    // scope when the call doesn't pass one
+    Single {
+        pattern: Pattern,
+        default: Option<Expression>,
+    },
+    // `...name`, the remaining arguments as an array
+    Rest(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Vec<Statement>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    // `_`, matches anything
+    Wildcard,
+    // Matches anything and binds it to the name
+    Binding(String),
+    // A number, string, boolean or null literal, negative numbers are a
+    // prefix minus on a number
+    Literal(Expression),
+    // Matches arrays of exactly this length
+    Array(Vec<Pattern>),
+    // Matches hashes that have every key, other keys are ignored
+    Hash(Vec<(Expression, Pattern)>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Prefix {
+    BANG,
+    MINUS,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Operator {
+    PLUS,
+    MINUS,
+    MULTIPLY,
+    DIVIDE,
+    GREATER,
+    LESS,
+    EQUAL,
+    NEQUAL,
+    GREATEREQUAL,
+    LESSEQUAL,
+    MODULO,
+    POWER,
+    RANGE,
+    RANGEINCLUSIVE,
+    AND,
+    OR,
+}
+
+#[derive(PartialOrd, PartialEq)]
+pub(super) enum Precedence {
+    LOWEST,
+    ASSIGN,      // = or +=
+    LOGICALOR,   // ||
+    LOGICALAND,  // &&
+    RANGE,       // .. or ..=
+    EQUALS,      // ==
+    LESSGREATER, // < or >
+    SUM,         // + or -
+    PRODUCT,     // * or /
+    PREFIX,      // -x
+    POWER,       // **, binds tighter than prefixes so -2 ** 2 is -(2 ** 2)
+    INDEX,       // array[index] or function(args)
+}
+
+impl Token {
+    fn precedence(&self) -> Precedence {
+        match self {
+            Token::PLUS => Precedence::SUM,
+            Token::MINUS => Precedence::SUM,
+            Token::LT => Precedence::LESSGREATER,
+            Token::GT => Precedence::LESSGREATER,
+            Token::LTEQ => Precedence::LESSGREATER,
+            Token::GTEQ => Precedence::LESSGREATER,
+            Token::EQ => Precedence::EQUALS,
+            Token::NEQ => Precedence::EQUALS,
+            Token::AND => Precedence::LOGICALAND,
+            Token::OR => Precedence::LOGICALOR,
+            Token::ASTERISK => Precedence::PRODUCT,
+            Token::SLASH => Precedence::PRODUCT,
+            Token::PERCENT => Precedence::PRODUCT,
+            Token::POWER => Precedence::POWER,
+            Token::ASSIGN => Precedence::ASSIGN,
+            Token::PLUSEQ => Precedence::ASSIGN,
+            Token::MINUSEQ => Precedence::ASSIGN,
+            Token::ASTERISKEQ => Precedence::ASSIGN,
+            Token::SLASHEQ => Precedence::ASSIGN,
+            Token::DOTDOT => Precedence::RANGE,
+            Token::DOTDOTEQ => Precedence::RANGE,
+            Token::LBRACKET => Precedence::INDEX,
+            Token::LPAREN => Precedence::INDEX,
+            _ => Precedence::LOWEST,
+        }
+    }
+}
+
+impl Operator {
+    pub(super) fn precedence(&self) -> Precedence {
+        match self {
+            Operator::PLUS | Operator::MINUS => Precedence::SUM,
+            Operator::MULTIPLY | Operator::DIVIDE | Operator::MODULO => Precedence::PRODUCT,
+            Operator::POWER => Precedence::POWER,
+            Operator::GREATER | Operator::LESS => Precedence::LESSGREATER,
+            Operator::GREATEREQUAL | Operator::LESSEQUAL => Precedence::LESSGREATER,
+            Operator::EQUAL | Operator::NEQUAL => Precedence::EQUALS,
+            Operator::RANGE | Operator::RANGEINCLUSIVE => Precedence::RANGE,
+            Operator::AND => Precedence::LOGICALAND,
+            Operator::OR => Precedence::LOGICALOR,
+        }
+    }
+}
+
+pub fn parse(tokens: &mut VecDeque<Token>) -> Result<Vec<Statement>, String> {
+    let statements = parse_statements(tokens)?;
+
+    match next(tokens) {
+        Token::EOF => Ok(statements),
+        token => Err(format!("Unexpected Token {:?} outside of a block.", token)),
+    }
+}
+
+// Parse statements until the end of the input or the end of a block
+fn parse_statements(tokens: &mut VecDeque<Token>) -> Result<Vec<Statement>, String> {
+    let mut statements: Vec<Statement> = Vec::new();
+
+    loop {
+        // Comments found part way through a statement end up here too, so
+        // they come after the statement they were in
+        match tokens.front() {
+            Some(Token::COMMENT(_)) | Some(Token::TRAILINGCOMMENT(_)) => {
+                match tokens.pop_front() {
+                    Some(Token::COMMENT(text)) => statements.push(Statement::Comment(text)),
+                    Some(Token::TRAILINGCOMMENT(text)) => {
+                        statements.push(Statement::TrailingComment(text))
+                    }
+                    _ => unreachable!("Comment checked above"),
+                }
+                continue;
+            }
+            _ => (),
+        }
+
+        match peek(tokens) {
+            Token::EOF => break,
+            Token::LET => {
+                next(tokens); // Discard LET Token
+                let statement = match peek(tokens) {
+                    Token::LBRACKET | Token::LBRACE => parse_destructure(tokens)?,
+                    _ => {
+                        let (name, value) = parse_binding(tokens, "let")?;
+                        Statement::Let { name, value }
+                    }
+                };
+                expect(tokens, Token::SEMICOLON)?;
+                statements.push(statement);
+            }
+            Token::CONST => {
+                next(tokens); // Discard CONST Token
+                let (name, value) = parse_binding(tokens, "const")?;
+                expect(tokens, Token::SEMICOLON)?;
+                statements.push(Statement::Const { name, value });
+            }
+            Token::RETURN => {
+                next(tokens); // Discard RETURN Token
+                let statement = parse_return(tokens)?;
+                expect(tokens, Token::SEMICOLON)?;
+                statements.push(statement);
+            }
+            Token::WHILE => {
+                next(tokens); // Discard WHILE Token
+                statements.push(parse_while(tokens)?);
+                skip_semicolon(tokens);
+            }
+            Token::BREAK => {
+                next(tokens);
+                statements.push(Statement::Break);
+                skip_semicolon(tokens);
+            }
+            Token::CONTINUE => {
+                next(tokens);
+                statements.push(Statement::Continue);
+                skip_semicolon(tokens);
+            }
+            Token::FOR => {
+                next(tokens); // Discard FOR Token
+                statements.push(parse_for(tokens)?);
+                skip_semicolon(tokens);
+            }
+            Token::RBRACE => break, // We must be at end of a block so break
+            _ => {
+                let exp = parse_expression(tokens, Precedence::LOWEST)?;
+                // Optional semi colon expression termination
+                skip_semicolon(tokens);
+                statements.push(Statement::ExpressionStatement(exp));
+            }
+        }
+    }
+
+    Ok(statements)
+}
+
+// Parse a list of statements surrounded by braces
+fn parse_block(tokens: &mut VecDeque<Token>) -> Result<Vec<Statement>, String> {
+    let _nested = Nested::enter()?;
+    expect(tokens, Token::LBRACE)?;
+    let statements = parse_statements(tokens)?;
+    expect(tokens, Token::RBRACE)?;
+    Ok(statements)
+}
+
+// Look at the next Token without consuming it, skipping any comments
+fn peek(tokens: &VecDeque<Token>) -> &Token {
+    tokens
+        .iter()
+        .find(|token| !is_comment(token))
+        .unwrap_or(&Token::EOF)
+}
+
+// Consume the next Token, running out of Tokens is the same as an EOF.
+// Comments are skipped and left in place for parse_statements.
+fn next(tokens: &mut VecDeque<Token>) -> Token {
+    match tokens.iter().position(|token| !is_comment(token)) {
+        Some(index) => tokens.remove(index).unwrap_or(Token::EOF),
+        None => Token::EOF,
+    }
+}
+
+// Consume an optional SEMICOLON
+fn skip_semicolon(tokens: &mut VecDeque<Token>) {
+    if peek(tokens) == &Token::SEMICOLON {
+        next(tokens);
+    }
+}
+
+fn is_comment(token: &Token) -> bool {
+    matches!(token, Token::COMMENT(_) | Token::TRAILINGCOMMENT(_))
+}
+
+// Consume the next Token and check it is the expected one
+fn expect(tokens: &mut VecDeque<Token>, expected: Token) -> Result<(), String> {
+    match next(tokens) {
+        token if token == expected => Ok(()),
+        token => Err(format!(
+            "Expected {:?} Token but found {:?}.",
+            expected, token
+        )),
+    }
+}
+
+// The `name = value` part of a `let` or `const` statement
+fn parse_binding(
+    tokens: &mut VecDeque<Token>,
+    keyword: &str,
+) -> Result<(String, Expression), String> {
+    let name = match next(tokens) {
+        Token::IDENT(name) => name,
+        _ => {
+            return Err(format!(
+                "Parse error in {} statement. Expected Identifier.",
+                keyword
+            ))
+        }
+    };
+
+    match next(tokens) {
+        Token::ASSIGN => (),
+        _ => {
+            return Err(format!(
+                "Parse error in {} statement. Expected ASSIGN Token.",
+                keyword
+            ))
+        }
+    };
+
+    let value = parse_expression(tokens, Precedence::LOWEST)?;
+
+    Ok((name, value))
+}
+
+// The `pattern = value` part of a destructuring `let`
+fn parse_destructure(tokens: &mut VecDeque<Token>) -> Result<Statement, String> {
+    let pattern = parse_pattern(tokens)?;
+    match next(tokens) {
+        Token::ASSIGN => (),
+        _ => return Err("Parse error in let statement. Expected ASSIGN Token.".to_owned()),
+    };
+    let value = parse_expression(tokens, Precedence::LOWEST)?;
+
+    Ok(Statement::Destructure { pattern, value })
+}
+
+fn parse_while(tokens: &mut VecDeque<Token>) -> Result<Statement, String> {
+    expect(tokens, Token::LPAREN)?;
+    let condition = parse_expression(tokens, Precedence::LOWEST)?;
+    expect(tokens, Token::RPAREN)?;
+    let body = parse_block(tokens)?;
+
+    Ok(Statement::While { condition, body })
+}
+
+fn parse_for(tokens: &mut VecDeque<Token>) -> Result<Statement, String> {
+    expect(tokens, Token::LPAREN)?;
+
+    let mut variables = vec![];
+    loop {
+        match next(tokens) {
+            Token::IDENT(name) => variables.push(name),
+            _ => return Err("Parse error in for loop. Expected Identifier.".to_owned()),
+        }
+        match next(tokens) {
+            Token::COMMA if variables.len() == 1 => continue,
+            Token::IN => break,
+            _ => return Err("Parse error in for loop. Expected IN Token.".to_owned()),
+        }
+    }
+
+    let iterable = parse_expression(tokens, Precedence::LOWEST)?;
+    expect(tokens, Token::RPAREN)?;
+    let body = parse_block(tokens)?;
+
+    Ok(Statement::For {
+        variables,
+        iterable,
+        body,
+    })
+}
+
+// Parse comma separated expressions up to the closing Token
+fn parse_list(tokens: &mut VecDeque<Token>, end: Token) -> Result<Vec<Expression>, String> {
+    let mut list = vec![];
+
+    if peek(tokens) == &end {
+        next(tokens);
+        return Ok(list);
+    }
+
+    loop {
+        list.push(parse_expression(tokens, Precedence::LOWEST)?);
+
+        match next(tokens) {
+            Token::COMMA => continue,
+            token if token == end => return Ok(list),
+            token => return Err(format!("Expected {:?} Token but found {:?}.", end, token)),
+        }
+    }
+}
+
+fn parse_hash(tokens: &mut VecDeque<Token>) -> Result<Expression, String> {
+    let mut pairs = vec![];
+
+    if peek(tokens) == &Token::RBRACE {
+        next(tokens);
+        return Ok(Expression::Hash(pairs));
+    }
+
+    loop {
+        let key = parse_expression(tokens, Precedence::LOWEST)?;
+        expect(tokens, Token::COLON)?;
+        let value = parse_expression(tokens, Precedence::LOWEST)?;
+        pairs.push((key, value));
+
+        match next(tokens) {
+            Token::COMMA => continue,
+            Token::RBRACE => return Ok(Expression::Hash(pairs)),
+            token => return Err(format!("Unexpected Token {:?} in hash literal.", token)),
+        }
+    }
+}
+
+// `(a, [b, c], d = 1, ...rest)`, parameters are patterns so arguments can
+// be destructured
+fn parse_parameters(tokens: &mut VecDeque<Token>) -> Result<Vec<Parameter>, String> {
+    expect(tokens, Token::LPAREN)?;
+    let mut parameters = vec![];
+
+    if peek(tokens) == &Token::RPAREN {
+        next(tokens);
+        return Ok(parameters);
+    }
+
+    loop {
+        let parameter = match peek(tokens) {
+            Token::ELLIPSIS => {
+                next(tokens);
+                match next(tokens) {
+                    Token::IDENT(name) => Parameter::Rest(name),
+                    _ => return Err("Expected Identifier after ELLIPSIS.".to_owned()),
+                }
+            }
+            _ => {
+                let pattern = parse_pattern(tokens)?;
+                let default = match peek(tokens) {
+                    Token::ASSIGN => {
+                        next(tokens);
+                        Some(parse_expression(tokens, Precedence::LOWEST)?)
+                    }
+                    _ => None,
+                };
+                let follows_default = matches!(
+                    parameters.last(),
+                    Some(Parameter::Single {
+                        default: Some(_),
+                        ..
+                    })
+                );
+                if default.is_none() && follows_default {
+                    return Err("Parameter without a default after one with a default.".to_owned());
+                }
+                Parameter::Single { pattern, default }
+            }
+        };
+        let is_rest = matches!(parameter, Parameter::Rest(_));
+        parameters.push(parameter);
+
+        match next(tokens) {
+            Token::COMMA if !is_rest => continue,
+            Token::RPAREN => return Ok(parameters),
+            Token::COMMA => return Err("Rest parameter must be the last parameter.".to_owned()),
+            _ => return Err("Unexpected Token in Function Literal.".to_owned()),
+        }
+    }
+}
+
+// `match (value) { pattern if guard => body, ... }`, a body is either a
+// block or a single expression
+fn parse_match(tokens: &mut VecDeque<Token>) -> Result<Expression, String> {
+    expect(tokens, Token::LPAREN)?;
+    let value = parse_expression(tokens, Precedence::LOWEST)?;
+    expect(tokens, Token::RPAREN)?;
+    expect(tokens, Token::LBRACE)?;
+
+    let mut arms = vec![];
+    loop {
+        if peek(tokens) == &Token::RBRACE {
+            next(tokens);
+            break;
+        }
+
+        let pattern = parse_pattern(tokens)?;
+        let guard = match peek(tokens) {
+            Token::IF => {
+                next(tokens);
+                Some(parse_expression(tokens, Precedence::LOWEST)?)
+            }
+            _ => None,
+        };
+        expect(tokens, Token::ARROW)?;
+        let body = match peek(tokens) {
+            Token::LBRACE => parse_block(tokens)?,
+            _ => vec![Statement::ExpressionStatement(parse_expression(
+                tokens,
+                Precedence::LOWEST,
+            )?)],
+        };
+        arms.push(MatchArm {
+            pattern,
+            guard,
+            body,
+        });
+
+        match next(tokens) {
+            Token::COMMA => continue,
+            Token::RBRACE => break,
+            token => return Err(format!("Unexpected Token {:?} in match expression.", token)),
+        }
+    }
+
+    Ok(Expression::Match {
+        value: Box::new(value),
+        arms,
+    })
+}
+
+fn parse_pattern(tokens: &mut VecDeque<Token>) -> Result<Pattern, String> {
+    let _nested = Nested::enter()?;
+    match next(tokens) {
+        Token::IDENT(name) if name == "_" => Ok(Pattern::Wildcard),
+        Token::IDENT(name) => Ok(Pattern::Binding(name)),
+        Token::LBRACKET => {
+            let mut items = vec![];
+            if peek(tokens) == &Token::RBRACKET {
+                next(tokens);
+                return Ok(Pattern::Array(items));
+            }
+            loop {
+                items.push(parse_pattern(tokens)?);
+                match next(tokens) {
+                    Token::COMMA => continue,
+                    Token::RBRACKET => return Ok(Pattern::Array(items)),
+                    token => return Err(format!("Unexpected Token {:?} in array pattern.", token)),
+                }
+            }
+        }
+        Token::LBRACE => {
+            let mut pairs = vec![];
+            if peek(tokens) == &Token::RBRACE {
+                next(tokens);
+                return Ok(Pattern::Hash(pairs));
+            }
+            loop {
+                let token = next(tokens);
+                let key = parse_literal(tokens, token)?;
+                expect(tokens, Token::COLON)?;
+                pairs.push((key, parse_pattern(tokens)?));
+                match next(tokens) {
+                    Token::COMMA => continue,
+                    Token::RBRACE => return Ok(Pattern::Hash(pairs)),
+                    token => return Err(format!("Unexpected Token {:?} in hash pattern.", token)),
+                }
+            }
+        }
+        token => Ok(Pattern::Literal(parse_literal(tokens, token)?)),
+    }
+}
+
+// A literal in a pattern starting with `token`, negative numbers are the
+// only prefix allowed
+fn parse_literal(tokens: &mut VecDeque<Token>, token: Token) -> Result<Expression, String> {
+    match token {
+        Token::INT(val) => Ok(Expression::Int(val)),
+        Token::BIGINT(val) => Ok(Expression::BigInt(val)),
+        Token::FLOAT(val) => Ok(Expression::Float(val)),
+        Token::TRUE => Ok(Expression::Boolean(true)),
+        Token::FALSE => Ok(Expression::Boolean(false)),
+        Token::NULL => Ok(Expression::Null),
+        Token::STRING(val) => Ok(Expression::String(val)),
+        Token::SPELLED(token, spelling) => Ok(Expression::Spelled {
+            value: Box::new(parse_literal(tokens, *token)?),
+            spelling,
+        }),
+        Token::MINUS => match next(tokens) {
+            token @ (Token::INT(_) | Token::BIGINT(_) | Token::FLOAT(_) | Token::SPELLED(..)) => {
+                Ok(Expression::Prefix {
+                    prefix: Prefix::MINUS,
+                    value: Box::new(parse_literal(tokens, token)?),
+                })
+            }
+            _ => Err("Expected a number after MINUS in pattern.".to_owned()),
+        },
+        token => Err(format!("Unexpected Token {:?} in pattern.", token)),
+    }
+}
+
+fn parse_return(tokens: &mut VecDeque<Token>) -> Result<Statement, String> {
+    let value = parse_expression(tokens, Precedence::LOWEST)?;
+
+    Ok(Statement::Return { value })
+}
+
+fn parse_expression(
+    tokens: &mut VecDeque<Token>,
+    precedence: Precedence,
+) -> Result<Expression, String> {
+    let _nested = Nested::enter()?;
+    let mut left_exp = match next(tokens) {
+        Token::INT(val) => Expression::Int(val),
+        Token::BIGINT(val) => Expression::BigInt(val),
+        Token::FLOAT(val) => Expression::Float(val),
+        Token::TRUE => Expression::Boolean(true),
+        Token::FALSE => Expression::Boolean(false),
+        Token::NULL => Expression::Null,
+        Token::STRING(val) => Expression::String(val),
+        Token::SPELLED(token, spelling) => Expression::Spelled {
+            value: Box::new(parse_literal(tokens, *token)?),
+            spelling,
+        },
+        Token::IDENT(name) => Expression::Ident(name),
+        Token::LPAREN => {
+            let exp = parse_expression(tokens, Precedence::LOWEST)?;
+            expect(tokens, Token::RPAREN)?;
+            exp
+        }
+        Token::LBRACKET => Expression::Array(parse_list(tokens, Token::RBRACKET)?),
+        Token::LBRACE => parse_hash(tokens)?,
+        Token::IF => {
+            expect(tokens, Token::LPAREN)?;
+            let condition = parse_expression(tokens, Precedence::LOWEST)?;
+            expect(tokens, Token::RPAREN)?;
+
+            let consequence = parse_block(tokens)?;
+
+            let alternative = match peek(tokens) {
+                Token::ELSE => {
+                    next(tokens);
+                    parse_block(tokens)?
+                }
+                _ => Vec::new(),
+            };
+
+            Expression::If {
+                condition: Box::new(condition),
+                consequence,
+                alternative,
+            }
+        }
+        Token::MINUS => Expression::Prefix {
+            prefix: Prefix::MINUS,
+            value: Box::new(parse_expression(tokens, Precedence::PREFIX)?),
+        },
+        Token::BANG => Expression::Prefix {
+            prefix: Prefix::BANG,
+            value: Box::new(parse_expression(tokens, Precedence::PREFIX)?),
+        },
+        Token::FN => {
+            let parameters = parse_parameters(tokens)?;
+            let body = parse_block(tokens)?;
+
+            Expression::FnLiteral { parameters, body }
+        }
+        Token::MATCH => parse_match(tokens)?,
+        token => return Err(format!("Unexpected Token {:?} in expression.", token)),
+    };
+
+    // Now expressions can be ended with or without a semi colon
+    if peek(tokens) == &Token::SEMICOLON {
+        return Ok(left_exp);
+    }
+
+    while precedence < peek(tokens).precedence() {
+        left_exp = parse_infix(tokens, left_exp)?;
+    }
+
+    Ok(left_exp)
+}
+
+fn parse_infix(tokens: &mut VecDeque<Token>, left: Expression) -> Result<Expression, String> {
+    let op = match next(tokens) {
+        Token::ASSIGN => return parse_assign(tokens, left, None),
+        Token::PLUSEQ => return parse_assign(tokens, left, Some(Operator::PLUS)),
+        Token::MINUSEQ => return parse_assign(tokens, left, Some(Operator::MINUS)),
+        Token::ASTERISKEQ => return parse_assign(tokens, left, Some(Operator::MULTIPLY)),
+        Token::SLASHEQ => return parse_assign(tokens, left, Some(Operator::DIVIDE)),
+        Token::LBRACKET => {
+            let index = parse_expression(tokens, Precedence::LOWEST)?;
+            expect(tokens, Token::RBRACKET)?;
+            return Ok(Expression::Index {
+                left: Box::new(left),
+                index: Box::new(index),
+            });
+        }
+        // Any expression followed by LPAREN is a function call
+        Token::LPAREN => {
+            return Ok(Expression::FnCall {
+                function: Box::new(left),
+                args: parse_list(tokens, Token::RPAREN)?,
+            });
+        }
+        Token::MINUS => Operator::MINUS,
+        Token::PLUS => Operator::PLUS,
+        Token::ASTERISK => Operator::MULTIPLY,
+        Token::SLASH => Operator::DIVIDE,
+        Token::PERCENT => Operator::MODULO,
+        Token::POWER => Operator::POWER,
+        Token::EQ => Operator::EQUAL,
+        Token::NEQ => Operator::NEQUAL,
+        Token::GT => Operator::GREATER,
+        Token::LT => Operator::LESS,
+        Token::GTEQ => Operator::GREATEREQUAL,
+        Token::LTEQ => Operator::LESSEQUAL,
+        Token::DOTDOT => Operator::RANGE,
+        Token::DOTDOTEQ => Operator::RANGEINCLUSIVE,
+        Token::AND => Operator::AND,
+        Token::OR => Operator::OR,
+        token => return Err(format!("Parse Infix called on invalid Token {:?}.", token)),
+    };
+    let precedence = match op {
+        // `**` is right associative, parsing the right side one level lower
+        // lets it take any `**` that follows
+        Operator::POWER => Precedence::PREFIX,
+        _ => op.precedence(),
+    };
+
+    let right_exp = parse_expression(tokens, precedence)?;
+
+    Ok(Expression::Infix {
+        left: Box::new(left),
+        op,
+        right: Box::new(right_exp),
+    })
+}
+
+// Assignment is right associative, so the value is parsed at the lowest
+// precedence and swallows any assignment that follows
+fn parse_assign(
+    tokens: &mut VecDeque<Token>,
+    target: Expression,
+    op: Option<Operator>,
+) -> Result<Expression, String> {
+    if target.assigned_variable().is_none() {
+        return Err("Invalid assignment target.".to_owned());
+    }
+
+    let value = parse_expression(tokens, Precedence::LOWEST)?;
+
+    Ok(Expression::Assign {
+        target: Box::new(target),
+        op,
+        value: Box::new(value),
+    })
+}
+
+impl Expression {
+    // The variable an assignment to this expression writes to, `a` for both
+    // `a = v` and `a[i][j] = v`. None if it can't be assigned to.
+    pub fn assigned_variable(&self) -> Option<&str> {
+        match self {
+            Expression::Ident(name) => Some(name),
+            Expression::Index { left, .. } => left.assigned_variable(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::lexer,
+        parser::{
+            parse, parser::MAX_NESTING, Expression, MatchArm, Operator, Parameter, Pattern, Prefix,
+            Statement,
+        },
+    };
+
+    #[test]
+    fn parse_basic_let_statement() {
+        let input = "let var_name = 8;";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::Let {
+            name: "var_name".to_owned(),
+            value: Expression::Int(8),
+        }];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn parse_basic_return_statement() {
+        let input = "return 5;";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::Return {
+            value: Expression::Int(5),
+        }];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn parse_basic_expression() {
+        let input = "2 + 5 + 8";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::ExpressionStatement(Expression::Infix {
+            left: Box::new(Expression::Infix {
+                left: Box::new(Expression::Int(2)),
+                op: Operator::PLUS,
+                right: Box::new(Expression::Int(5)),
+            }),
+            op: Operator::PLUS,
+            right: Box::new(Expression::Int(8)),
+        })];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn parse_multiple_epressions() {
+        let input = "1; 2; 3;";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![
+            Statement::ExpressionStatement(Expression::Int(1)),
+            Statement::ExpressionStatement(Expression::Int(2)),
+            Statement::ExpressionStatement(Expression::Int(3)),
+        ];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn parse_parenthesised_expression() {
+        let input = "2 + (5 + 8)";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::ExpressionStatement(Expression::Infix {
+            left: Box::new(Expression::Int(2)),
+            op: Operator::PLUS,
+            right: Box::new(Expression::Infix {
+                left: Box::new(Expression::Int(5)),
+                op: Operator::PLUS,
+                right: Box::new(Expression::Int(8)),
+            }),
+        })];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn parse_operators() {
+        let input = "1 + 2 * 3";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::ExpressionStatement(Expression::Infix {
+            left: Box::new(Expression::Int(1)),
+            op: Operator::PLUS,
+            right: Box::new(Expression::Infix {
+                left: Box::new(Expression::Int(2)),
+                op: Operator::MULTIPLY,
+                right: Box::new(Expression::Int(3)),
+            }),
+        })];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_if_statement() {
+        let input = "if (7) { 1 + 3 } else { 8 }";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::ExpressionStatement(Expression::If {
+            condition: Box::new(Expression::Int(7)),
+            consequence: vec![Statement::ExpressionStatement(Expression::Infix {
+                left: Box::new(Expression::Int(1)),
+                op: Operator::PLUS,
+                right: Box::new(Expression::Int(3)),
+            })],
+            alternative: vec![Statement::ExpressionStatement(Expression::Int(8))],
+        })];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_prefixes() {
+        let input = "let a = -33;
+        let b = !true;
+        let c = -1 + 2 + 3;";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![
+            Statement::Let {
+                name: "a".to_owned(),
+                value: Expression::Prefix {
+                    prefix: Prefix::MINUS,
+                    value: Box::new(Expression::Int(33)),
+                },
+            },
+            Statement::Let {
+                name: "b".to_owned(),
+                value: Expression::Prefix {
+                    prefix: Prefix::BANG,
+                    value: Box::new(Expression::Boolean(true)),
+                },
+            },
+            Statement::Let {
+                name: "c".to_owned(),
+                value: Expression::Infix {
+                    left: Box::new(Expression::Infix {
+                        left: Box::new(Expression::Prefix {
+                            prefix: Prefix::MINUS,
+                            value: Box::new(Expression::Int(1)),
+                        }),
+                        op: Operator::PLUS,
+                        right: Box::new(Expression::Int(2)),
+                    }),
+                    op: Operator::PLUS,
+                    right: Box::new(Expression::Int(3)),
+                },
+            },
+        ];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_fn_literal() {
+        let input = "fn(a, b) {
+            return 23;
+        }";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::ExpressionStatement(Expression::FnLiteral {
+            parameters: vec![
+                Parameter::Single {
+                    pattern: Pattern::Binding("a".to_owned()),
+                    default: None,
+                },
+                Parameter::Single {
+                    pattern: Pattern::Binding("b".to_owned()),
+                    default: None,
+                },
+            ],
+            body: vec![Statement::Return {
+                value: Expression::Int(23),
+            }],
+        })];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_function_call() {
+        let input = "add(2, 7)";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::ExpressionStatement(Expression::FnCall {
+            function: Box::new(Expression::Ident("add".to_owned())),
+            args: vec![Expression::Int(2), Expression::Int(7)],
+        })];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let parsed = |input: String| parse(&mut lexer(&input));
+
+        let input = format!("{}1{}", "[".repeat(100), "]".repeat(100));
+        assert!(parsed(input).is_ok());
+
+        let expected = Err(format!(
+            "Input nested more than {} levels deep.",
+            MAX_NESTING
+        ));
+        let tests = vec![
+            "(".repeat(50000),
+            format!("{}true", "!".repeat(200)),
+            format!("{}1", "a = ".repeat(200)),
+            format!("{}1{}", "[".repeat(200), "]".repeat(200)),
+            format!("{}{}", "while (x) { ".repeat(200), "}".repeat(200)),
+            format!("let {}x{} = 1;", "[".repeat(200), "]".repeat(200)),
+        ];
+        for input in tests {
+            assert_eq!(expected, parsed(input));
+        }
+    }
+
+    #[test]
+    fn test_call_any_expression() {
+        let input = "h['f'](4); f(1)(2); fn(x) { x }(3)";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let call = |function, arg| Expression::FnCall {
+            function: Box::new(function),
+            args: vec![Expression::Int(arg)],
+        };
+        let expected = vec![
+            Statement::ExpressionStatement(call(
+                Expression::Index {
+                    left: Box::new(Expression::Ident("h".to_owned())),
+                    index: Box::new(Expression::String("f".to_owned())),
+                },
+                4,
+            )),
+            Statement::ExpressionStatement(call(call(Expression::Ident("f".to_owned()), 1), 2)),
+            Statement::ExpressionStatement(call(
+                Expression::FnLiteral {
+                    parameters: vec![Parameter::Single {
+                        pattern: Pattern::Binding("x".to_owned()),
+                        default: None,
+                    }],
+                    body: vec![Statement::ExpressionStatement(Expression::Ident(
+                        "x".to_owned(),
+                    ))],
+                },
+                3,
+            )),
+        ];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_mock_program() {
+        let input = "let x = 7;
+        let hello = true;
+        let name = 'spyro';
+
+        if(hello) {
+            let y = x;
+            11
+        } else {
+            2 + 3 * 9
+        }
+        
+        return 1;";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![
+            Statement::Let {
+                name: "x".to_owned(),
+                value: Expression::Int(7),
+            },
+            Statement::Let {
+                name: "hello".to_owned(),
+                value: Expression::Boolean(true),
+            },
+            Statement::Let {
+                name: "name".to_owned(),
+                value: Expression::String("spyro".to_owned()),
+            },
+            Statement::ExpressionStatement(Expression::If {
+                condition: Box::new(Expression::Ident("hello".to_owned())),
+                consequence: vec![
+                    Statement::Let {
+                        name: "y".to_owned(),
+                        value: Expression::Ident("x".to_owned()),
+                    },
+                    Statement::ExpressionStatement(Expression::Int(11)),
+                ],
+                alternative: vec![Statement::ExpressionStatement(Expression::Infix {
+                    left: Box::new(Expression::Int(2)),
+                    op: Operator::PLUS,
+                    right: Box::new(Expression::Infix {
+                        left: Box::new(Expression::Int(3)),
+                        op: Operator::MULTIPLY,
+                        right: Box::new(Expression::Int(9)),
+                    }),
+                })],
+            }),
+            Statement::Return {
+                value: Expression::Int(1),
+            },
+        ];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_empty_function_call() {
+        let input = "now(); 1";
+
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![
+            Statement::ExpressionStatement(Expression::FnCall {
+                function: Box::new(Expression::Ident("now".to_owned())),
+                args: vec![],
+            }),
+            Statement::ExpressionStatement(Expression::Int(1)),
+        ];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_while() {
+        let input = "while (x < 3) { let x = x + 1; if (x == 2) { continue } break; };";
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::While {
+            condition: Expression::Infix {
+                left: Box::new(Expression::Ident("x".to_owned())),
+                op: Operator::LESS,
+                right: Box::new(Expression::Int(3)),
+            },
+            body: vec![
+                Statement::Let {
+                    name: "x".to_owned(),
+                    value: Expression::Infix {
+                        left: Box::new(Expression::Ident("x".to_owned())),
+                        op: Operator::PLUS,
+                        right: Box::new(Expression::Int(1)),
+                    },
+                },
+                Statement::ExpressionStatement(Expression::If {
+                    condition: Box::new(Expression::Infix {
+                        left: Box::new(Expression::Ident("x".to_owned())),
+                        op: Operator::EQUAL,
+                        right: Box::new(Expression::Int(2)),
+                    }),
+                    consequence: vec![Statement::Continue],
+                    alternative: vec![],
+                }),
+                Statement::Break,
+            ],
+        }];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_collections() {
+        let input = "[1, x][0]; {}; {'a': 1, b: [2]}";
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![
+            Statement::ExpressionStatement(Expression::Index {
+                left: Box::new(Expression::Array(vec![
+                    Expression::Int(1),
+                    Expression::Ident("x".to_owned()),
+                ])),
+                index: Box::new(Expression::Int(0)),
+            }),
+            Statement::ExpressionStatement(Expression::Hash(vec![])),
+            Statement::ExpressionStatement(Expression::Hash(vec![
+                (Expression::String("a".to_owned()), Expression::Int(1)),
+                (
+                    Expression::Ident("b".to_owned()),
+                    Expression::Array(vec![Expression::Int(2)]),
+                ),
+            ])),
+        ];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_for() {
+        let input = "for (k, v in 0..n + 1) { -a[k] }";
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::For {
+            variables: vec!["k".to_owned(), "v".to_owned()],
+            iterable: Expression::Infix {
+                left: Box::new(Expression::Int(0)),
+                op: Operator::RANGE,
+                right: Box::new(Expression::Infix {
+                    left: Box::new(Expression::Ident("n".to_owned())),
+                    op: Operator::PLUS,
+                    right: Box::new(Expression::Int(1)),
+                }),
+            },
+            body: vec![Statement::ExpressionStatement(Expression::Prefix {
+                prefix: Prefix::MINUS,
+                value: Box::new(Expression::Index {
+                    left: Box::new(Expression::Ident("a".to_owned())),
+                    index: Box::new(Expression::Ident("k".to_owned())),
+                }),
+            })],
+        }];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_match() {
+        let input =
+            "match (x) { -1 => 'neg', [a, _] if a > 0 => { a }, {'k': [v]} => v, _ => {}, }";
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::ExpressionStatement(Expression::Match {
+            value: Box::new(Expression::Ident("x".to_owned())),
+            arms: vec![
+                MatchArm {
+                    pattern: Pattern::Literal(Expression::Prefix {
+                        prefix: Prefix::MINUS,
+                        value: Box::new(Expression::Int(1)),
+                    }),
+                    guard: None,
+                    body: vec![Statement::ExpressionStatement(Expression::String(
+                        "neg".to_owned(),
+                    ))],
+                },
+                MatchArm {
+                    pattern: Pattern::Array(vec![
+                        Pattern::Binding("a".to_owned()),
+                        Pattern::Wildcard,
+                    ]),
+                    guard: Some(Expression::Infix {
+                        left: Box::new(Expression::Ident("a".to_owned())),
+                        op: Operator::GREATER,
+                        right: Box::new(Expression::Int(0)),
+                    }),
+                    body: vec![Statement::ExpressionStatement(Expression::Ident(
+                        "a".to_owned(),
+                    ))],
+                },
+                MatchArm {
+                    pattern: Pattern::Hash(vec![(
+                        Expression::String("k".to_owned()),
+                        Pattern::Array(vec![Pattern::Binding("v".to_owned())]),
+                    )]),
+                    guard: None,
+                    body: vec![Statement::ExpressionStatement(Expression::Ident(
+                        "v".to_owned(),
+                    ))],
+                },
+                MatchArm {
+                    pattern: Pattern::Wildcard,
+                    guard: None,
+                    body: vec![],
+                },
+            ],
+        })];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_parameters() {
+        let input = "fn(a, b = a + 1, ...rest) {}";
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![Statement::ExpressionStatement(Expression::FnLiteral {
+            parameters: vec![
+                Parameter::Single {
+                    pattern: Pattern::Binding("a".to_owned()),
+                    default: None,
+                },
+                Parameter::Single {
+                    pattern: Pattern::Binding("b".to_owned()),
+                    default: Some(Expression::Infix {
+                        left: Box::new(Expression::Ident("a".to_owned())),
+                        op: Operator::PLUS,
+                        right: Box::new(Expression::Int(1)),
+                    }),
+                },
+                Parameter::Rest("rest".to_owned()),
+            ],
+            body: vec![],
+        })];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_destructure() {
+        let input = "let [a, [_, b]] = pair; let {'x': x, 1: y} = point; fn([h, t], n) { h }";
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let expected = vec![
+            Statement::Destructure {
+                pattern: Pattern::Array(vec![
+                    Pattern::Binding("a".to_owned()),
+                    Pattern::Array(vec![Pattern::Wildcard, Pattern::Binding("b".to_owned())]),
+                ]),
+                value: Expression::Ident("pair".to_owned()),
+            },
+            Statement::Destructure {
+                pattern: Pattern::Hash(vec![
+                    (
+                        Expression::String("x".to_owned()),
+                        Pattern::Binding("x".to_owned()),
+                    ),
+                    (Expression::Int(1), Pattern::Binding("y".to_owned())),
+                ]),
+                value: Expression::Ident("point".to_owned()),
+            },
+            Statement::ExpressionStatement(Expression::FnLiteral {
+                parameters: vec![
+                    Parameter::Single {
+                        pattern: Pattern::Array(vec![
+                            Pattern::Binding("h".to_owned()),
+                            Pattern::Binding("t".to_owned()),
+                        ]),
+                        default: None,
+                    },
+                    Parameter::Single {
+                        pattern: Pattern::Binding("n".to_owned()),
+                        default: None,
+                    },
+                ],
+                body: vec![Statement::ExpressionStatement(Expression::Ident(
+                    "h".to_owned(),
+                ))],
+            }),
+        ];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_const() {
+        let input = "const x = 5; const y";
+        let mut tokens = lexer(input);
+        let expected = "Parse error in const statement. Expected ASSIGN Token.";
+        assert_eq!(Err(expected.to_owned()), parse(&mut tokens));
+
+        let mut tokens = lexer("const x = 5;");
+        let expected = vec![Statement::Const {
+            name: "x".to_owned(),
+            value: Expression::Int(5),
+        }];
+        assert_eq!(Ok(expected), parse(&mut tokens));
+    }
+
+    #[test]
+    fn test_assign() {
+        let input = "a = b += 1 + 2; m[k][0] *= 3";
+        let mut tokens = lexer(input);
+        let statements = parse(&mut tokens).unwrap();
+
+        let ident = |name: &str| Box::new(Expression::Ident(name.to_owned()));
+        let expected = vec![
+            Statement::ExpressionStatement(Expression::Assign {
+                target: ident("a"),
+                op: None,
+                value: Box::new(Expression::Assign {
+                    target: ident("b"),
+                    op: Some(Operator::PLUS),
+                    value: Box::new(Expression::Infix {
+                        left: Box::new(Expression::Int(1)),
+                        op: Operator::PLUS,
+                        right: Box::new(Expression::Int(2)),
+                    }),
+                }),
+            }),
+            Statement::ExpressionStatement(Expression::Assign {
+                target: Box::new(Expression::Index {
+                    left: Box::new(Expression::Index {
+                        left: ident("m"),
+                        index: ident("k"),
+                    }),
+                    index: Box::new(Expression::Int(0)),
+                }),
+                op: Some(Operator::MULTIPLY),
+                value: Box::new(Expression::Int(3)),
+            }),
+        ];
+
+        assert_eq!(expected, statements);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let inputs = vec![
+            "5 +",
+            "let = 5;",
+            "let x 5;",
+            "let x = 5",
+            "if (true { 1 }",
+            "if (true) { 1",
+            "fn(a b) { a }",
+            "add(1 2)",
+            "(1 + 2",
+            "}",
+            "'abc",
+            "while true { 1 }",
+            "for (x y in z) {}",
+            "for (a, b, c in z) {}",
+            "for (x of z) {}",
+            "[1, 2",
+            "{1 2}",
+            "{1: 2",
+            "a[1",
+            "1 = 2",
+            "a + b = 2",
+            "f() = 1",
+            "[a][0] = 1",
+            "x +=",
+            "while (true) 1",
+            "match x { _ => 1 }",
+            "match (x) { 1 2 }",
+            "match (x) { a + 1 => 2 }",
+            "match (x) { -a => 2 }",
+            "match (x) { {k: v} => 2 }",
+            "match (x) { [a b] => 2 }",
+            "match (x) { _ => 1 _ => 2 }",
+            "match (x) { _ if => 1 }",
+            "let [a, b] pair;",
+            "let [a, b] = pair",
+            "let [a + 1] = pair;",
+            "let {x: x} = point;",
+            "fn([a b]) { a }",
+            "fn(a,) { a }",
+            "fn(a = 1, b) { a }",
+            "fn(...rest, a) { a }",
+            "fn(...[a]) { a }",
+            "fn(a = ) { a }",
+        ];
+
+        for input in inputs {
+            let mut tokens = lexer(input);
+            assert!(parse(&mut tokens).is_err(), "{} should not parse", input);
+        }
+    }
+}
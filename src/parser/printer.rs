@@ -0,0 +1,641 @@
+use crate::parser::{
+    parser::Precedence, Expression, MatchArm, Operator, Parameter, Pattern, Prefix, Statement,
+};
+use std::fmt;
+
+// Displays a Statement or Expression with every infix and prefix expression
+// wrapped in parentheses so the parsed precedence is explicit
+pub struct Parenthesised<'a, T>(pub &'a T);
+
+// Displays a program with one statement per line and the statements in
+// blocks indented by four spaces
+pub struct Indented<'a>(pub &'a [Statement]);
+
+#[derive(Clone, Copy)]
+enum Style {
+    Canonical,
+    Parenthesised,
+    // Blocks are written over several lines, the field is the depth of the
+    // statement being written
+    Indented(usize),
+}
+
+// An Expression displayed in any Style
+struct Styled<'a>(&'a Expression, Style);
+
+impl fmt::Display for Styled<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_expression(f, self.0, self.1)
+    }
+}
+
+impl Style {
+    fn parens(self) -> bool {
+        matches!(self, Style::Parenthesised)
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_statement(f, self, Style::Canonical)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_expression(f, self, Style::Canonical)
+    }
+}
+
+impl fmt::Display for Parenthesised<'_, Statement> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_statement(f, self.0, Style::Parenthesised)
+    }
+}
+
+impl fmt::Display for Parenthesised<'_, Expression> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_expression(f, self.0, Style::Parenthesised)
+    }
+}
+
+impl fmt::Display for Indented<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, statement) in self.0.iter().enumerate() {
+            match statement {
+                Statement::TrailingComment(text) if i > 0 => write!(f, " {}", text)?,
+                statement => {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write_statement(f, statement, Style::Indented(0))?;
+                }
+            }
+        }
+        if !self.0.is_empty() {
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Binding(name) => write!(f, "{}", name),
+            // Patterns can't hold parentheses, so literals are always
+            // written canonically
+            Pattern::Literal(literal) => write!(f, "{}", literal),
+            Pattern::Array(items) => {
+                write!(f, "[")?;
+                write_patterns(f, items)?;
+                write!(f, "]")
+            }
+            Pattern::Hash(pairs) => {
+                write!(f, "{{")?;
+                for (i, (key, pattern)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, pattern)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_parameter(f, self, Style::Canonical)
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operator::PLUS => "+",
+            Operator::MINUS => "-",
+            Operator::MULTIPLY => "*",
+            Operator::DIVIDE => "/",
+            Operator::MODULO => "%",
+            Operator::POWER => "**",
+            Operator::GREATER => ">",
+            Operator::LESS => "<",
+            Operator::GREATEREQUAL => ">=",
+            Operator::LESSEQUAL => "<=",
+            Operator::EQUAL => "==",
+            Operator::NEQUAL => "!=",
+            Operator::RANGE => "..",
+            Operator::RANGEINCLUSIVE => "..=",
+            Operator::AND => "&&",
+            Operator::OR => "||",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Prefix::BANG => write!(f, "!"),
+            Prefix::MINUS => write!(f, "-"),
+        }
+    }
+}
+
+fn write_statement(f: &mut fmt::Formatter, statement: &Statement, style: Style) -> fmt::Result {
+    match statement {
+        Statement::Let { name, value } => {
+            write!(f, "let {} = ", name)?;
+            write_expression(f, value, style)?;
+        }
+        Statement::Destructure { pattern, value } => {
+            write!(f, "let {} = ", pattern)?;
+            write_expression(f, value, style)?;
+        }
+        Statement::Const { name, value } => {
+            write!(f, "const {} = ", name)?;
+            write_expression(f, value, style)?;
+        }
+        Statement::Return { value } => {
+            write!(f, "return ")?;
+            write_expression(f, value, style)?;
+        }
+        Statement::ExpressionStatement(value) => write_expression(f, value, style)?,
+        Statement::While { condition, body } => {
+            write!(f, "while (")?;
+            write_expression(f, condition, style)?;
+            write!(f, ") ")?;
+            return write_block(f, body, style);
+        }
+        Statement::For {
+            variables,
+            iterable,
+            body,
+        } => {
+            write!(f, "for ({} in ", variables.join(", "))?;
+            write_expression(f, iterable, style)?;
+            write!(f, ") ")?;
+            return write_block(f, body, style);
+        }
+        Statement::Break => write!(f, "break")?,
+        Statement::Continue => write!(f, "continue")?,
+        Statement::Comment(text) | Statement::TrailingComment(text) => {
+            return write!(f, "{}", text)
+        }
+    }
+    write!(f, ";")
+}
+
+fn write_block(f: &mut fmt::Formatter, statements: &[Statement], style: Style) -> fmt::Result {
+    if statements.is_empty() {
+        return write!(f, "{{}}");
+    }
+
+    if let Style::Indented(depth) = style {
+        write!(f, "{{")?;
+        for statement in statements {
+            if let Statement::TrailingComment(text) = statement {
+                write!(f, " {}", text)?;
+                continue;
+            }
+            writeln!(f)?;
+            write_indent(f, depth + 1)?;
+            write_statement(f, statement, Style::Indented(depth + 1))?;
+        }
+        writeln!(f)?;
+        write_indent(f, depth)?;
+        return write!(f, "}}");
+    }
+
+    write!(f, "{{ ")?;
+    for statement in statements {
+        write_statement(f, statement, style)?;
+        write!(f, " ")?;
+    }
+    write!(f, "}}")
+}
+
+// Canonically `match (x) { 1 => a, _ => { b; } }`, indented styles put each
+// arm on its own line
+fn write_match(
+    f: &mut fmt::Formatter,
+    value: &Expression,
+    arms: &[MatchArm],
+    style: Style,
+) -> fmt::Result {
+    write!(f, "match (")?;
+    write_expression(f, value, style)?;
+    write!(f, ") ")?;
+    if arms.is_empty() {
+        return write!(f, "{{}}");
+    }
+
+    let arm_style = match style {
+        Style::Indented(depth) => Style::Indented(depth + 1),
+        style => style,
+    };
+    write!(f, "{{")?;
+    for (i, arm) in arms.iter().enumerate() {
+        match arm_style {
+            Style::Indented(depth) => {
+                writeln!(f)?;
+                write_indent(f, depth)?;
+            }
+            _ => write!(f, " ")?,
+        }
+        write!(f, "{}", arm.pattern)?;
+        if let Some(guard) = &arm.guard {
+            write!(f, " if ")?;
+            write_expression(f, guard, arm_style)?;
+        }
+        write!(f, " => ")?;
+        write_arm_body(f, &arm.body, arm_style)?;
+        // Every arm ends in a comma when they are on their own lines
+        if i + 1 < arms.len() || matches!(style, Style::Indented(_)) {
+            write!(f, ",")?;
+        }
+    }
+    match style {
+        Style::Indented(depth) => {
+            writeln!(f)?;
+            write_indent(f, depth)?;
+        }
+        _ => write!(f, " ")?,
+    }
+    write!(f, "}}")
+}
+
+// A body that is a single expression is written without braces, unless it
+// would then start with a brace and read back as a block
+fn write_arm_body(f: &mut fmt::Formatter, body: &[Statement], style: Style) -> fmt::Result {
+    match body {
+        [Statement::ExpressionStatement(value)] => {
+            let written = Styled(value, style).to_string();
+            if written.starts_with('{') {
+                write!(f, "({})", written)
+            } else {
+                write!(f, "{}", written)
+            }
+        }
+        body => write_block(f, body, style),
+    }
+}
+
+fn write_indent(f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+    write!(f, "{:1$}", "", depth * 4)
+}
+
+// The precedence an expression was parsed at, None for expressions that
+// never need parentheses
+fn precedence(expression: &Expression) -> Option<Precedence> {
+    match expression {
+        Expression::Infix { op, .. } => Some(op.precedence()),
+        Expression::Prefix { .. } => Some(Precedence::PREFIX),
+        Expression::Assign { .. } => Some(Precedence::ASSIGN),
+        _ => None,
+    }
+}
+
+// Write an operand, wrapping it in parentheses if it binds looser than
+// `min` or as loosely as `min` when `inclusive` is set
+fn write_operand(
+    f: &mut fmt::Formatter,
+    operand: &Expression,
+    min: Precedence,
+    inclusive: bool,
+    style: Style,
+) -> fmt::Result {
+    let needs_parens = match precedence(operand) {
+        Some(precedence) => precedence < min || (inclusive && precedence == min),
+        None => false,
+    };
+
+    if needs_parens && !style.parens() {
+        write!(f, "(")?;
+        write_expression(f, operand, style)?;
+        write!(f, ")")
+    } else {
+        write_expression(f, operand, style)
+    }
+}
+
+fn write_expression(f: &mut fmt::Formatter, expression: &Expression, style: Style) -> fmt::Result {
+    match expression {
+        Expression::Int(val) => write!(f, "{}", val),
+        Expression::BigInt(val) => write!(f, "{}", val),
+        // Debug always has a `.` or exponent, so the literal reads back as a float
+        Expression::Float(val) => write!(f, "{:?}", val),
+        Expression::Spelled { spelling, .. } => write!(f, "{}", spelling),
+        Expression::Boolean(val) => write!(f, "{}", val),
+        Expression::Null => write!(f, "null"),
+        Expression::Ident(name) => write!(f, "{}", name),
+        Expression::String(val) => write!(f, "'{}'", val),
+        Expression::Infix { left, op, right } => {
+            if style.parens() {
+                write!(f, "(")?;
+            }
+            // Infix operators are left associative so only the right
+            // operand needs parentheses at the same precedence, except for
+            // `**` which is the other way around
+            let right_associative = *op == Operator::POWER;
+            write_operand(f, left, op.precedence(), right_associative, style)?;
+            match op {
+                // Ranges read better without spaces, `0..10`
+                Operator::RANGE | Operator::RANGEINCLUSIVE => write!(f, "{}", op)?,
+                _ => write!(f, " {} ", op)?,
+            }
+            write_operand(f, right, op.precedence(), !right_associative, style)?;
+            if style.parens() {
+                write!(f, ")")?;
+            }
+            Ok(())
+        }
+        Expression::Prefix { prefix, value } => {
+            if style.parens() {
+                write!(f, "(")?;
+            }
+            write!(f, "{}", prefix)?;
+            write_operand(f, value, Precedence::PREFIX, false, style)?;
+            if style.parens() {
+                write!(f, ")")?;
+            }
+            Ok(())
+        }
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            write!(f, "if (")?;
+            write_expression(f, condition, style)?;
+            write!(f, ") ")?;
+            write_block(f, consequence, style)?;
+            if !alternative.is_empty() {
+                write!(f, " else ")?;
+                write_block(f, alternative, style)?;
+            }
+            Ok(())
+        }
+        Expression::FnLiteral { parameters, body } => {
+            write!(f, "fn(")?;
+            for (i, parameter) in parameters.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_parameter(f, parameter, style)?;
+            }
+            write!(f, ") ")?;
+            write_block(f, body, style)
+        }
+        Expression::FnCall { function, args } => {
+            write_operand(f, function, Precedence::INDEX, false, style)?;
+            write!(f, "(")?;
+            write_list(f, args, style)?;
+            write!(f, ")")
+        }
+        Expression::Array(items) => {
+            write!(f, "[")?;
+            write_list(f, items, style)?;
+            write!(f, "]")
+        }
+        Expression::Hash(pairs) => {
+            write!(f, "{{")?;
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_expression(f, key, style)?;
+                write!(f, ": ")?;
+                write_expression(f, value, style)?;
+            }
+            write!(f, "}}")
+        }
+        Expression::Index { left, index } => {
+            write_operand(f, left, Precedence::INDEX, false, style)?;
+            write!(f, "[")?;
+            write_expression(f, index, style)?;
+            write!(f, "]")
+        }
+        Expression::Assign { target, op, value } => {
+            if style.parens() {
+                write!(f, "(")?;
+            }
+            write_expression(f, target, style)?;
+            match op {
+                Some(op) => write!(f, " {}= ", op)?,
+                None => write!(f, " = ")?,
+            }
+            // Assignment is right associative so a chain needs no parentheses
+            write_operand(f, value, Precedence::ASSIGN, false, style)?;
+            if style.parens() {
+                write!(f, ")")?;
+            }
+            Ok(())
+        }
+        Expression::Match { value, arms } => write_match(f, value, arms, style),
+    }
+}
+
+fn write_parameter(f: &mut fmt::Formatter, parameter: &Parameter, style: Style) -> fmt::Result {
+    match parameter {
+        Parameter::Single { pattern, default } => {
+            write!(f, "{}", pattern)?;
+            if let Some(default) = default {
+                write!(f, " = ")?;
+                write_expression(f, default, style)?;
+            }
+            Ok(())
+        }
+        Parameter::Rest(name) => write!(f, "...{}", name),
+    }
+}
+
+fn write_patterns(f: &mut fmt::Formatter, patterns: &[Pattern]) -> fmt::Result {
+    for (i, pattern) in patterns.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", pattern)?;
+    }
+    Ok(())
+}
+
+fn write_list(f: &mut fmt::Formatter, items: &[Expression], style: Style) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write_expression(f, item, style)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::lexer,
+        parser::{parse, Parenthesised, Statement},
+    };
+
+    fn parsed(input: &str) -> Vec<Statement> {
+        let mut tokens = lexer(input);
+        parse(&mut tokens).unwrap()
+    }
+
+    fn canonical(input: &str) -> String {
+        let statements: Vec<String> = parsed(input).iter().map(|s| s.to_string()).collect();
+        statements.join(" ")
+    }
+
+    fn parenthesised(input: &str) -> String {
+        let statements: Vec<String> = parsed(input)
+            .iter()
+            .map(|s| Parenthesised(s).to_string())
+            .collect();
+        statements.join(" ")
+    }
+
+    #[test]
+    fn test_parenthesised() {
+        let input = "1 + 2 * 3";
+        let expected = "(1 + (2 * 3));";
+        assert_eq!(expected, parenthesised(input));
+
+        let input = "1 - 2 - 3";
+        let expected = "((1 - 2) - 3);";
+        assert_eq!(expected, parenthesised(input));
+
+        let input = "-a * !b == c";
+        let expected = "(((-a) * (!b)) == c);";
+        assert_eq!(expected, parenthesised(input));
+
+        let input = "let x = add(1 + 2, 3 < 4);";
+        let expected = "let x = add((1 + 2), (3 < 4));";
+        assert_eq!(expected, parenthesised(input));
+
+        let input = "a = b += 1 * 2";
+        let expected = "(a = (b += (1 * 2)));";
+        assert_eq!(expected, parenthesised(input));
+
+        let input = "a % b ** c ** -d >= e";
+        let expected = "((a % (b ** (c ** (-d)))) >= e);";
+        assert_eq!(expected, parenthesised(input));
+
+        let input = "-a ** b";
+        let expected = "(-(a ** b));";
+        assert_eq!(expected, parenthesised(input));
+
+        let input = "a || b && c == d || !e";
+        let expected = "((a || (b && (c == d))) || (!e));";
+        assert_eq!(expected, parenthesised(input));
+    }
+
+    #[test]
+    fn test_canonical() {
+        let input = "(1 + (2 * 3))";
+        let expected = "1 + 2 * 3;";
+        assert_eq!(expected, canonical(input));
+
+        let input = "(1 + 2) * 3";
+        let expected = "(1 + 2) * 3;";
+        assert_eq!(expected, canonical(input));
+
+        let input = "1 - (2 - 3)";
+        let expected = "1 - (2 - 3);";
+        assert_eq!(expected, canonical(input));
+
+        let input = "-(1 + 2) - -3";
+        let expected = "-(1 + 2) - -3;";
+        assert_eq!(expected, canonical(input));
+
+        let input = "(a ** b) ** (c ** d) <= (-e) ** f";
+        let expected = "(a ** b) ** c ** d <= (-e) ** f;";
+        assert_eq!(expected, canonical(input));
+
+        let input = "(a || b) && (c && d)";
+        let expected = "(a || b) && (c && d);";
+        assert_eq!(expected, canonical(input));
+
+        // Numbers keep the spelling they were written with
+        let input = "1.50 + 2e3 * 1e-9 - 1e16 + 0xFF * 0b1010 - 1_000_000 + -0o17";
+        let expected = "1.50 + 2e3 * 1e-9 - 1e16 + 0xFF * 0b1010 - 1_000_000 + -0o17;";
+        assert_eq!(expected, canonical(input));
+
+        let input = "match (x) { 0xFF => 1, [-1_0] => 2 }";
+        let expected = "match (x) { 0xFF => 1, [-1_0] => 2 };";
+        assert_eq!(expected, canonical(input));
+
+        let input = "let  name='spyro' ;return name;";
+        let expected = "let name = 'spyro'; return name;";
+        assert_eq!(expected, canonical(input));
+
+        let input = "(f)(1)(2) + (a + b)(3) - (-c)(4)";
+        let expected = "f(1)(2) + (a + b)(3) - (-c)(4);";
+        assert_eq!(expected, canonical(input));
+    }
+
+    #[test]
+    fn test_blocks() {
+        let input = "if (x) { let y = 1; y } else { 2 }";
+        let expected = "if (x) { let y = 1; y; } else { 2; };";
+        assert_eq!(expected, canonical(input));
+
+        let input = "if (x) {}";
+        let expected = "if (x) {};";
+        assert_eq!(expected, canonical(input));
+
+        let input = "fn(a, b) { return a + b; }";
+        let expected = "fn(a, b) { return a + b; };";
+        assert_eq!(expected, canonical(input));
+
+        let input = "fn() { now() }";
+        let expected = "fn() { now(); };";
+        assert_eq!(expected, canonical(input));
+
+        let input = "for(k,v in {'a':[1,2][0], 2:{}}){ for (x in 0..=k+1) { -x[0] } }";
+        let expected = "for (k, v in {'a': [1, 2][0], 2: {}}) { for (x in 0..=k + 1) { -x[0]; } }";
+        assert_eq!(expected, canonical(input));
+
+        let input = "a = b -= c[0] /= 2; (x = 1) + 2; -(y *= 3); (z = [1])[0]";
+        let expected = "a = b -= c[0] /= 2; (x = 1) + 2; -(y *= 3); (z = [1])[0];";
+        assert_eq!(expected, canonical(input));
+
+        let input = "(-a)[0]; (1..2)[0]; [0..1, 2]";
+        let expected = "(-a)[0]; (1..2)[0]; [0..1, 2];";
+        assert_eq!(expected, canonical(input));
+
+        let input = "match(x){1=>a,[_,b] if b=>{let c=b;},{'k':v}=>({'v':v}),_=>{}}";
+        let expected =
+            "match (x) { 1 => a, [_, b] if b => { let c = b; }, {'k': v} => ({'v': v}), _ => {} };";
+        assert_eq!(expected, canonical(input));
+        let expected =
+            "match (x) { 1 => a, [_, b] if b => { let c = b; }, {'k': v} => ({'v': v}), _ => {} };";
+        assert_eq!(expected, parenthesised(input));
+
+        let input = "match (x + 1) { -2.5 => ({1: 2})[0], _ if -x => 1 + 2 }; match (x) {}";
+        let expected = "match (x + 1) { -2.5 => ({1: 2}[0]), _ if -x => 1 + 2 }; match (x) {};";
+        assert_eq!(expected, canonical(input));
+        let expected =
+            "match ((x + 1)) { -2.5 => ({1: 2}[0]), _ if (-x) => (1 + 2) }; match (x) {};";
+        assert_eq!(expected, parenthesised(input));
+
+        let input = "let[a,[_,b]]=p;let{'x':x,-1:{}}=q;fn([h,t],n){h}";
+        let expected = "let [a, [_, b]] = p; let {'x': x, -1: {}} = q; fn([h, t], n) { h; };";
+        assert_eq!(expected, canonical(input));
+
+        let input = "fn(a,b=a+1,...c){[a,b,c]}; fn(...rest) {}; fn({'k': v} = {}) { v }";
+        let expected =
+            "fn(a, b = a + 1, ...c) { [a, b, c]; }; fn(...rest) {}; fn({'k': v} = {}) { v; };";
+        assert_eq!(expected, canonical(input));
+        let expected =
+            "fn(a, b = (a + 1), ...c) { [a, b, c]; }; fn(...rest) {}; fn({'k': v} = {}) { v; };";
+        assert_eq!(expected, parenthesised(input));
+
+        let input = "while (a < b) { if (a) { break } else { continue } }";
+        let expected = "while (a < b) { if (a) { break; } else { continue; }; }";
+        assert_eq!(expected, canonical(input));
+    }
+}
@@ -1,212 +1,712 @@
 use crate::{
-    compiler::{two_u8_to_usize, ByteCode},
-    evaluator::Object,
+    compiler::{four_u8_to_usize, two_u8_to_usize, ByteCode},
+    evaluator::{
+        arity_error, builtin, mismatch_error, negate, Hash, Iter, Numbers, Object, BUILTINS,
+        MAX_FRAMES,
+    },
 };
-use std::mem;
+use std::{cmp::Ordering, rc::Rc};
 
 const STACK_SIZE: usize = 2048;
-const GLOBAL_SIZE: usize = 2048; // Setting this too high causes an overflow
+
+// An instruction with its operand already decoded. Jump targets are indexes
+// into the decoded instructions rather than byte offsets.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Instruction {
+    Constant(usize),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pop,
+    True,
+    False,
+    Greater,
+    Less,
+    Equal,
+    NotEqual,
+    Bang,
+    Minus,
+    Jmp(usize),
+    JmpIfFalse(usize),
+    JmpIfTrue(usize),
+    Mod,
+    Pow,
+    GreaterEqual,
+    LessEqual,
+    Null,
+    SetGlobal(usize),
+    GetGlobal(usize),
+    Array(usize),
+    Hash(usize),
+    Index,
+    Range,
+    RangeInclusive,
+    // Whether the loop takes a key and a value
+    IterInit(bool),
+    IterNext(usize),
+    SetIndex,
+    MatchEqual,
+    MatchArray(usize),
+    MatchHash,
+    MatchKey,
+    Mismatch,
+    AssignGlobal(usize),
+    SetConst(usize),
+    // Jumps to the end of the function body
+    Function(usize),
+    Call(usize),
+    Return,
+    CallBuiltin(usize, usize),
+    Parameters {
+        required: usize,
+        max: usize,
+        rest: bool,
+    },
+    Locals(usize),
+    HasArgument(usize),
+    GetLocal(usize),
+    SetLocal(usize),
+    AssignLocal(usize),
+    SetLocalConst(usize),
+    TailCall(usize),
+    CurrentFunction,
+}
+
+// Decode byte code instructions up front so `run` doesn't have to decode
+// operands on every step. Byte code can be loaded from a file, so anything
+// the compiler wouldn't produce is an error: unknown opcodes, operands cut
+// off at the end, constants and built in functions that don't exist and
+// jumps that don't land on an instruction.
+pub(crate) fn decode(bytes: &[u8], constants: usize) -> Result<Vec<Instruction>, String> {
+    let mut instructions = Vec::new();
+    // Maps the byte offset of each instruction to its decoded index, None
+    // for offsets inside an instruction
+    let mut offsets = vec![None; bytes.len() + 1];
+    let mut ip = 0;
+
+    while ip < bytes.len() {
+        offsets[ip] = Some(instructions.len());
+
+        let operand = |width: usize| {
+            bytes
+                .get(ip + 1..ip + 1 + width)
+                .ok_or_else(|| format!("Instruction at byte {} is cut off", ip))
+        };
+        let two_byte = || operand(2).map(|bytes| two_u8_to_usize(bytes[0], bytes[1]));
+        let four_byte = || operand(4).map(four_u8_to_usize);
+        let (instruction, width) = match bytes[ip] {
+            0x01 => (Instruction::Constant(two_byte()?), 3),
+            0x02 => (Instruction::Add, 1),
+            0x03 => (Instruction::Sub, 1),
+            0x04 => (Instruction::Mul, 1),
+            0x05 => (Instruction::Div, 1),
+            0x06 => (Instruction::Pop, 1),
+            0x07 => (Instruction::True, 1),
+            0x08 => (Instruction::False, 1),
+            0x09 => (Instruction::Greater, 1),
+            0x0a => (Instruction::Less, 1),
+            0x0b => (Instruction::Equal, 1),
+            0x0c => (Instruction::NotEqual, 1),
+            0x0d => (Instruction::Bang, 1),
+            0x0e => (Instruction::Minus, 1),
+            0x0f => (Instruction::Jmp(two_byte()?), 3),
+            0x10 => (Instruction::JmpIfFalse(two_byte()?), 3),
+            0x11 => (Instruction::SetGlobal(two_byte()?), 3),
+            0x12 => (Instruction::GetGlobal(two_byte()?), 3),
+            0x13 => (Instruction::Constant(four_byte()?), 5),
+            0x14 => (Instruction::Jmp(four_byte()?), 5),
+            0x15 => (Instruction::JmpIfFalse(four_byte()?), 5),
+            0x16 => (Instruction::SetGlobal(four_byte()?), 5),
+            0x17 => (Instruction::GetGlobal(four_byte()?), 5),
+            0x18 => (Instruction::Array(two_byte()?), 3),
+            0x19 => (Instruction::Hash(two_byte()?), 3),
+            0x1a => (Instruction::Index, 1),
+            0x1b => (Instruction::Range, 1),
+            0x1c => (Instruction::RangeInclusive, 1),
+            0x1d => match operand(1)?[0] {
+                1 => (Instruction::IterInit(false), 2),
+                2 => (Instruction::IterInit(true), 2),
+                count => return Err(format!("Can't loop over {} variables", count)),
+            },
+            0x1e => (Instruction::IterNext(two_byte()?), 3),
+            0x1f => (Instruction::IterNext(four_byte()?), 5),
+            0x20 => (Instruction::SetIndex, 1),
+            0x21 => (Instruction::JmpIfTrue(two_byte()?), 3),
+            0x22 => (Instruction::JmpIfTrue(four_byte()?), 5),
+            0x23 => (Instruction::Mod, 1),
+            0x24 => (Instruction::Pow, 1),
+            0x25 => (Instruction::GreaterEqual, 1),
+            0x26 => (Instruction::LessEqual, 1),
+            0x27 => (Instruction::Null, 1),
+            0x28 => (Instruction::MatchEqual, 1),
+            0x29 => (Instruction::MatchArray(two_byte()?), 3),
+            0x2a => (Instruction::MatchHash, 1),
+            0x2b => (Instruction::MatchKey, 1),
+            0x2c => (Instruction::Mismatch, 1),
+            0x2d => (Instruction::AssignGlobal(two_byte()?), 3),
+            0x2e => (Instruction::AssignGlobal(four_byte()?), 5),
+            0x2f => (Instruction::SetConst(two_byte()?), 3),
+            0x30 => (Instruction::SetConst(four_byte()?), 5),
+            0x31 => (Instruction::Function(two_byte()?), 3),
+            0x32 => (Instruction::Function(four_byte()?), 5),
+            0x33 => (Instruction::Call(two_byte()?), 3),
+            0x34 => (Instruction::Return, 1),
+            0x35 => {
+                let operands = operand(3)?;
+                let count = two_u8_to_usize(operands[1], operands[2]);
+                (Instruction::CallBuiltin(operands[0] as usize, count), 4)
+            }
+            0x36 => {
+                let operands = operand(5)?;
+                let rest = match operands[4] {
+                    0 => false,
+                    1 => true,
+                    flag => return Err(format!("Invalid rest parameter flag: {}", flag)),
+                };
+                let parameters = Instruction::Parameters {
+                    required: two_u8_to_usize(operands[0], operands[1]),
+                    max: two_u8_to_usize(operands[2], operands[3]),
+                    rest,
+                };
+                (parameters, 6)
+            }
+            0x37 => (Instruction::Locals(two_byte()?), 3),
+            0x38 => (Instruction::HasArgument(two_byte()?), 3),
+            0x39 => (Instruction::GetLocal(two_byte()?), 3),
+            0x3a => (Instruction::SetLocal(two_byte()?), 3),
+            0x3b => (Instruction::AssignLocal(two_byte()?), 3),
+            0x3c => (Instruction::SetLocalConst(two_byte()?), 3),
+            0x3d => (Instruction::TailCall(two_byte()?), 3),
+            0x3e => (Instruction::CurrentFunction, 1),
+            invalid => return Err(format!("Invalid instruction: {}", invalid)),
+        };
+
+        instructions.push(instruction);
+        ip += width;
+    }
+    // Jumps may target the end of the instructions
+    offsets[bytes.len()] = Some(instructions.len());
+
+    // Now every instruction has an index, convert jump targets. The compiler
+    // gives every global at least one instruction that sets it, so a global
+    // index can't be more than the number of instructions. Locals are
+    // checked as they are used, as it isn't known which function they
+    // belong to.
+    let count = instructions.len();
+    for instruction in instructions.iter_mut() {
+        match instruction {
+            Instruction::Jmp(target)
+            | Instruction::JmpIfFalse(target)
+            | Instruction::JmpIfTrue(target)
+            | Instruction::IterNext(target)
+            | Instruction::Function(target) => {
+                *target = offsets.get(*target).copied().flatten().ok_or_else(|| {
+                    format!("Jump to byte {} is not the start of an instruction", target)
+                })?;
+            }
+            Instruction::Constant(index) if *index >= constants => {
+                return Err(format!("Constant {} doesn't exist", index));
+            }
+            Instruction::CallBuiltin(index, _) if *index >= BUILTINS.len() => {
+                return Err(format!("Built in function {} doesn't exist", index));
+            }
+            Instruction::SetGlobal(index)
+            | Instruction::GetGlobal(index)
+            | Instruction::AssignGlobal(index)
+            | Instruction::SetConst(index)
+                if *index >= count =>
+            {
+                return Err(format!("Global {} is out of range", index));
+            }
+            _ => (),
+        }
+    }
+
+    Ok(instructions)
+}
+
+// A global or local
+#[derive(Clone)]
+struct Variable {
+    value: Object,
+    // The name and declaration of a variable last set by a `const`
+    declaration: Option<Object>,
+}
+
+// A call to a function that hasn't returned yet
+struct Frame {
+    function: Object,
+    // Where the caller carries on
+    return_ip: usize,
+    // Where the function's locals start
+    locals: usize,
+    // Where the function was on the stack, its result goes here
+    stack: usize,
+}
 
 pub struct Vm {
-    instructions: Vec<u8>,
+    instructions: Vec<Instruction>,
     constants: Vec<Object>,
-    stack: [Object; STACK_SIZE],
-    globals: [Object; GLOBAL_SIZE],
+    stack: Vec<Object>,
+    // Globals grow on demand as the compiler hands out new indexes, a
+    // variable declared in a branch that didn't run has no value
+    globals: Vec<Option<Variable>>,
+    // The locals of every frame, one after the other
+    locals: Vec<Option<Variable>>,
+    frames: Vec<Frame>,
     stack_pointer: usize,
 }
 
 impl Vm {
-    fn new(bytecode: ByteCode) -> Self {
-        Vm {
-            instructions: bytecode.instructions,
+    // Fails if the byte code isn't valid, see `decode`
+    pub fn new(bytecode: ByteCode) -> Result<Self, String> {
+        Ok(Vm {
+            instructions: decode(&bytecode.instructions, bytecode.constants.len())?,
             constants: bytecode.constants,
-            stack: unsafe { mem::zeroed() },
-            globals: unsafe { mem::zeroed() },
+            stack: vec![Object::Null; STACK_SIZE],
+            globals: Vec::new(),
+            locals: Vec::new(),
+            frames: Vec::new(),
             stack_pointer: 0,
-        }
+        })
     }
 
-    fn run(&mut self) {
+    pub fn run(&mut self) -> Result<(), String> {
         let mut ip = 0;
 
         while ip < self.instructions.len() {
             match self.instructions[ip] {
-                0x01 => {
-                    // OpConstant
-                    let const_index =
-                        two_u8_to_usize(self.instructions[ip + 1], self.instructions[ip + 2]);
-
-                    self.push(self.constants[const_index].clone());
-                    ip += 3;
-                }
-                0x02 => {
-                    // OpAdd
-                    match (self.pop(), self.pop()) {
-                        (Object::Int(right), Object::Int(left)) => {
-                            self.push(Object::Int(left + right));
-                        }
-                        _ => panic!("Invalid OpAdd operand"),
-                    };
-                    ip += 1;
+                Instruction::Constant(const_index) => {
+                    self.push(self.constants[const_index].clone())?;
                 }
-                0x03 => {
-                    // OpSub
-                    match (self.pop(), self.pop()) {
-                        (Object::Int(right), Object::Int(left)) => {
-                            self.push(Object::Int(left - right));
-                        }
-                        _ => panic!("Invalid OpSub operand"),
+                Instruction::Add => {
+                    let numbers = self.pop_numbers("OpAdd")?;
+                    self.push(numbers.add()?)?;
+                }
+                Instruction::Sub => {
+                    let numbers = self.pop_numbers("OpSub")?;
+                    self.push(numbers.subtract()?)?;
+                }
+                Instruction::Mul => {
+                    let numbers = self.pop_numbers("OpMul")?;
+                    self.push(numbers.multiply()?)?;
+                }
+                Instruction::Div => {
+                    let numbers = self.pop_numbers("OpDiv")?;
+                    self.push(numbers.divide()?)?;
+                }
+                Instruction::Mod => {
+                    let numbers = self.pop_numbers("OpMod")?;
+                    self.push(numbers.modulo()?)?;
+                }
+                Instruction::Pow => {
+                    let numbers = self.pop_numbers("OpPow")?;
+                    self.push(numbers.power()?)?;
+                }
+                Instruction::Pop => {
+                    self.pop()?;
+                }
+                Instruction::True => {
+                    self.push(Object::Boolean(true))?;
+                }
+                Instruction::False => {
+                    self.push(Object::Boolean(false))?;
+                }
+                Instruction::Null => {
+                    self.push(Object::Null)?;
+                }
+                Instruction::Greater => {
+                    let ordering = self.pop_numbers("OpGreater")?.compare();
+                    self.push(Object::Boolean(ordering == Ordering::Greater))?;
+                }
+                Instruction::Less => {
+                    let ordering = self.pop_numbers("OpLess")?.compare();
+                    self.push(Object::Boolean(ordering == Ordering::Less))?;
+                }
+                Instruction::GreaterEqual => {
+                    let ordering = self.pop_numbers("OpGreaterEqual")?.compare();
+                    self.push(Object::Boolean(ordering != Ordering::Less))?;
+                }
+                Instruction::LessEqual => {
+                    let ordering = self.pop_numbers("OpLessEqual")?.compare();
+                    self.push(Object::Boolean(ordering != Ordering::Greater))?;
+                }
+                Instruction::Equal => {
+                    let equal = self.pop_equality("OpEqual")?;
+                    self.push(Object::Boolean(equal))?;
+                }
+                Instruction::NotEqual => {
+                    let equal = self.pop_equality("OpNotEqual")?;
+                    self.push(Object::Boolean(!equal))?;
+                }
+                Instruction::Bang => {
+                    let value = self.pop()?;
+                    self.push(Object::Boolean(!value.is_truthy()))?;
+                }
+                Instruction::Minus => {
+                    let value = self.pop()?;
+                    match negate(&value) {
+                        Some(value) => self.push(value)?,
+                        None => return Err("Invalid OpMinus operand".to_owned()),
                     };
-                    ip += 1;
                 }
-                0x04 => {
-                    // OpMul
-                    match (self.pop(), self.pop()) {
-                        (Object::Int(right), Object::Int(left)) => {
-                            self.push(Object::Int(left * right));
-                        }
-                        _ => panic!("Invalid OpMul operand"),
+                Instruction::Jmp(target) => {
+                    ip = target;
+                    continue;
+                }
+                Instruction::JmpIfFalse(target) => {
+                    if !self.pop()?.is_truthy() {
+                        ip = target;
+                        continue;
+                    }
+                }
+                Instruction::JmpIfTrue(target) => {
+                    if self.pop()?.is_truthy() {
+                        ip = target;
+                        continue;
+                    }
+                }
+                Instruction::SetGlobal(global_index) => {
+                    let value = self.pop()?;
+                    self.set_global(global_index, value, None);
+                }
+                Instruction::SetConst(global_index) => {
+                    let declaration = self.pop()?;
+                    let value = self.pop()?;
+                    self.set_global(global_index, value, Some(declaration));
+                }
+                Instruction::AssignGlobal(global_index) => {
+                    let assignment = self.pop()?;
+                    assignable(self.global(global_index), &assignment)?;
+                    let value = self.pop()?;
+                    self.set_global(global_index, value, None);
+                }
+                Instruction::GetGlobal(global_index) => {
+                    let value = readable(self.global(global_index))?;
+                    self.push(value)?;
+                }
+                Instruction::SetLocal(index) => {
+                    let value = self.pop()?;
+                    self.set_local(index, value, None)?;
+                }
+                Instruction::SetLocalConst(index) => {
+                    let declaration = self.pop()?;
+                    let value = self.pop()?;
+                    self.set_local(index, value, Some(declaration))?;
+                }
+                Instruction::AssignLocal(index) => {
+                    let assignment = self.pop()?;
+                    assignable(self.local(index)?.as_ref(), &assignment)?;
+                    let value = self.pop()?;
+                    self.set_local(index, value, None)?;
+                }
+                Instruction::GetLocal(index) => {
+                    let value = readable(self.local(index)?.as_ref())?;
+                    self.push(value)?;
+                }
+                Instruction::HasArgument(index) => {
+                    let passed = self.local(index)?.is_some();
+                    self.push(Object::Boolean(passed))?;
+                }
+                Instruction::Function(end) => {
+                    let signature = match self.pop()? {
+                        Object::String(signature) => signature,
+                        _ => return Err("Invalid OpFunction operand".to_owned()),
                     };
-                    ip += 1;
-                }
-                0x05 => {
-                    // OpDiv
-                    match (self.pop(), self.pop()) {
-                        (Object::Int(right), Object::Int(left)) => {
-                            // TODO Handle remainders, currently they are truncated
-                            self.push(Object::Int(left / right));
+                    let entry = ip + 1;
+                    self.push(Object::CompiledFunction { entry, signature })?;
+                    ip = end;
+                    continue;
+                }
+                Instruction::Call(count) | Instruction::TailCall(count) => {
+                    let (function, entry) = self.pop_function(count)?;
+                    let tail = matches!(self.instructions[ip], Instruction::TailCall(_));
+                    match self.frames.last_mut() {
+                        // Reuse the frame of the function making the call
+                        Some(frame) if tail => {
+                            self.locals.truncate(frame.locals);
+                            frame.function = function;
                         }
-                        _ => panic!("Invalid OpDiv operand"),
-                    };
-                    ip += 1;
-                }
-                0x06 => {
-                    // OpPop
-                    self.pop();
-                    ip += 1;
-                }
-                0x07 => {
-                    // OpTrue
-                    self.push(Object::Boolean(true));
-                    ip += 1;
-                }
-                0x08 => {
-                    // OpFalse
-                    self.push(Object::Boolean(false));
-                    ip += 1;
-                }
-                0x09 => {
-                    // OpGreater
-                    match (self.pop(), self.pop()) {
-                        (Object::Int(right), Object::Int(left)) => {
-                            self.push(Object::Boolean(left > right));
+                        _ => {
+                            if self.frames.len() >= MAX_FRAMES {
+                                return Err("Stack overflow".to_owned());
+                            }
+                            self.frames.push(Frame {
+                                function,
+                                return_ip: ip + 1,
+                                locals: self.locals.len(),
+                                stack: self.stack_pointer - count - 1,
+                            });
                         }
-                        _ => panic!("Invalid OpGreater operand"),
-                    };
-                    ip += 1;
+                    }
+                    // The arguments become the first locals
+                    let start = self.stack_pointer - count;
+                    let args = self.stack[start..self.stack_pointer].iter().cloned();
+                    self.locals.extend(args.map(|value| {
+                        Some(Variable {
+                            value,
+                            declaration: None,
+                        })
+                    }));
+                    self.stack_pointer = self.frame()?.stack;
+                    ip = entry;
+                    continue;
                 }
-                0x0a => {
-                    // OpLess
-                    match (self.pop(), self.pop()) {
-                        (Object::Int(right), Object::Int(left)) => {
-                            self.push(Object::Boolean(left < right));
-                        }
-                        _ => panic!("Invalid OpLess operand"),
-                    };
-                    ip += 1;
+                Instruction::Return => {
+                    let value = self.pop()?;
+                    let frame = self.frames.pop().ok_or("Return outside of a function")?;
+                    self.locals.truncate(frame.locals);
+                    self.stack_pointer = frame.stack;
+                    self.push(value)?;
+                    ip = frame.return_ip;
+                    continue;
+                }
+                Instruction::CurrentFunction => {
+                    let function = self.frame()?.function.clone();
+                    self.push(function)?;
+                }
+                Instruction::CallBuiltin(index, count) => {
+                    let start = self
+                        .stack_pointer
+                        .checked_sub(count)
+                        .ok_or("Stack underflow")?;
+                    let result = builtin(BUILTINS[index], &self.stack[start..self.stack_pointer])?;
+                    self.stack_pointer = start;
+                    self.push(result)?;
                 }
-                0x0b => {
-                    // OpEqual
-                    match (self.pop(), self.pop()) {
-                        (Object::Int(right), Object::Int(left)) => {
-                            self.push(Object::Boolean(left == right));
+                Instruction::Parameters {
+                    required,
+                    max,
+                    rest,
+                } => {
+                    let frame = self.frame()?;
+                    let start = frame.locals;
+                    if let Object::CompiledFunction { signature, .. } = &frame.function {
+                        arity_error(required, max, rest, signature, self.locals.len() - start)?;
+                    }
+                    if rest {
+                        // Parameters with defaults might not have been passed
+                        if self.locals.len() < start + max {
+                            self.locals.resize(start + max, None);
                         }
-                        _ => panic!("Invalid OpEqual operand"),
+                        let rest = self.locals.drain(start + max..);
+                        let items = rest.flatten().map(|variable| variable.value).collect();
+                        self.locals.push(Some(Variable {
+                            value: Object::Array(Rc::new(items)),
+                            declaration: None,
+                        }));
+                    }
+                }
+                Instruction::Locals(count) => {
+                    let start = self.frame()?.locals;
+                    self.locals.resize(start + count, None);
+                }
+                Instruction::Array(len) => {
+                    let start = self
+                        .stack_pointer
+                        .checked_sub(len)
+                        .ok_or("Stack underflow")?;
+                    let items = self.stack[start..self.stack_pointer].to_vec();
+                    self.stack_pointer = start;
+                    self.push(Object::Array(Rc::new(items)))?;
+                }
+                Instruction::Hash(len) => {
+                    let start = self
+                        .stack_pointer
+                        .checked_sub(len * 2)
+                        .ok_or("Stack underflow")?;
+                    let mut hash = Hash::new();
+                    for pair in self.stack[start..self.stack_pointer].chunks(2) {
+                        hash.insert(pair[0].clone(), pair[1].clone())?;
+                    }
+                    self.stack_pointer = start;
+                    self.push(Object::Hash(Rc::new(hash)))?;
+                }
+                Instruction::Index => {
+                    let index = self.pop()?;
+                    let left = self.pop()?;
+                    self.push(left.index(&index)?)?;
+                }
+                Instruction::SetIndex => {
+                    let index = self.pop()?;
+                    let mut container = self.pop()?;
+                    // The value stays on the stack as the result
+                    let top = self.stack_pointer.checked_sub(1).ok_or("Stack underflow")?;
+                    container.set_index(index, self.stack[top].clone())?;
+                    self.push(container)?;
+                }
+                Instruction::MatchEqual => {
+                    let literal = self.pop()?;
+                    let value = self.pop()?;
+                    self.push(Object::Boolean(value.matches(&literal)))?;
+                }
+                Instruction::MatchArray(len) => {
+                    let is_match = match self.pop()? {
+                        Object::Array(items) => items.len() == len,
+                        _ => false,
                     };
-                    ip += 1;
+                    self.push(Object::Boolean(is_match))?;
                 }
-                0x0c => {
-                    // OpNotEqual
-                    match (self.pop(), self.pop()) {
-                        (Object::Int(right), Object::Int(left)) => {
-                            self.push(Object::Boolean(left != right));
+                Instruction::MatchHash => {
+                    let is_match = matches!(self.pop()?, Object::Hash(_));
+                    self.push(Object::Boolean(is_match))?;
+                }
+                Instruction::MatchKey => {
+                    let key = self.pop()?;
+                    let value = self.pop()?;
+                    self.push(Object::Boolean(value.has_key(&key)))?;
+                }
+                Instruction::Mismatch => {
+                    let detail = self.pop()?;
+                    let value = self.pop()?;
+                    return Err(mismatch_error(&value, &detail.to_string()));
+                }
+                Instruction::Range | Instruction::RangeInclusive => {
+                    match (self.pop()?, self.pop()?) {
+                        (Object::Int(end), Object::Int(start)) => {
+                            let inclusive = self.instructions[ip] == Instruction::RangeInclusive;
+                            self.push(Object::Range {
+                                start,
+                                end,
+                                inclusive,
+                            })?;
                         }
-                        _ => panic!("Invalid OpNotEqual operand"),
+                        _ => return Err("Invalid OpRange operand".to_owned()),
                     };
-                    ip += 1;
                 }
-                0x0d => {
-                    // OpBang
-                    match self.pop() {
-                        Object::Boolean(val) => self.push(Object::Boolean(!val)),
-                        _ => panic!("Invalid OpBang operand"),
-                    };
-                    ip += 1;
+                Instruction::IterInit(pairs) => {
+                    let iter = Box::new(Iter::new(&self.pop()?)?);
+                    self.push(Object::Iterator { iter, pairs })?;
                 }
-                0x0e => {
-                    // OpMinus
-                    match self.pop() {
-                        Object::Int(val) => self.push(Object::Int(-val)),
-                        _ => panic!("Invalid OpMinus operand"),
+                Instruction::IterNext(target) => {
+                    // The iterator stays on the stack until the loop ends
+                    let top = self.stack_pointer.checked_sub(1).ok_or("Stack underflow")?;
+                    let next = match &mut self.stack[top] {
+                        Object::Iterator { iter, pairs: false } => {
+                            iter.next_item().map(|item| (item, None))
+                        }
+                        Object::Iterator { iter, pairs: true } => {
+                            iter.next_pair().map(|(key, value)| (key, Some(value)))
+                        }
+                        _ => return Err("Invalid OpIterNext operand".to_owned()),
                     };
-                    ip += 1;
-                }
-                0x0f => {
-                    // OpJmp
-                    let new_ip =
-                        two_u8_to_usize(self.instructions[ip + 1], self.instructions[ip + 2]);
-                    ip = new_ip;
-                }
-                0x10 => {
-                    // OpJmpIfFalse
-                    match self.pop() {
-                        Object::Boolean(true) => {
-                            ip += 3;
+
+                    match next {
+                        Some((first, second)) => {
+                            self.push(first)?;
+                            if let Some(second) = second {
+                                self.push(second)?;
+                            }
                         }
-                        Object::Boolean(false) => {
-                            let new_ip = two_u8_to_usize(
-                                self.instructions[ip + 1],
-                                self.instructions[ip + 2],
-                            );
-                            ip = new_ip;
+                        None => {
+                            ip = target;
+                            continue;
                         }
-                        _ => panic!("Invalid OpJmpIfFalse operand"),
                     }
                 }
-                0x11 => {
-                    // OpSetGlobal
-                    let global_index =
-                        two_u8_to_usize(self.instructions[ip + 1], self.instructions[ip + 2]);
-                    self.globals[global_index] = self.pop();
-                    ip += 3;
-                }
-                0x12 => {
-                    // OpGetGlobal
-                    let global_index =
-                        two_u8_to_usize(self.instructions[ip + 1], self.instructions[ip + 2]);
-                    self.push(self.globals[global_index].clone());
-                    ip += 3;
-                }
-                invalid => panic!("Invalid instruction: {}", invalid),
             }
+            ip += 1;
         }
+
+        Ok(())
     }
 
-    fn push(&mut self, obj: Object) {
+    fn push(&mut self, obj: Object) -> Result<(), String> {
         if self.stack_pointer >= STACK_SIZE {
-            panic!("Stack overflow");
+            return Err("Stack overflow".to_owned());
         }
 
         self.stack[self.stack_pointer] = obj;
         self.stack_pointer += 1;
+        Ok(())
+    }
+
+    // The last value popped off the stack, this is the result of the last
+    // expression statement
+    pub fn last_popped(&self) -> &Object {
+        &self.stack[self.stack_pointer]
+    }
+
+    fn global(&self, index: usize) -> Option<&Variable> {
+        self.globals.get(index)?.as_ref()
+    }
+
+    fn set_global(&mut self, index: usize, value: Object, declaration: Option<Object>) {
+        if index >= self.globals.len() {
+            self.globals.resize(index + 1, None);
+        }
+        self.globals[index] = Some(Variable { value, declaration });
+    }
+
+    fn frame(&self) -> Result<&Frame, String> {
+        self.frames
+            .last()
+            .ok_or_else(|| "Local variable used outside of a function".to_owned())
+    }
+
+    // A local of the function running, None if it hasn't been set
+    fn local(&self, index: usize) -> Result<&Option<Variable>, String> {
+        let start = self.frame()?.locals;
+        self.locals
+            .get(start + index)
+            .ok_or_else(|| format!("Local {} is out of range", index))
+    }
+
+    fn set_local(
+        &mut self,
+        index: usize,
+        value: Object,
+        declaration: Option<Object>,
+    ) -> Result<(), String> {
+        let start = self.frame()?.locals;
+        let local = self
+            .locals
+            .get_mut(start + index)
+            .ok_or_else(|| format!("Local {} is out of range", index))?;
+        *local = Some(Variable { value, declaration });
+        Ok(())
     }
 
-    fn pop(&mut self) -> Object {
+    // Check the function under the operand's number of arguments can be
+    // called, giving it and where its body starts
+    fn pop_function(&self, count: usize) -> Result<(Object, usize), String> {
+        let position = self
+            .stack_pointer
+            .checked_sub(count + 1)
+            .ok_or("Stack underflow")?;
+        match &self.stack[position] {
+            function @ Object::CompiledFunction { entry, .. } => Ok((function.clone(), *entry)),
+            _ => Err("Attempted to call non-function".to_owned()),
+        }
+    }
+
+    // Pop the operands of an instruction that only works on numbers
+    fn pop_numbers(&mut self, op_name: &str) -> Result<Numbers, String> {
+        let right = self.pop()?;
+        let left = self.pop()?;
+        Numbers::new(&left, &right).ok_or_else(|| format!("Invalid {} operand", op_name))
+    }
+
+    // Pop two numbers, two booleans, or null and anything, and compare them
+    fn pop_equality(&mut self, op_name: &str) -> Result<bool, String> {
+        let right = self.pop()?;
+        let left = self.pop()?;
+        left.equals(&right)
+            .ok_or_else(|| format!("Invalid {} operand", op_name))
+    }
+
+    fn pop(&mut self) -> Result<Object, String> {
+        if self.stack_pointer == 0 {
+            return Err("Stack underflow".to_owned());
+        }
+
         self.stack_pointer -= 1;
-        self.stack[self.stack_pointer].clone()
+        Ok(self.stack[self.stack_pointer].clone())
     }
 
     // Utility function to observe stack
+    #[allow(dead_code)]
     fn print_stack(&self, num: usize) {
         println!("Vm Stack");
         for i in 0..num {
@@ -215,125 +715,710 @@ impl Vm {
     }
 }
 
+// The value of a variable, failing if it was declared in a branch that
+// didn't run
+fn readable(variable: Option<&Variable>) -> Result<Object, String> {
+    variable
+        .map(|variable| variable.value.clone())
+        .ok_or_else(|| "Attempted to access invalid variable".to_owned())
+}
+
+// Fail unless the variable can be assigned to, naming the assignment
+fn assignable(variable: Option<&Variable>, assignment: &Object) -> Result<(), String> {
+    match variable {
+        None => Err(format!(
+            "Assignment to undeclared variable in `{}`",
+            assignment
+        )),
+        Some(Variable {
+            declaration: Some(constant),
+            ..
+        }) => Err(format!(
+            "Assignment to constant {} in `{}`",
+            constant, assignment
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         compiler::{ByteCode, Compiler},
         evaluator::Object,
-        vm::Vm,
+        vm::{decode, Instruction, Vm},
     };
 
     fn compiled(input: &str) -> ByteCode {
-        Compiler::from_source(input)
+        Compiler::from_source(input).unwrap()
+    }
+
+    #[test]
+    fn test_decode() {
+        #[rustfmt::skip]
+        let input = vec![
+            7,         // OpTrue
+            16, 0, 10, // OpJmpIfFalse
+            1, 0, 0,   // OpConstant
+            15, 0, 13, // OpJmp
+            1, 0, 1,   // OpConstant
+            6,         // OpPop
+        ];
+        let expected = vec![
+            Instruction::True,
+            Instruction::JmpIfFalse(4),
+            Instruction::Constant(0),
+            Instruction::Jmp(5),
+            Instruction::Constant(1),
+            Instruction::Pop,
+        ];
+        assert_eq!(Ok(expected), decode(&input, 2));
+
+        #[rustfmt::skip]
+        let input = vec![
+            19, 0, 1, 0, 0,  // OpConstantWide
+            22, 0, 0, 0, 1,  // OpSetGlobalWide
+            23, 0, 0, 0, 1,  // OpGetGlobalWide
+            20, 0, 0, 0, 20, // OpJmpWide
+        ];
+        let expected = vec![
+            Instruction::Constant(65536),
+            Instruction::SetGlobal(1),
+            Instruction::GetGlobal(1),
+            Instruction::Jmp(4),
+        ];
+        assert_eq!(Ok(expected), decode(&input, 65537));
+
+        #[rustfmt::skip]
+        let input = vec![
+            1, 0, 0,           // OpConstant
+            49, 0, 13,         // OpFunction
+            54, 0, 1, 0, 1, 0, // OpParameters
+            52,                // OpReturn
+            53, 0, 0, 1,       // OpCallBuiltin
+        ];
+        let expected = vec![
+            Instruction::Constant(0),
+            Instruction::Function(4),
+            Instruction::Parameters {
+                required: 1,
+                max: 1,
+                rest: false,
+            },
+            Instruction::Return,
+            Instruction::CallBuiltin(0, 1),
+        ];
+        assert_eq!(Ok(expected), decode(&input, 1));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let tests = vec![
+            (vec![255], "Invalid instruction: 255"),
+            (vec![1, 0], "Instruction at byte 0 is cut off"),
+            (vec![7, 20, 0, 0], "Instruction at byte 1 is cut off"),
+            (vec![1, 0, 1], "Constant 1 doesn't exist"),
+            (vec![17, 0, 9], "Global 9 is out of range"),
+            (vec![29, 3], "Can't loop over 3 variables"),
+            (vec![53, 7, 0, 0], "Built in function 7 doesn't exist"),
+            (vec![54, 0, 0, 0, 0, 2], "Invalid rest parameter flag: 2"),
+            // Into the operand of the OpConstant
+            (
+                vec![1, 0, 0, 15, 0, 1],
+                "Jump to byte 1 is not the start of an instruction",
+            ),
+            (
+                vec![15, 0, 4],
+                "Jump to byte 4 is not the start of an instruction",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(Err(expected.to_owned()), decode(&input, 1), "{:?}", input);
+        }
+
+        let byte_code = ByteCode {
+            instructions: vec![255],
+            constants: vec![],
+        };
+        assert!(Vm::new(byte_code).is_err());
+
+        // Locals depend on the function running, so are checked as they run
+        let byte_code = ByteCode {
+            instructions: vec![57, 0, 0],
+            constants: vec![],
+        };
+        let expected = Err("Local variable used outside of a function".to_owned());
+        assert_eq!(expected, Vm::new(byte_code).unwrap().run());
     }
 
     #[test]
     fn test_basics() {
         let input = "7";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(7), vm.stack[0]);
 
         let input = "1 + 2";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(3), vm.stack[0]);
 
         let input = "2 * 3";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(6), vm.stack[0]);
 
         let input = "2 * 2 + 6 / 2 - 9";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(-2), vm.stack[0]);
 
+        let input = "17 % 5 + 2 ** 3 ** 2";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(514), vm.stack[0]);
+
+        let input = "7 / 2.0 + 0.25";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Float(3.75), vm.stack[0]);
+
+        let input = "-(9223372036854775807 + 1) - 1 + 2 ** 64";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!("9223372036854775807", vm.stack[0].to_string());
+
+        let input = "2 ** 100 / 2 ** 99";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(2), vm.stack[0]);
+
+        let input = "-1.5 < 1 && 2 == 2.0";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Boolean(true), vm.stack[0]);
+
         let input = "1; 2; 3;";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(3), vm.stack[0]);
 
         let input = "false";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Boolean(false), vm.stack[0]);
 
         let input = "true;";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Boolean(true), vm.stack[0]);
     }
 
     #[test]
     fn test_comparisons() {
         let input = "1 < 2";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Boolean(true), vm.stack[0]);
 
         let input = "1 > 2";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Boolean(false), vm.stack[0]);
 
         let input = "3 == 3";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Boolean(true), vm.stack[0]);
 
         let input = "3 != 7";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Boolean(true), vm.stack[0]);
+
+        let input = "true == false";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Boolean(false), vm.stack[0]);
+
+        let input = "(1 < 2) != false";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Boolean(true), vm.stack[0]);
+
+        let input = "2 <= 2";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Boolean(true), vm.stack[0]);
+
+        let input = "1 >= 2";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Boolean(false), vm.stack[0]);
     }
 
     #[test]
     fn test_prefixes() {
         let input = "-2";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(-2), vm.stack[0]);
 
         let input = "!true";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Boolean(false), vm.stack[0]);
 
         let input = "!!true";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Boolean(true), vm.stack[0]);
     }
 
     #[test]
     fn test_conditionals() {
         let input = "if(true) { 10 }";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(10), vm.stack[0]);
 
         let input = "if(false) { 10 } else { 20 }";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(20), vm.stack[0]);
+
+        let input = "if(false) { 10 }";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(&Object::Null, vm.last_popped());
+
+        let input = "let x = null; [if(x) { 1 } else { 2 }, !x, x == null, x != 1, null || true]";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!("[2, true, true, true, true]", vm.last_popped().to_string());
+
+        let input = "[if(0) { 1 } else { 2 }, if('') { 3 }, if({'a': 1}) { 4 }, !(1..1)]";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!("[2, null, 4, true]", vm.last_popped().to_string());
+    }
+
+    #[test]
+    fn test_match() {
+        let tests = vec![
+            ("match (2) { 1 => 'one', 2 => 'two', _ => 'many' }", "two"),
+            ("match (7) { 1 => 'one', n => n * 2 }", "14"),
+            ("match (1.0) { 1 => 'int', _ => 'other' }", "int"),
+            ("match (-3) { -3 => true, _ => false }", "true"),
+            ("match ('a') { 1 => 1, null => 2, 'a' => 3 }", "3"),
+            ("match ([1, 2]) { [a] => a, [a, b] => a + b }", "3"),
+            (
+                "match ([1, [2, 3]]) { [_, [b, 4]] => b, [_, [b, c]] => c }",
+                "3",
+            ),
+            ("match ({'k': 5, 'x': 0}) { {'k': v} => v }", "5"),
+            ("match ({'x': 0}) { {'k': v} => v, {} => 'hash' }", "hash"),
+            ("match ([]) { {} => 'hash', [] => 'array' }", "array"),
+            (
+                "match (5) { n if n > 9 => 'big', n if n > 3 => 'mid', _ => 'small' }",
+                "mid",
+            ),
+            ("match (5) { 1 => 1 }", "null"),
+            ("match (5) { n => { let m = n + 1; m } }", "6"),
+            ("match (match (1) { 1 => [2] }) { [n] => n }", "2"),
+            (
+                "[match (1) { n => match (n + 1) { m => [n, m] } }, match (3) { k => k }]",
+                "[[1, 2], 3]",
+            ),
+            (
+                "let t = 0; for (x in 0..5) { match (x) { 3 => { break; }, n => { t += n; } } }; t",
+                "3",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut vm = Vm::new(compiled(input)).unwrap();
+            vm.run().unwrap();
+            assert_eq!(expected, vm.last_popped().to_string(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_destructure() {
+        let tests = vec![
+            ("let [a, b] = [1, 2]; a + b", "3"),
+            ("let [a, [_, b]] = [1, [2, 3]]; [a, b]", "[1, 3]"),
+            (
+                "let {'x': x, 'y': [y]} = {'y': [2], 'x': 1, 'z': 0}; [x, y]",
+                "[1, 2]",
+            ),
+            ("let [0, n] = [0, 5]; n", "5"),
+            ("if (true) { let [a] = [4]; }", "[4]"),
+            (
+                "let t = 0; for (p in [[1, 2], [3, 4]]) { let [a, b] = p; t += a * b; }; t",
+                "14",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut vm = Vm::new(compiled(input)).unwrap();
+            vm.run().unwrap();
+            assert_eq!(expected, vm.last_popped().to_string(), "{}", input);
+        }
+
+        let tests = vec![
+            (
+                "let [a, b] = [1, 2, 3];",
+                "Can't destructure [1, 2, 3] into `[a, b]`, expected an array of length 2",
+            ),
+            (
+                "let {'x': x} = {'y': 1};",
+                "Can't destructure {y: 1} into `{'x': x}`, expected a hash with the key 'x'",
+            ),
+            (
+                "let {} = [];",
+                "Can't destructure [] into `{}`, expected a hash",
+            ),
+            (
+                "let [a, [1]] = [1, [2]];",
+                "Can't destructure 2 into `1`, expected 1",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut vm = Vm::new(compiled(input)).unwrap();
+            assert_eq!(Err(expected.to_owned()), vm.run(), "{}", input);
+        }
     }
 
     #[test]
     fn test_let_statements() {
         let input = "let x = 1; x;";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(1), vm.stack[0]);
 
         let input = "let x = 2; let y = x; y;";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(2), vm.stack[0]);
 
         let input = "let x = 1; let y = 2; x + y;";
-        let mut vm = Vm::new(compiled(input));
-        vm.run();
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
         assert_eq!(Object::Int(3), vm.stack[0]);
     }
+
+    #[test]
+    fn test_while() {
+        let input = "let i = 0; while (i < 5) { let i = i + 1; }; i";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(5), vm.stack[0]);
+
+        let input = "let i = 0; let sum = 0;
+            while (true) {
+                let i = i + 1;
+                if (i > 5) { break; } else { 0 };
+                if (i == 3) { continue; } else { 0 };
+                let sum = sum + i;
+            };
+            sum";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(12), vm.stack[0]);
+
+        // Leaving a loop from inside an expression drops its operands
+        let input = "let t = 0;
+            for (x in 1..=3) {
+                for (y in 1..=3) {
+                    t += [x, match (y) { 2 => { continue; }, 3 => { break; }, _ => y }][1];
+                }
+            };
+            t";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(3), vm.last_popped().clone());
+        assert_eq!(0, vm.stack_pointer);
+
+        let input = "let a = [0]; for (x in 1..3) { a[if (x == 2) { break; } else { 0 }] = x; }; a";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!("[1]", vm.last_popped().to_string());
+    }
+
+    #[test]
+    fn test_logical() {
+        let tests = vec![
+            ("true && true", true),
+            ("true && false", false),
+            ("false && true", false),
+            ("false || false", false),
+            ("false || true", true),
+            ("true || false", true),
+            ("1 < 2 && 2 < 3 || false", true),
+        ];
+        for (input, expected) in tests {
+            let mut vm = Vm::new(compiled(input)).unwrap();
+            vm.run().unwrap();
+            assert_eq!(Object::Boolean(expected), vm.stack[0], "{}", input);
+        }
+
+        // The right side only runs when it decides the result
+        let input = "let x = 0; false && (x = 1) == 1; true || (x = 2) == 2; x";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(0), vm.stack[0]);
+
+        let mut vm = Vm::new(compiled("[true && 1, '' || [], 0 || 'a']")).unwrap();
+        vm.run().unwrap();
+        assert_eq!("[true, false, true]", vm.last_popped().to_string());
+    }
+
+    #[test]
+    fn test_collections() {
+        let input = "let a = [1, 2 + 3, 'x']; a[1]";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(5), vm.stack[0]);
+
+        let input = "[1][1]";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Null, vm.stack[0]);
+
+        let input = "let h = {'a': 1, true: 2}; h[true]";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(2), vm.stack[0]);
+
+        let input = "{[1]: 2}";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(Err("Unusable as hash key: [1]".to_owned()), vm.run());
+    }
+
+    #[test]
+    fn test_for() {
+        let input = "let sum = 0; for (x in 1..=4) { let sum = sum * 10 + x; }; sum";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(1234), vm.stack[0]);
+
+        let input = "let sum = 0;
+            for (k, v in {1: 10, 2: 20, 3: 30}) {
+                if (k == 3) { break; } else { 0 };
+                let sum = sum + k * v;
+            };
+            sum";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(50), vm.stack[0]);
+
+        let input = "let last = 0; for (i, c in 'abc') { let last = i; }; last";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(2), vm.stack[0]);
+
+        let input = "for (x in 5) { x }";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(Err("Can't iterate over 5".to_owned()), vm.run());
+    }
+
+    #[test]
+    fn test_assign() {
+        let input = "let x = 1; x = x + 1; x += 10; x *= 2; x -= 4; x /= 5; x";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(4), vm.stack[0]);
+
+        let input = "let a = 1; let b = 2; a = b = 7; a + b";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(14), vm.stack[0]);
+
+        let input = "let a = [1, [2, 3]]; let b = a; a[1][0] = 9; a[0] += 1; [a, b]";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!("[[2, [9, 3]], [1, [2, 3]]]", vm.stack[0].to_string());
+
+        let input = "let h = {}; h['a'] = 1; h['b'] = h['a'] + 1; h['a'] = 3; h";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!("{a: 3, b: 2}", vm.stack[0].to_string());
+
+        // Variables declared in a branch that didn't run have no value
+        let input = "if (false) { let z = 1; }; z = 5";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(
+            Err("Assignment to undeclared variable in `z = 5`".to_owned()),
+            vm.run()
+        );
+
+        let input = "if (false) { let z = [1]; }; let w = 2; z[0] = 5";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(
+            Err("Attempted to access invalid variable".to_owned()),
+            vm.run()
+        );
+
+        let input = "let a = [1, 2]; a[2] = 3";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(
+            Err("Index 2 out of range for length 2".to_owned()),
+            vm.run()
+        );
+    }
+
+    #[test]
+    fn test_const() {
+        let input = "const a = [1]; let b = a; b[0] = 2; [a, b]";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!("[[1], [2]]", vm.last_popped().to_string());
+
+        // Which declaration ran last decides, not which was compiled last
+        let input = "let x = 1; let i = 0; while (i < 2) { i += 1; x = 2; const x = 3; }";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(
+            Err("Assignment to constant x declared by `const x = 3;` in `x = 2`".to_owned()),
+            vm.run()
+        );
+    }
+
+    #[test]
+    fn test_wide_operands() {
+        // Constant indexes past 65535 use OpConstantWide
+        let input = format!("{} 7;", "1;".repeat(70000));
+        let mut vm = Vm::new(compiled(&input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(7), vm.stack[0]);
+
+        // Jump targets past 65535 use the wide jumps
+        let input = format!("if(false) {{ {} }} else {{ 20 }}", "1;".repeat(20000));
+        let mut vm = Vm::new(compiled(&input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(20), vm.stack[0]);
+
+        let input = format!("if(true) {{ {} 10 }} else {{ 20 }}", "1;".repeat(20000));
+        let mut vm = Vm::new(compiled(&input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(10), vm.stack[0]);
+
+        // Global indexes past 65535 use the wide global ops
+        // Identifiers can't contain digits so spell the index out in letters
+        let name = |mut i: usize| {
+            (0..4)
+                .map(|_| {
+                    let ch = (b'a' + (i % 26) as u8) as char;
+                    i /= 26;
+                    ch
+                })
+                .collect::<String>()
+        };
+        let lets: String = (0..65540)
+            .map(|i| format!("let {} = {};", name(i), i))
+            .collect();
+        let input = format!("{} {};", lets, name(65539));
+        let mut vm = Vm::new(compiled(&input)).unwrap();
+        vm.run().unwrap();
+        assert_eq!(Object::Int(65539), vm.stack[0]);
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let input = "1 / 0";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(Err("Division by zero".to_owned()), vm.run());
+
+        let input = "1 % 0";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(Err("Modulo by zero".to_owned()), vm.run());
+
+        let input = "2 ** 2 ** 63";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(Err("Integer too large".to_owned()), vm.run());
+
+        let input = "2 ** -1";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(Err("Negative exponent".to_owned()), vm.run());
+
+        let input = "0.5 / 0";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(Err("Division by zero".to_owned()), vm.run());
+
+        let input = "1e200 * 1e200";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(Err("Float overflow".to_owned()), vm.run());
+
+        let input = "-true";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(Err("Invalid OpMinus operand".to_owned()), vm.run());
+    }
+
+    #[test]
+    fn test_functions() {
+        let tests = vec![
+            ("let f = fn(x, y = 2) { x * y }; [f(3), f(3, 3)]", "[6, 9]"),
+            (
+                "let f = fn(first, ...rest) { [first, rest] }; f(1, 2, 3)",
+                "[1, [2, 3]]",
+            ),
+            ("let f = fn(a = 1, ...rest) { [a, rest] }; f()", "[1, []]"),
+            ("let f = fn({'a': a}, [b]) { a + b }; f({'a': 1}, [2])", "3"),
+            (
+                "let f = fn(n) { if (n == 0) { 'done' } else { f(n - 1) } }; let g = f; g(3)",
+                "done",
+            ),
+            ("let f = fn(f) { f }; f(1)", "1"),
+            ("let f = fn() { let x = 1; x += 1; }; f()", "2"),
+            ("let f = fn(x, ...rest) { x }; f", "fn(x, ...rest)"),
+            ("len('abc') + floor(2.5)", "5"),
+        ];
+        for (input, expected) in tests {
+            let mut vm = Vm::new(compiled(input)).unwrap();
+            vm.run().unwrap();
+            assert_eq!(expected, vm.last_popped().to_string(), "{}", input);
+        }
+
+        let tests = vec![
+            (
+                "let f = fn(x, y) { x }; f(1)",
+                "fn(x, y) takes 2 arguments but was called with 1",
+            ),
+            ("let f = 1; f()", "Attempted to call non-function"),
+            (
+                "let f = fn() { if (false) { let z = 1; }; z }; f()",
+                "Attempted to access invalid variable",
+            ),
+            ("sqrt(-1)", "Can't take the square root of -1.0"),
+            ("let f = fn(g) { 1 + g(g) }; f(f)", "Stack overflow"),
+        ];
+        for (input, expected) in tests {
+            let mut vm = Vm::new(compiled(input)).unwrap();
+            assert_eq!(Err(expected.to_owned()), vm.run(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        // Calls in tail position reuse the frame of the function making them
+        let tests = vec![
+            (
+                "let loop = fn(n) { if (n == 0) { 0 } else { loop(n - 1) } }; loop(1000000)",
+                "0",
+            ),
+            // A return from a loop leaves the iterator behind
+            (
+                "let sum = fn(n, total) { for (i in 0..1) { if (n == 0) { return total; } return sum(n - 1, total + n); } }; sum(100000, 0)",
+                "5000050000",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut vm = Vm::new(compiled(input)).unwrap();
+            vm.run().unwrap();
+            assert_eq!(expected, vm.last_popped().to_string(), "{}", input);
+        }
+
+        // Other calls are limited
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100000)";
+        let mut vm = Vm::new(compiled(input)).unwrap();
+        assert_eq!(Err("Stack overflow".to_owned()), vm.run());
+    }
 }
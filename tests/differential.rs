@@ -0,0 +1,218 @@
+// Runs every program in tests/programs through both the evaluator and the
+// VM. Each `name.mk` program has a `name.out` file holding the expected
+// result, or `error` if the program should fail in both engines. Generated
+// arithmetic near the limits of an integer checks the engines agree on
+// promotion to big ints and division by zero, and generated matches, calls
+// and destructuring check they agree on which values fit which patterns.
+use monkey_lang::{
+    compiler::Compiler,
+    evaluator::{eval, Environment, Object},
+    lexer::lexer,
+    parser::parse,
+    vm::Vm,
+};
+use proptest::prelude::*;
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
+
+// The evaluator still reports errors by panicking, catch the panic and
+// treat it as a failed run
+fn catch<F: FnOnce() -> Object>(f: F) -> Result<Object, ()> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).map_err(|_| ());
+    panic::set_hook(hook);
+    result
+}
+
+fn run_evaluator(input: &str) -> Result<Object, ()> {
+    let mut tokens = lexer(input);
+    let ast = parse(&mut tokens).map_err(|_| ())?;
+    catch(|| eval(ast, &mut Environment::new()))
+}
+
+fn run_vm(input: &str) -> Result<Object, ()> {
+    let byte_code = Compiler::from_source(input).map_err(|_| ())?;
+    let mut vm = Vm::new(byte_code).map_err(|_| ())?;
+    vm.run().map_err(|_| ())?;
+    Ok(vm.last_popped().clone())
+}
+
+fn outcome(result: &Result<Object, ()>) -> String {
+    match result {
+        Ok(object) => object.to_string(),
+        Err(()) => "error".to_owned(),
+    }
+}
+
+#[test]
+fn engines_agree() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mk"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No programs found in {:?}", dir);
+
+    let mut failures = vec![];
+    for path in paths {
+        let input = fs::read_to_string(&path).unwrap();
+        let expected = fs::read_to_string(path.with_extension("out"))
+            .unwrap_or_else(|_| panic!("Missing expected output for {:?}", path));
+        let expected = expected.trim();
+
+        let evaluated = run_evaluator(&input);
+        let vm = run_vm(&input);
+        let name = path.file_name().unwrap().to_string_lossy();
+
+        if evaluated != vm {
+            failures.push(format!(
+                "{}: evaluator returned {:?} but the vm returned {:?}",
+                name, evaluated, vm
+            ));
+        } else if outcome(&evaluated) != expected {
+            failures.push(format!(
+                "{}: expected {} but both engines returned {}",
+                name,
+                expected,
+                outcome(&evaluated)
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}\n", failures.join("\n"));
+}
+
+// Operands around the points where ints become big ints or divide by zero
+#[rustfmt::skip]
+const OPERANDS: &[&str] = &[
+    "0", "1", "2", "3", "7", "-1", "-2", "62", "63", "64",
+    "3037000499", "3037000500", "4611686018427387904",
+    "9223372036854775807", "(-9223372036854775807 - 1)",
+    "9223372036854775808", "18446744073709551616",
+];
+
+const OPERATORS: &[&str] = &["+", "-", "*", "/", "%", "**"];
+
+fn arithmetic() -> impl Strategy<Value = String> {
+    let leaf = prop::sample::select(OPERANDS).prop_map(str::to_owned);
+    leaf.prop_recursive(4, 16, 2, |inner| {
+        prop_oneof![
+            inner.clone().prop_map(|value| format!("-({})", value)),
+            (inner.clone(), prop::sample::select(OPERATORS), inner)
+                .prop_map(|(left, op, right)| format!("({} {} {})", left, op, right)),
+        ]
+    })
+}
+
+// Values and patterns of every kind, so each pair of a value and a pattern
+// checks the engines agree on whether they match
+#[rustfmt::skip]
+const MATCH_VALUES: &[&str] = &[
+    "0", "1", "-1", "1.0", "9223372036854775808", "'a'", "''", "true", "null",
+    "[]", "[1]", "[1, 'a']", "[[1], 2]", "{}", "{'a': 1}", "{'a': [1], 1: 2}", "0..1",
+];
+
+#[rustfmt::skip]
+const MATCH_PATTERNS: &[&str] = &[
+    "_", "x", "0", "1", "-1", "1.0", "9223372036854775808", "'a'", "true", "null",
+    "[]", "[x]", "[1, x]", "[[x], _]", "{}", "{'a': x}", "{'a': [x]}", "{1: 2, 'a': _}",
+];
+
+fn match_program() -> impl Strategy<Value = String> {
+    (
+        prop::sample::select(MATCH_VALUES),
+        prop::collection::vec(prop::sample::select(MATCH_PATTERNS), 1..4),
+    )
+        .prop_map(|(value, patterns)| {
+            let arms: Vec<String> = patterns
+                .iter()
+                .enumerate()
+                .map(|(i, pattern)| format!("{} => {}", pattern, i))
+                .collect();
+            format!("match ({}) {{ {} }}", value, arms.join(", "))
+        })
+}
+
+// Calls with too few or too many arguments for parameters with defaults,
+// patterns and a rest parameter, so the engines agree on which arguments
+// are bound to which names. Calls don't recurse so they always finish.
+fn call_program() -> impl Strategy<Value = String> {
+    let parameter = (
+        prop::sample::select(MATCH_PATTERNS).prop_filter("Not a literal", |pattern| {
+            *pattern == "_" || pattern.starts_with(['x', '[', '{'])
+        }),
+        prop::option::of(prop::sample::select(MATCH_VALUES)),
+    );
+    (
+        prop::collection::vec(parameter, 0..3),
+        any::<bool>(),
+        prop::collection::vec(prop::sample::select(MATCH_VALUES), 0..4),
+    )
+        .prop_map(|(mut parameters, rest, args)| {
+            // Parameters with defaults have to come last
+            parameters.sort_by_key(|(_, default)| default.is_some());
+            let mut list: Vec<String> = parameters
+                .iter()
+                .map(|(pattern, default)| match default {
+                    Some(default) => format!("{} = {}", pattern, default),
+                    None => pattern.to_string(),
+                })
+                .collect();
+            let mut body = vec![];
+            if parameters.iter().any(|(pattern, _)| pattern.contains('x')) {
+                body.push("x");
+            }
+            if rest {
+                list.push("...r".to_owned());
+                body.push("r");
+            }
+            format!(
+                "let f = fn({}) {{ [{}] }}; f({})",
+                list.join(", "),
+                body.join(", "),
+                args.join(", ")
+            )
+        })
+}
+
+// Only array and hash patterns can follow `let`
+fn destructure_program() -> impl Strategy<Value = String> {
+    let patterns: Vec<&str> = MATCH_PATTERNS
+        .iter()
+        .copied()
+        .filter(|pattern| pattern.starts_with(['[', '{']))
+        .collect();
+    (
+        prop::sample::select(patterns),
+        prop::sample::select(MATCH_VALUES),
+    )
+        .prop_map(|(pattern, value)| format!("let {} = {}; 0", pattern, value))
+}
+
+proptest! {
+    #[test]
+    fn arithmetic_agrees(input in arithmetic()) {
+        prop_assert_eq!(run_evaluator(&input), run_vm(&input), "{}", input);
+    }
+
+    #[test]
+    fn matches_agree(input in match_program()) {
+        prop_assert_eq!(run_evaluator(&input), run_vm(&input), "{}", input);
+    }
+
+    #[test]
+    fn calls_agree(input in call_program()) {
+        prop_assert_eq!(run_evaluator(&input), run_vm(&input), "{}", input);
+    }
+
+    #[test]
+    fn destructures_agree(input in destructure_program()) {
+        prop_assert_eq!(run_evaluator(&input), run_vm(&input), "{}", input);
+    }
+}
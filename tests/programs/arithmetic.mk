@@ -0,0 +1,2 @@
+1 + 2 * 3 - 4 / 2;
+(5 + 10 * 2 + 15 / 3) * 2 + -10
@@ -0,0 +1,5 @@
+let max = 9223372036854775807;
+let min = -max - 1;
+let x = max - 1;
+x += 1;
+[x == max, min + max, min / 1, -7 / 2, 7 / -2, min % -1, max * -1, min / 2 * 2 == min]
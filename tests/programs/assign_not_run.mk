@@ -0,0 +1,2 @@
+if (false) { let z = 1; };
+z = 5
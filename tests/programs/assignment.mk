@@ -0,0 +1,9 @@
+let total = 0;
+let counts = {'a': 0, 'b': 0};
+let seen = [false, false, false];
+for (i in 0..10) {
+    total += i;
+    seen[i / 4] = true;
+    if (i < 5) { counts['a'] += 1; } else { counts['b'] *= 2; };
+};
+[total, counts['a'], counts['b'], seen]
@@ -0,0 +1,2 @@
+!true == false;
+!!(3 > 2)
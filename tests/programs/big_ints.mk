@@ -0,0 +1,12 @@
+// Ints that overflow become big ints, and shrink back when they fit again
+let max = 9223372036854775807;
+let min = -max - 1;
+let n = max;
+n += 1;
+let id = 123456789012345678901234567890;
+let ids = {id: 'first'};
+[
+    n, min / -1, 4611686018427387904 * 2, -min, min - 2, 10 ** 19,
+    n - 1 == max, id % 1000000007, ids[id + 1 - 1],
+    2 ** 128 / 2 ** 64, -(2 ** 64) < min, 2 ** 70 * 0.25
+]
@@ -0,0 +1,3 @@
+true == true;
+(1 < 2) == (2 > 1);
+(1 > 2) != true
@@ -0,0 +1,4 @@
+for (x in [1, 2]) {
+    let y = 1 + if (true) { break; } else { 2 };
+};
+5
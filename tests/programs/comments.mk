@@ -0,0 +1,3 @@
+// Comments are skipped by both engines
+let x = 6; /* a block /* with a nested block */ */
+x * /* inline */ 7 // 42
@@ -0,0 +1,10 @@
+const limit = 5;
+const names = ['a', 'b'];
+let copy = names;
+copy[0] = 'z';
+let total = 0;
+for (i in 0..limit) {
+    const step = i * 2;
+    total += step;
+};
+[total, names, copy]
@@ -0,0 +1,3 @@
+const settings = {'depth': 1};
+settings['depth'] = 2;
+settings
@@ -0,0 +1,3 @@
+const x = 1;
+if (false) { x = 2; };
+x
@@ -0,0 +1,3 @@
+let c = false;
+if (c) { const x = 1; } else { let x = 2; };
+x = 3
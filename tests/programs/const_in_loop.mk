@@ -0,0 +1,7 @@
+let x = 1;
+let i = 0;
+while (i < 2) {
+    i += 1;
+    x = 2;
+    const x = 3;
+}
@@ -0,0 +1,3 @@
+const x = 1;
+match (5) { x => x };
+x
@@ -0,0 +1,4 @@
+const x = 1;
+match (2) { [x] => 0, _ => 0 };
+x = 5;
+x
@@ -0,0 +1,3 @@
+const x = 1;
+let x = 2;
+x = 3
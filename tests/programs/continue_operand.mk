@@ -0,0 +1,6 @@
+let n = 0;
+while (n < 3000) {
+    n += 1;
+    let a = [1, if (true) { continue; }];
+};
+n
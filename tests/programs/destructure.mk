@@ -0,0 +1,10 @@
+let pairs = [[1, 'one'], [2, 'two']];
+let names = {};
+for (pair in pairs) {
+    let [number, name] = pair;
+    names[name] = number;
+};
+let {'one': one, 'two': two} = names;
+let [first, [_, second]] = [one, pairs[1]];
+let whole = if (true) { let [a, b] = [first, second]; };
+[one + two, second, whole]
@@ -0,0 +1,3 @@
+let point = {'x': 1};
+let {'x': x, 'y': y} = point;
+x
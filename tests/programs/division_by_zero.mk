@@ -0,0 +1,3 @@
+let total = 10;
+let parts = 0;
+total / parts
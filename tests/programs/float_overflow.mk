@@ -0,0 +1,2 @@
+let big = 1e300;
+big * big
@@ -0,0 +1,6 @@
+let total = 0;
+for (i in 1..=4) {
+    total += 1 / (i * 1.0);
+};
+let mean = total / 4;
+[total, mean > 0.5 && mean < 0.6, 7 / 2, 7 / 2.0, -7.5 % 2, 2.0 ** -2, 1e3, 0.1 + 0.2, 3 == 3.0]
@@ -0,0 +1,3 @@
+let f = fn(n) { if (n == 0) { 'done' } else { f(n - 1) } };
+let g = f;
+g(3)
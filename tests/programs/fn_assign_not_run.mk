@@ -0,0 +1,5 @@
+let f = fn() {
+    if (false) { let z = 1; };
+    z = 5
+};
+f()
@@ -0,0 +1,4 @@
+while (true) {
+    let f = fn() { break; };
+    f();
+}
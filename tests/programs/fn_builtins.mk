@@ -0,0 +1,2 @@
+let f = fn(s) { [len(s), upperCase(s), sqrt(len(s) * 4)] };
+f('abcd')
@@ -0,0 +1,4 @@
+let twice = fn(x) { x * 2 };
+let h = {'f': twice};
+let curry = fn(a) { fn(b) { b * 10 } };
+[h['f'](4), curry(1)(2), fn(x) { x + 1 }(3), [twice][0](5)]
@@ -0,0 +1,5 @@
+let f = fn() {
+    const x = 1;
+    x = 2;
+};
+f()
@@ -0,0 +1,5 @@
+let f = fn(c) {
+    if (c) { const x = 1; } else { let x = 2; };
+    x = 3
+};
+f(false)
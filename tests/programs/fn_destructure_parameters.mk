@@ -0,0 +1,2 @@
+let f = fn([a, b], {'k': c} = {'k': 3}) { a + b + c };
+[f([1, 2]), f([1, 2], {'k': 10})]
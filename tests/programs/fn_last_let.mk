@@ -0,0 +1,2 @@
+let f = fn() { let x = 5; };
+f()
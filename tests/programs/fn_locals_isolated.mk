@@ -0,0 +1,5 @@
+let f = fn(n) {
+    let x = n * 2;
+    if (n > 0) { x + f(n - 1) } else { x }
+};
+f(3)
@@ -0,0 +1,16 @@
+let sum = fn(items) {
+    let total = 0;
+    for (item in items) {
+        if (item > 3) {
+            break;
+        }
+        total += item;
+    }
+    let i = 0;
+    while (i < 3) {
+        i += 1;
+        total *= 2;
+    }
+    total
+};
+sum([1, 2, 3, 4, 5])
@@ -0,0 +1,5 @@
+let outer = fn(x) {
+    let inner = fn(y) { y * 2 };
+    inner(x) + 1
+};
+outer(5)
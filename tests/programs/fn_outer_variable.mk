@@ -0,0 +1,3 @@
+let y = 1;
+let f = fn() { y };
+f()
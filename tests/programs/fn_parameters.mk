@@ -0,0 +1,2 @@
+let f = fn(x, y = x * 10, ...rest) { [x, y, rest] };
+[f(1), f(1, 2), f(1, 2, 3, 4)]
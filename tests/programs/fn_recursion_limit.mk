@@ -0,0 +1,2 @@
+let depth = fn(n) { if (n == 0) { 0 } else { 1 + depth(n - 1) } };
+depth(1023)
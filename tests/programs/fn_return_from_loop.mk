@@ -0,0 +1,9 @@
+let find = fn(items, target) {
+    for (i, item in items) {
+        if (item == target) {
+            return i;
+        }
+    }
+    -1
+};
+[find([5, 6, 7], 6), find([5], 9)]
@@ -0,0 +1,2 @@
+let len = fn(x) { 0 };
+len('abc')
@@ -0,0 +1,2 @@
+let f = fn(f) { f * 2 };
+f(4)
@@ -0,0 +1,2 @@
+let loop = fn(n) { if (n == 0) { 0 } else { loop(n - 1) } };
+loop(100000)
@@ -0,0 +1,7 @@
+let count = fn(n, total = 0) {
+    match (n) {
+        0 => total,
+        _ => count(n - 1, total + 2),
+    }
+};
+count(50000)
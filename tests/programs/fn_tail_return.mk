@@ -0,0 +1,7 @@
+let sum = fn(n, total) {
+    if (n == 0) {
+        return total;
+    }
+    return sum(n - 1, total + n);
+};
+sum(50000, 0)
@@ -0,0 +1,2 @@
+let f = fn(x, y) { x };
+f(1)
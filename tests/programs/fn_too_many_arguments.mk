@@ -0,0 +1,2 @@
+let f = fn(x, y = 2) { x };
+f(1, 2, 3)
@@ -0,0 +1 @@
+nope(1)
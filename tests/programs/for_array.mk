@@ -0,0 +1,6 @@
+let grid = [[1, 2], [3, 4], [5, 6]];
+let found = -1;
+for (i, row in grid) {
+    if (row[1] == 4) { let found = i; break; } else { 0 };
+};
+grid[found][0] * 10 + found
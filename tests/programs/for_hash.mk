@@ -0,0 +1,7 @@
+let prices = {'apple': 3, 'pear': 5, 'plum': 2};
+let total = 0;
+for (name, price in prices) {
+    if (price > 4) { continue; } else { 0 };
+    let total = total + price;
+};
+total
@@ -0,0 +1,8 @@
+let t = 0;
+let r = if (true) {
+    for (x in 1..4) {
+        if (x == 3) { break; }
+        t += x;
+    }
+};
+[r, t]
@@ -0,0 +1,4 @@
+let x = 7;
+x;
+for (a in []) {
+}
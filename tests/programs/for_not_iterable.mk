@@ -0,0 +1,2 @@
+for (x in true) { x };
+1
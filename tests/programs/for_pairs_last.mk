@@ -0,0 +1,4 @@
+let t = 0;
+for (k, v in {1: 2}) {
+    t += k + v;
+}
@@ -0,0 +1,6 @@
+// Sum of squares with an inclusive range
+let sum = 0;
+for (i in 1..=10) {
+    let sum = sum + i * i;
+};
+sum
@@ -0,0 +1,8 @@
+// Strings iterate over characters, not bytes
+let n = 0;
+let last = '';
+for (i, c in 'héllo') {
+    let n = i;
+    let last = c;
+};
+[n, last]
@@ -0,0 +1,4 @@
+let a = 5;
+let b = a * 2;
+let c = a + b;
+c * c - b
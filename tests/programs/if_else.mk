@@ -0,0 +1,3 @@
+let grade = 72;
+let pass = grade > 50;
+if (pass == true) { grade - 50 } else { 50 - grade }
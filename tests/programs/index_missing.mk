@@ -0,0 +1,2 @@
+let h = {1: 'one'};
+[h[2], [1, 2][5]]
@@ -0,0 +1,10 @@
+let calls = 0;
+let a = [3, 0, 8];
+let found = false;
+let i = 0;
+while (i < 3 && !found) {
+    found = a[i] == 0 || a[i] > 5;
+    i += 1;
+};
+let guarded = false && (calls += 1) > 0;
+[found, i, guarded, calls, true || false && false]
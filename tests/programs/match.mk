@@ -0,0 +1,20 @@
+let describe = {};
+let i = 0;
+for (value in [0, 1.0, -2, 'two', null, true, [1, 2], [3, [4, 5]], {'name': 'x'}, {}, 99]) {
+    let text = match (value) {
+        0 => 'zero',
+        1 => 'one',
+        'two' => 'string two',
+        null => 'nothing',
+        true => 'yes',
+        [a, b] if a == 1 => a + b,
+        [_, [c, d]] => c * d,
+        {'name': name} => name,
+        {} => 'empty hash',
+        n if n < 0 => 'negative',
+        _ => 'other',
+    };
+    describe[i] = text;
+    i += 1;
+};
+describe
@@ -0,0 +1,3 @@
+let x = 1;
+match (5) { x if false => 0, _ => 1 };
+x
@@ -0,0 +1,3 @@
+let x = 5;
+let result = match (x) { 1 => 'one', n if n > 9 => 'big' };
+[result, match ([x]) { [y] => { let z = y * 2; } }]
@@ -0,0 +1,3 @@
+let a = 1;
+match ([7, 8]) { [a, 9] => 0, _ => 1 };
+a
@@ -0,0 +1 @@
+-(-5) - -(2 * -3)
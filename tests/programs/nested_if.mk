@@ -0,0 +1,6 @@
+let x = 10;
+if (x > 5) {
+    if (x < 8) { 1 } else { 2 }
+} else {
+    3
+}
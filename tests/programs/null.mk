@@ -0,0 +1,4 @@
+let missing = {'a': 1}['b'];
+let found = if (missing == null) { 'default' };
+let skipped = if (false) { 'never' };
+[found, skipped, !missing, missing || true, if (null) { 1 } else { 2 }, [1][5] == null]
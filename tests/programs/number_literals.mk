@@ -0,0 +1,3 @@
+let mask = 0xFF_FF;
+let flags = 0b1010 + 0o17;
+[mask, flags, 1_000_000 * 2, 0xffff_ffff_ffff_ffff_ff, 1_0.5e1_0]
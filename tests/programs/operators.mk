@@ -0,0 +1,10 @@
+let sum = 0;
+let count = 0;
+for (n in 2..=30) {
+    let prime = true;
+    for (d in 2..n) {
+        if (d ** 2 <= n && n % d == 0) { prime = false; } else { 0 };
+    };
+    if (prime) { sum += n; count += 1; } else { 0 };
+};
+[sum, count, -7 % 3, 2 ** 3 ** 2, -2 ** 2, (-2) ** 2, 3 >= 3]
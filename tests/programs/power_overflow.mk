@@ -0,0 +1,2 @@
+let base = 10;
+base ** 1000000
@@ -0,0 +1 @@
+2 - 3 + 29 == 4 * 7
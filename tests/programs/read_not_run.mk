@@ -0,0 +1,3 @@
+if (false) { let z = 1; };
+let w = 2;
+z
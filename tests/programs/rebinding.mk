@@ -0,0 +1,4 @@
+let a = 1;
+let a = a + 1;
+let a = a * 10;
+a
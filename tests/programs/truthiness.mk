@@ -0,0 +1,10 @@
+let queue = [3, 2, 1];
+let i = 0;
+let seen = 0;
+while (i < 3 && queue[i]) {
+    seen += queue[i];
+    i += 1;
+}
+let name = '';
+let label = if (name) { name } else { 'anonymous' };
+[seen, label, !0, !0.0, ![], !{}, !'x', !null, 0 || 0.5, [] && true, if (1..=1) { 'range' }]
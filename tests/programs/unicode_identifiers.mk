@@ -0,0 +1,4 @@
+let café = 'crème brûlée';
+let user_2fa = 2;
+let x1 = user_2fa * 3;
+[café, x1, '日本語']
@@ -0,0 +1,6 @@
+let i = 0;
+while (true) {
+    if (i > 100) { break; } else { 0 };
+    let i = i * 2 + 1;
+};
+i
@@ -0,0 +1,6 @@
+let i = 0;
+if (true) {
+    while (i < 3) {
+        i += 1;
+    }
+}
@@ -0,0 +1,9 @@
+// Sum the numbers up to 10, skipping 5
+let i = 0;
+let sum = 0;
+while (i < 10) {
+    let i = i + 1;
+    if (i == 5) { continue; } else { 0 };
+    let sum = sum + i;
+};
+sum
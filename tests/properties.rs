@@ -0,0 +1,359 @@
+// Property tests checking the lexer, parser, compiler and VM never panic,
+// and that printing a well formed AST and parsing it gives the AST back.
+use monkey_lang::{
+    compiler::{ByteCode, Compiler},
+    formatter::format,
+    lexer::lexer,
+    parser::{
+        parse, Expression, MatchArm, Operator, Parameter, Parenthesised, Pattern, Prefix, Statement,
+    },
+    vm::Vm,
+};
+use proptest::prelude::*;
+
+// Source fragments that lex to every kind of Token
+#[rustfmt::skip]
+const FRAGMENTS: &[&str] = &[
+    "let", "const", "fn", "if", "else", "return", "true", "false", "x", "y", "add",
+    "0", "7", "42", "'str'", "99999999999999999999",
+    "0.5", "1e-9", "2.5e3", "1e999", "0x1F", "0b", "1_0", "9a", "x2", "é", "null",
+    "=", "+", "-", "*", "/", "<", ">", "==", "!=", "!", "&&", "||",
+    "<=", ">=", "%", "**", ",", ";",
+    "+=", "-=", "*=", "/=",
+    "(", ")", "{", "}", "[", "]", ":", "..", "..=", "'", "#", "//", "/*", "*/",
+    "\n", "for", "in", "break", "continue", "match", "=>", "_",
+    "...",
+];
+
+const NAMES: &[&str] = &["a", "b", "c", "x", "y", "add", "x1", "café"];
+
+fn name() -> impl Strategy<Value = String> {
+    prop::sample::select(NAMES).prop_map(|name| name.to_owned())
+}
+
+// Operators that have an assignment form like `+=`
+fn compound_operator() -> impl Strategy<Value = Operator> {
+    prop_oneof![
+        Just(Operator::PLUS),
+        Just(Operator::MINUS),
+        Just(Operator::MULTIPLY),
+        Just(Operator::DIVIDE),
+    ]
+}
+
+fn operator() -> impl Strategy<Value = Operator> {
+    prop_oneof![
+        Just(Operator::PLUS),
+        Just(Operator::MINUS),
+        Just(Operator::MULTIPLY),
+        Just(Operator::DIVIDE),
+        Just(Operator::GREATER),
+        Just(Operator::LESS),
+        Just(Operator::EQUAL),
+        Just(Operator::NEQUAL),
+        Just(Operator::RANGE),
+        Just(Operator::RANGEINCLUSIVE),
+        Just(Operator::AND),
+        Just(Operator::OR),
+        Just(Operator::GREATEREQUAL),
+        Just(Operator::LESSEQUAL),
+        Just(Operator::MODULO),
+        Just(Operator::POWER),
+    ]
+}
+
+// Literals a pattern can hold, negative numbers are a prefix minus
+fn literal() -> impl Strategy<Value = Expression> {
+    prop_oneof![
+        (0..1000isize).prop_map(Expression::Int),
+        (1..1000isize).prop_map(|val| Expression::Prefix {
+            prefix: Prefix::MINUS,
+            value: Box::new(Expression::Int(val)),
+        }),
+        prop::num::f64::POSITIVE.prop_map(Expression::Float),
+        any::<bool>().prop_map(Expression::Boolean),
+        Just(Expression::Null),
+        "[a-z ]{0,8}".prop_map(Expression::String),
+    ]
+}
+
+fn pattern() -> impl Strategy<Value = Pattern> {
+    let leaf = prop_oneof![
+        Just(Pattern::Wildcard),
+        name().prop_map(Pattern::Binding),
+        literal().prop_map(Pattern::Literal),
+    ];
+    leaf.prop_recursive(3, 12, 3, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..3).prop_map(Pattern::Array),
+            prop::collection::vec((literal(), inner), 0..3).prop_map(Pattern::Hash),
+        ]
+    })
+}
+
+fn expression() -> BoxedStrategy<Expression> {
+    let leaf = prop_oneof![
+        (0..1000isize).prop_map(Expression::Int),
+        // Smaller literals are lexed as ints
+        (isize::MAX as u128 + 1..=u128::MAX).prop_map(|val| Expression::BigInt(val.into())),
+        // Negative numbers are a prefix minus on a literal
+        (prop::num::f64::POSITIVE
+            | prop::num::f64::ZERO
+            | prop::num::f64::NORMAL
+            | prop::num::f64::SUBNORMAL)
+            .prop_map(Expression::Float),
+        any::<bool>().prop_map(Expression::Boolean),
+        Just(Expression::Null),
+        name().prop_map(Expression::Ident),
+        "[a-z ]{0,8}".prop_map(Expression::String),
+    ];
+
+    leaf.prop_recursive(4, 32, 4, |inner| {
+        let block = prop::collection::vec(statement(inner.clone()), 0..3);
+        prop_oneof![
+            (inner.clone(), operator(), inner.clone()).prop_map(|(left, op, right)| {
+                Expression::Infix {
+                    left: Box::new(left),
+                    op,
+                    right: Box::new(right),
+                }
+            }),
+            (
+                prop_oneof![Just(Prefix::BANG), Just(Prefix::MINUS)],
+                inner.clone()
+            )
+                .prop_map(|(prefix, value)| Expression::Prefix {
+                    prefix,
+                    value: Box::new(value),
+                }),
+            (inner.clone(), block.clone(), block.clone()).prop_map(
+                |(condition, consequence, alternative)| Expression::If {
+                    condition: Box::new(condition),
+                    consequence,
+                    alternative,
+                }
+            ),
+            (
+                prop::collection::vec(pattern(), 0..3),
+                prop::collection::vec((pattern(), inner.clone()), 0..2),
+                prop::option::of(name()),
+                block.clone()
+            )
+                .prop_map(|(required, defaults, rest, body)| {
+                    let parameters =
+                        required
+                            .into_iter()
+                            .map(|pattern| Parameter::Single {
+                                pattern,
+                                default: None,
+                            })
+                            .chain(defaults.into_iter().map(|(pattern, default)| {
+                                Parameter::Single {
+                                    pattern,
+                                    default: Some(default),
+                                }
+                            }))
+                            .chain(rest.map(Parameter::Rest))
+                            .collect();
+                    Expression::FnLiteral { parameters, body }
+                }),
+            (name(), prop::collection::vec(inner.clone(), 0..3)).prop_map(|(name, args)| {
+                Expression::FnCall {
+                    function: Box::new(Expression::Ident(name)),
+                    args,
+                }
+            }),
+            (
+                inner.clone(),
+                prop::collection::vec((pattern(), prop::option::of(inner.clone()), block), 0..3)
+            )
+                .prop_map(|(value, arms)| Expression::Match {
+                    value: Box::new(value),
+                    arms: arms
+                        .into_iter()
+                        .map(|(pattern, guard, body)| MatchArm {
+                            pattern,
+                            guard,
+                            body,
+                        })
+                        .collect(),
+                }),
+            prop::collection::vec(inner.clone(), 0..3).prop_map(Expression::Array),
+            prop::collection::vec((inner.clone(), inner.clone()), 0..3).prop_map(Expression::Hash),
+            (inner.clone(), inner.clone()).prop_map(|(left, index)| Expression::Index {
+                left: Box::new(left),
+                index: Box::new(index),
+            }),
+            (
+                target(inner.clone()),
+                prop::option::of(compound_operator()),
+                inner
+            )
+                .prop_map(|(target, op, value)| Expression::Assign {
+                    target: Box::new(target),
+                    op,
+                    value: Box::new(value),
+                }),
+        ]
+    })
+    .boxed()
+}
+
+// A variable or an index into one
+fn target(index: BoxedStrategy<Expression>) -> impl Strategy<Value = Expression> {
+    (name(), prop::collection::vec(index, 0..3)).prop_map(|(name, indexes)| {
+        indexes
+            .into_iter()
+            .fold(Expression::Ident(name), |left, index| Expression::Index {
+                left: Box::new(left),
+                index: Box::new(index),
+            })
+    })
+}
+
+fn statement(expression: BoxedStrategy<Expression>) -> BoxedStrategy<Statement> {
+    prop_oneof![
+        (name(), expression.clone()).prop_map(|(name, value)| Statement::Let { name, value }),
+        (name(), expression.clone()).prop_map(|(name, value)| Statement::Const { name, value }),
+        // A pattern starting with a name would read back as a plain `let`
+        (
+            prop_oneof![
+                prop::collection::vec(pattern(), 0..3).prop_map(Pattern::Array),
+                prop::collection::vec((literal(), pattern()), 0..3).prop_map(Pattern::Hash),
+            ],
+            expression.clone()
+        )
+            .prop_map(|(pattern, value)| Statement::Destructure { pattern, value }),
+        expression
+            .clone()
+            .prop_map(|value| Statement::Return { value }),
+        expression.prop_map(Statement::ExpressionStatement),
+    ]
+    .boxed()
+}
+
+fn program() -> impl Strategy<Value = Vec<Statement>> {
+    prop::collection::vec(statement(expression()), 0..6)
+}
+
+// Loops aren't part of program() as a generated loop may never finish
+fn loop_statement() -> impl Strategy<Value = Statement> {
+    let body = prop_oneof![
+        statement(expression()),
+        Just(Statement::Break),
+        Just(Statement::Continue),
+    ];
+    let body = prop::collection::vec(body, 0..4);
+    prop_oneof![
+        (expression(), body.clone())
+            .prop_map(|(condition, body)| Statement::While { condition, body }),
+        (prop::collection::vec(name(), 1..3), expression(), body).prop_map(
+            |(variables, iterable, body)| Statement::For {
+                variables,
+                iterable,
+                body,
+            }
+        ),
+    ]
+}
+
+fn canonical(statements: &[Statement]) -> String {
+    let statements: Vec<String> = statements.iter().map(|s| s.to_string()).collect();
+    statements.join(" ")
+}
+
+fn parenthesised(statements: &[Statement]) -> String {
+    let statements: Vec<String> = statements
+        .iter()
+        .map(|s| Parenthesised(s).to_string())
+        .collect();
+    statements.join(" ")
+}
+
+// Run source through every stage, any stage is allowed to fail but none
+// of them may panic
+fn run(input: &str) {
+    let mut tokens = lexer(input);
+    let _ = parse(&mut tokens);
+    let _ = format(input);
+
+    if let Ok(byte_code) = Compiler::from_source(input) {
+        // Whatever the compiler produces can be saved and loaded again
+        let bytes = byte_code.to_bytes().expect("Compiled byte code serializes");
+        assert_eq!(Ok(&byte_code), ByteCode::from_bytes(&bytes).as_ref());
+
+        let mut vm = Vm::new(byte_code).expect("Compiled byte code is valid");
+        // Like a loop, a function can call itself forever
+        if !input.contains("fn") {
+            let _ = vm.run();
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn lexer_never_panics(input in prop::collection::vec(any::<u8>(), 0..64)) {
+        lexer(&String::from_utf8_lossy(&input));
+    }
+
+    // Loading is checked, but running could loop forever so isn't tried
+    #[test]
+    fn loading_byte_code_never_panics(instructions in prop::collection::vec(any::<u8>(), 0..64)) {
+        let mut bytes = b"MONK\x01".to_vec();
+        bytes.extend(&(instructions.len() as u32).to_be_bytes());
+        bytes.extend(&instructions);
+        bytes.extend(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 7]);
+        let _ = ByteCode::from_bytes(&bytes);
+        let _ = Vm::new(ByteCode { instructions, constants: vec![] });
+    }
+
+    #[test]
+    fn strings_never_panic(input in "\\PC{0,64}") {
+        run(&input);
+    }
+
+    #[test]
+    fn token_streams_never_panic(
+        fragments in prop::collection::vec(prop::sample::select(FRAGMENTS), 0..32)
+    ) {
+        run(&fragments.join(" "));
+    }
+
+    #[test]
+    fn well_formed_programs_never_panic(statements in program()) {
+        run(&canonical(&statements));
+    }
+
+    #[test]
+    fn canonical_round_trip(statements in program()) {
+        let source = canonical(&statements);
+        let mut tokens = lexer(&source);
+        prop_assert_eq!(Ok(statements), parse(&mut tokens), "{}", source);
+    }
+
+    #[test]
+    fn parenthesised_round_trip(statements in program()) {
+        let source = parenthesised(&statements);
+        let mut tokens = lexer(&source);
+        prop_assert_eq!(Ok(statements), parse(&mut tokens), "{}", source);
+    }
+
+    #[test]
+    fn loop_round_trip(statements in prop::collection::vec(loop_statement(), 0..3)) {
+        let source = canonical(&statements);
+        let mut tokens = lexer(&source);
+        prop_assert_eq!(Ok(statements.clone()), parse(&mut tokens), "{}", source);
+
+        let formatted = format(&source).unwrap();
+        let mut tokens = lexer(&formatted);
+        prop_assert_eq!(Ok(statements), parse(&mut tokens), "{}", formatted);
+    }
+
+    #[test]
+    fn format_round_trip(statements in program()) {
+        let formatted = format(&canonical(&statements)).unwrap();
+        let mut tokens = lexer(&formatted);
+        prop_assert_eq!(Ok(statements), parse(&mut tokens), "{}", formatted);
+        prop_assert_eq!(Ok(formatted.clone()), format(&formatted));
+    }
+}